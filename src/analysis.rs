@@ -0,0 +1,93 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A reusable context for analyses over many expressions.
+//!
+//! The free functions [`get_dependencies`](crate::get_dependencies),
+//! [`get_num_nodes`](crate::get_num_nodes), etc. start from empty caches on
+//! every call. [`AnalysisContext`] instead keeps its caches alive between
+//! calls, keyed by node identity, so that later passes over expressions
+//! sharing nodes with earlier ones reuse the results computed before.
+
+use crate::mapper_impls::deduplicator::Deduplicator;
+use crate::mapper_impls::dependency::DependenciesGetter;
+use crate::mapper_impls::fingerprint::{Fingerprint, Fingerprinter};
+use crate::mapper_impls::node_counter::SharedNodeCounter;
+use crate::mappers::combine::CombineMapper;
+use crate::primitives::Expression;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Memoizes analysis results across calls. Results are keyed by node
+/// identity, and the context holds on to every expression it has analysed.
+///
+/// # Example
+/// ```rust
+/// use std::rc::Rc;
+/// use symoxide::{parse, AnalysisContext};
+///
+/// let mut ctx = AnalysisContext::new();
+/// let expr = ctx.deduplicate(&parse("(x + y) * (x + y)"));
+/// assert_eq!(ctx.get_num_nodes(&expr), 4);
+/// assert_eq!(ctx.get_dependencies(&expr).len(), 2);
+/// // already deduplicated expressions are returned as is
+/// assert!(Rc::ptr_eq(&ctx.deduplicate(&expr), &expr));
+/// ```
+#[derive(Default)]
+pub struct AnalysisContext {
+    dependencies_getter: DependenciesGetter,
+    node_counter: SharedNodeCounter,
+    deduplicator: Deduplicator,
+    fingerprinter: Fingerprinter,
+}
+
+impl AnalysisContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the names of the variables *expr* depends on. See
+    /// [`get_dependencies`](crate::get_dependencies).
+    pub fn get_dependencies(&mut self, expr: &Rc<Expression>) -> HashSet<String> {
+        self.dependencies_getter
+            .visit(expr)
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the number of nodes in the DAG *expr*. See
+    /// [`get_num_nodes`](crate::get_num_nodes).
+    pub fn get_num_nodes(&mut self, expr: &Rc<Expression>) -> u32 {
+        self.node_counter.get_num_nodes(expr)
+    }
+
+    /// Returns *expr* with structurally equal sub-expressions mapped to the
+    /// same node, shared with every expression deduplicated before. See
+    /// [`deduplicate_nodes`](crate::deduplicate_nodes).
+    pub fn deduplicate(&mut self, expr: &Rc<Expression>) -> Rc<Expression> {
+        self.deduplicator.deduplicate(expr)
+    }
+
+    /// Returns the structural [`Fingerprint`] of *expr*.
+    pub fn fingerprint(&mut self, expr: &Rc<Expression>) -> Fingerprint {
+        self.fingerprinter.fingerprint(expr)
+    }
+}
@@ -0,0 +1,479 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A registry of the functions that can be invoked in an
+//! [`Expression::Call`].
+//!
+//! [`Expression::Call`] treats its callee as an arbitrary expression.
+//! Evaluation, compilation, differentiation, type inference and code
+//! generation consult a [`FunctionRegistry`] to learn about the semantics of a
+//! callee named by an [`Expression::Variable`]. [`FunctionRegistry::default`]
+//! is populated with the common math functions such as `sin`, `exp` and `sqrt`.
+
+use crate::builders::var;
+use crate::mappers::walk::WalkMapper;
+use crate::mappers::CachedMapper;
+use crate::operations as ops;
+use crate::operations::ConvertibleToExpr;
+use crate::primitives::{DType, Expression, LiteralT, SmallVecExprT};
+use crate::utils::ExpressionRawPointer;
+use lazy_static::lazy_static;
+use smallvec::{smallvec, SmallVec};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Numeric implementation of a function.
+pub type NumericFunction = fn(&[LiteralT]) -> LiteralT;
+
+/// Returns the values of the partial derivatives of a function w.r.t. each of
+/// its arguments.
+pub type NumericPartials = fn(&[LiteralT]) -> SmallVec<[f64; 4]>;
+
+/// Returns the expressions for the partial derivatives of a function w.r.t.
+/// each of its arguments.
+pub type SymbolicPartials = fn(&[Rc<Expression>]) -> SmallVecExprT;
+
+/// Returns the data-type of a function's result given the data-types of its
+/// arguments.
+pub type ResultDType = fn(&[DType]) -> DType;
+
+/// Languages targeted by the code generators.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PrintTarget {
+    Python,
+    NumPy,
+    /// Names starting with a `.` are printed as a method call on the first
+    /// argument, for ex. `.sin` prints `sin(x)` as `x.sin()`.
+    Rust,
+    LaTeX,
+}
+
+// {{{ FunctionInfo
+
+/// Metadata of a function known to a [`FunctionRegistry`].
+#[derive(Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub arity: usize,
+    /// *true* only if the function has no side-effects, i.e. calls with equal
+    /// arguments are interchangeable.
+    pub is_pure: bool,
+    pub eval: NumericFunction,
+    pub partials: Option<NumericPartials>,
+    pub symbolic_partials: Option<SymbolicPartials>,
+    pub result_dtype: ResultDType,
+    /// The name of the function in the generated code for each target.
+    /// Targets missing in the mapping print the function as `name`.
+    pub printed_names: HashMap<PrintTarget, String>,
+}
+
+impl FunctionInfo {
+    /// Returns a pure function without any derivative rules whose result is
+    /// always an `f64`.
+    pub fn new<T: ToString>(name: T, arity: usize, eval: NumericFunction) -> Self {
+        FunctionInfo { name: name.to_string(),
+                       arity,
+                       is_pure: true,
+                       eval,
+                       partials: None,
+                       symbolic_partials: None,
+                       result_dtype: |_| DType::F64,
+                       printed_names: HashMap::new() }
+    }
+
+    /// Returns the name of the function in code generated for `target`.
+    pub fn printed_name(&self, target: PrintTarget) -> &str {
+        match self.printed_names.get(&target) {
+            Some(x) => x,
+            None => &self.name,
+        }
+    }
+}
+
+// }}}
+
+// {{{ CallError
+
+/// An invalid call found by [`FunctionRegistry::check_calls`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// The callee is not an [`Expression::Variable`].
+    UnnamedCallee(String),
+    UnknownFunction(String),
+    WrongArity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::UnnamedCallee(call) => write!(f, "Cannot call '{}'.", call),
+            CallError::UnknownFunction(name) => write!(f, "Unknown function '{}'.", name),
+            CallError::WrongArity { name,
+                                    expected,
+                                    got, } => {
+                write!(f, "'{}' expects {} arguments, got {}.", name, expected, got)
+            }
+        }
+    }
+}
+
+// }}}
+
+// {{{ FunctionRegistry
+
+/// A mapping from function names to their [`FunctionInfo`].
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::functions::{FunctionInfo, FunctionRegistry};
+/// use symoxide::mapper_impls::evaluate::evaluate_with_registry;
+/// use symoxide::{parse, LiteralT};
+///
+/// let mut registry = FunctionRegistry::default();
+/// registry.register(FunctionInfo::new("square", 1, |args| {
+///                       LiteralT::F64(args[0].as_f64() * args[0].as_f64())
+///                   }));
+/// let expr = parse("square(x) + sin(0.)");
+/// assert!(registry.check_calls(&expr).is_ok());
+///
+/// let env = HashMap::from([("x".to_string(), LiteralT::F64(3.0))]);
+/// assert_eq!(evaluate_with_registry(&expr, &env, &registry), LiteralT::F64(9.0));
+/// ```
+#[derive(Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, FunctionInfo>,
+}
+
+impl FunctionRegistry {
+    /// Returns a registry without any functions. Also see
+    /// [`FunctionRegistry::default`].
+    pub fn new() -> Self {
+        FunctionRegistry { functions: HashMap::new() }
+    }
+
+    /// Adds `function` to the registry. Returns the previously registered
+    /// function with the same name, if any.
+    pub fn register(&mut self, function: FunctionInfo) -> Option<FunctionInfo> {
+        self.functions.insert(function.name.clone(), function)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FunctionInfo> {
+        self.functions.get(name)
+    }
+
+    /// Returns the function invoked by the call `call(*params)`.
+    pub fn get_callee(&self, call: &Expression, params: &SmallVecExprT)
+                      -> Result<&FunctionInfo, CallError> {
+        let name = match call {
+            Expression::Variable(name) => name,
+            _ => return Err(CallError::UnnamedCallee(call.to_string())),
+        };
+        let function = match self.functions.get(name) {
+            Some(x) => x,
+            None => return Err(CallError::UnknownFunction(name.clone())),
+        };
+        if function.arity != params.len() {
+            return Err(CallError::WrongArity { name: name.clone(),
+                                               expected: function.arity,
+                                               got: params.len() });
+        }
+        Ok(function)
+    }
+
+    /// Panicking variant of [`FunctionRegistry::get_callee`].
+    pub(crate) fn expect_callee(&self, call: &Expression, params: &SmallVecExprT) -> &FunctionInfo {
+        match self.get_callee(call, params) {
+            Ok(x) => x,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Returns the invalid calls in `expr` as per the functions in the
+    /// registry.
+    pub fn check_calls(&self, expr: &Rc<Expression>) -> Result<(), Vec<CallError>> {
+        let mut checker = CallsChecker { registry: self,
+                                         errors: vec![],
+                                         cache: HashMap::new() };
+        checker.visit(expr);
+        if checker.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(checker.errors)
+        }
+    }
+}
+
+impl Default for FunctionRegistry {
+    /// Returns a registry with the common math functions.
+    fn default() -> Self {
+        let mut registry = FunctionRegistry::new();
+        for function in get_math_functions() {
+            registry.register(function);
+        }
+        registry
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT_REGISTRY: FunctionRegistry = FunctionRegistry::default();
+}
+
+/// Returns a shared instance of [`FunctionRegistry::default`].
+pub fn default_registry() -> &'static FunctionRegistry {
+    &DEFAULT_REGISTRY
+}
+
+struct CallsChecker<'a> {
+    registry: &'a FunctionRegistry,
+    errors: Vec<CallError>,
+    cache: HashMap<ExpressionRawPointer, bool>,
+}
+
+impl CachedMapper<ExpressionRawPointer, bool> for CallsChecker<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer) -> Option<&bool> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer, value: bool) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl WalkMapper for CallsChecker<'_> {
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) {
+        if let Err(e) = self.registry.get_callee(call, params) {
+            if !self.errors.contains(&e) {
+                self.errors.push(e);
+            }
+        }
+        if !matches!(**call, Expression::Variable(_)) {
+            self.visit(call);
+        }
+        for param in params {
+            self.visit(param);
+        }
+    }
+}
+
+// }}}
+
+// {{{ math functions
+
+macro_rules! float_fn {
+    ($method: ident) => {
+        |args: &[LiteralT]| LiteralT::F64(args[0].as_f64().$method())
+    };
+}
+
+macro_rules! float_partial {
+    (|$x: ident| $body: expr) => {
+        |args: &[LiteralT]| {
+            let $x = args[0].as_f64();
+            smallvec![$body]
+        }
+    };
+}
+
+macro_rules! symbolic_partial {
+    (|$x: ident| $body: expr) => {
+        |args: &[Rc<Expression>]| {
+            let $x = &args[0];
+            smallvec![$body]
+        }
+    };
+}
+
+fn call1(name: &str, x: &Rc<Expression>) -> Rc<Expression> {
+    ops::call(var(name), [x.clone()])
+}
+
+fn math_function(name: &str, arity: usize, eval: NumericFunction, partials: NumericPartials,
+                 symbolic_partials: SymbolicPartials, latex_name: &str)
+                 -> FunctionInfo {
+    let mut function = FunctionInfo::new(name, arity, eval);
+    function.partials = Some(partials);
+    function.symbolic_partials = Some(symbolic_partials);
+    function.printed_names = HashMap::from([(PrintTarget::Python, format!("math.{}", name)),
+                                            (PrintTarget::NumPy, format!("np.{}", name)),
+                                            (PrintTarget::Rust, format!(".{}", name)),
+                                            (PrintTarget::LaTeX, latex_name.to_string())]);
+    function
+}
+
+fn get_math_functions() -> Vec<FunctionInfo> {
+    let mut functions =
+        vec![math_function("sin",
+                           1,
+                           float_fn!(sin),
+                           float_partial!(|x| x.cos()),
+                           symbolic_partial!(|x| call1("cos", x)),
+                           "\\sin"),
+             math_function("cos",
+                           1,
+                           float_fn!(cos),
+                           float_partial!(|x| -x.sin()),
+                           symbolic_partial!(|x| ops::neg(&call1("sin", x))),
+                           "\\cos"),
+             math_function("tan",
+                           1,
+                           float_fn!(tan),
+                           float_partial!(|x| 1.0 / (x.cos() * x.cos())),
+                           symbolic_partial!(|x| ops::div(&1.0, &ops::pow(&call1("cos", x), &2))),
+                           "\\tan"),
+             math_function("asin",
+                           1,
+                           float_fn!(asin),
+                           float_partial!(|x| 1.0 / (1.0 - x * x).sqrt()),
+                           symbolic_partial!(|x| {
+                               ops::div(&1.0, &call1("sqrt", &ops::sub(&1.0, &ops::pow(x, &2))))
+                           }),
+                           "\\arcsin"),
+             math_function("acos",
+                           1,
+                           float_fn!(acos),
+                           float_partial!(|x| -1.0 / (1.0 - x * x).sqrt()),
+                           symbolic_partial!(|x| {
+                               ops::div(&-1.0, &call1("sqrt", &ops::sub(&1.0, &ops::pow(x, &2))))
+                           }),
+                           "\\arccos"),
+             math_function("atan",
+                           1,
+                           float_fn!(atan),
+                           float_partial!(|x| 1.0 / (1.0 + x * x)),
+                           symbolic_partial!(|x| {
+                               ops::div(&1.0, &ops::add(&1.0, &ops::pow(x, &2)))
+                           }),
+                           "\\arctan"),
+             math_function("sinh",
+                           1,
+                           float_fn!(sinh),
+                           float_partial!(|x| x.cosh()),
+                           symbolic_partial!(|x| call1("cosh", x)),
+                           "\\sinh"),
+             math_function("cosh",
+                           1,
+                           float_fn!(cosh),
+                           float_partial!(|x| x.sinh()),
+                           symbolic_partial!(|x| call1("sinh", x)),
+                           "\\cosh"),
+             math_function("tanh",
+                           1,
+                           float_fn!(tanh),
+                           float_partial!(|x| 1.0 - x.tanh() * x.tanh()),
+                           symbolic_partial!(|x| {
+                               ops::sub(&1.0, &ops::pow(&call1("tanh", x), &2))
+                           }),
+                           "\\tanh"),
+             math_function("exp",
+                           1,
+                           float_fn!(exp),
+                           float_partial!(|x| x.exp()),
+                           symbolic_partial!(|x| call1("exp", x)),
+                           "\\exp"),
+             math_function("log",
+                           1,
+                           float_fn!(ln),
+                           float_partial!(|x| 1.0 / x),
+                           symbolic_partial!(|x| ops::div(&1.0, x)),
+                           "\\log"),
+             math_function("log10",
+                           1,
+                           float_fn!(log10),
+                           float_partial!(|x| 1.0 / (x * std::f64::consts::LN_10)),
+                           symbolic_partial!(|x| {
+                               ops::div(&1.0, &ops::mul(x, &std::f64::consts::LN_10))
+                           }),
+                           "\\log_{10}"),
+             math_function("sqrt",
+                           1,
+                           float_fn!(sqrt),
+                           float_partial!(|x| 0.5 / x.sqrt()),
+                           symbolic_partial!(|x| ops::div(&0.5, &call1("sqrt", x))),
+                           "\\sqrt"),
+             math_function("floor",
+                           1,
+                           float_fn!(floor),
+                           float_partial!(|_x| 0.0),
+                           symbolic_partial!(|_x| 0.to_expr()),
+                           "\\operatorname{floor}"),
+             math_function("ceil",
+                           1,
+                           float_fn!(ceil),
+                           float_partial!(|_x| 0.0),
+                           symbolic_partial!(|_x| 0.to_expr()),
+                           "\\operatorname{ceil}"),
+             math_function("atan2",
+                           2,
+                           |args: &[LiteralT]| {
+                               LiteralT::F64(args[0].as_f64().atan2(args[1].as_f64()))
+                           },
+                           |args: &[LiteralT]| {
+                               let (y, x) = (args[0].as_f64(), args[1].as_f64());
+                               let denom = x * x + y * y;
+                               smallvec![x / denom, -y / denom]
+                           },
+                           |args: &[Rc<Expression>]| {
+                               let (y, x) = (&args[0], &args[1]);
+                               let denom = ops::add(&ops::pow(x, &2), &ops::pow(y, &2));
+                               smallvec![ops::div(x, &denom), ops::neg(&ops::div(y, &denom))]
+                           },
+                           "\\operatorname{atan2}"),];
+
+    // natural logarithm is f64::ln in Rust
+    for function in functions.iter_mut().filter(|f| f.name == "log") {
+        function.printed_names
+                .insert(PrintTarget::Rust, ".ln".to_string());
+    }
+
+    let mut abs = math_function("abs",
+                                1,
+                                |args: &[LiteralT]| {
+                                    if args[0].is_integral() {
+                                        LiteralT::I64(args[0].as_i64().wrapping_abs())
+                                    } else {
+                                        LiteralT::F64(args[0].as_f64().abs())
+                                    }
+                                },
+                                float_partial!(|x| if x < 0.0 { -1.0 } else { 1.0 }),
+                                symbolic_partial!(|x| {
+                                    ops::ifthenelse(ops::less(x, &0), (-1).to_expr(), 1.to_expr())
+                                }),
+                                "\\operatorname{abs}");
+    abs.result_dtype = |args| {
+        if args[0].is_integral() {
+            DType::I64
+        } else {
+            DType::F64
+        }
+    };
+    abs.printed_names
+       .insert(PrintTarget::Python, "abs".to_string());
+    functions.push(abs);
+    functions
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -25,36 +25,121 @@
 //! - [`primitives`] contains the supported expression types.
 //! - [`builders`] contains routines to build these expressions.
 //! - [`mod@parse`] contains a parser implementation for these expressions.
+//! - [`path`] provides addressing and editing of sub-expressions by their
+//!   position.
+//! - [`query`] provides composable selectors for finding sub-expressions.
 //! - [`operations`] provides routines for performing common arithmetic
 //!   operations on these
 //! expressions.
+//! - [`functions`] provides a registry of the functions known to the evaluators
+//!   and code generators.
 //! - [`mappers`] provides abstract visitor for common traversal patters over
 //!   scalar expressions.
 //! - [`mapper_impls`] uses [`mappers`] to provide helpful analysis tools over
 //!   the expressions.
+//! - [`analysis`] provides a context that memoizes these analyses across calls.
 //! - [`design_doc`] goes over the key design decisions that were baked into
 //!   Symoxide's
 //! architecture.
 
+pub mod analysis;
 pub mod builders;
 pub mod design_doc;
 pub mod display;
+pub mod functions;
 pub mod macros;
 pub mod mapper_impls;
 pub mod mappers;
 pub mod operations;
 pub mod parse;
+pub mod path;
 pub mod primitives;
+pub mod query;
 mod utils;
 
+pub use analysis::AnalysisContext;
 pub use builders::var;
-pub use mapper_impls::deduplicator::deduplicate_nodes;
-pub use mapper_impls::dependency::get_dependencies;
-pub use mapper_impls::equality::are_structurally_equal;
-pub use mapper_impls::graphvizifier::show_dot;
-pub use mapper_impls::hasher::get_hasher;
-pub use mapper_impls::node_counter::get_num_nodes;
+pub use mapper_impls::alpha_equivalence::{
+    are_alpha_equivalent, are_alpha_equivalent_with_options, AlphaEquivalenceOptions,
+};
+pub use mapper_impls::array_access::{
+    collect_array_accesses, find_out_of_bounds_accesses, get_footprints,
+};
+pub use mapper_impls::compiler::{compile, Program};
+pub use mapper_impls::deduplicator::{deduplicate_many, deduplicate_nodes, Deduplicator};
+pub use mapper_impls::dependency::{get_dependencies, get_dependencies_many, DependencyInfo};
+pub use mapper_impls::diff::{diff, tree_edit_distance, DiffOptions, ExprDiff};
+pub use mapper_impls::equality::{are_structurally_equal, are_structurally_equal_many};
+pub use mapper_impls::equivalence::probably_equivalent;
+pub use mapper_impls::evaluate::{evaluate, try_evaluate};
+pub use mapper_impls::fingerprint::{fingerprint, Fingerprint, Fingerprinter, FINGERPRINT_VERSION};
+pub use mapper_impls::forward_diff::{evaluate_dual, evaluate_with_gradient};
+pub use mapper_impls::graphvizifier::{show_dot, to_dot, write_dot, DotOptions, RankDir};
+pub use mapper_impls::hasher::{get_hasher, HashCacher};
+pub use mapper_impls::latexifier::{to_latex, LatexOptions, MulSymbol};
+pub use mapper_impls::linearize::{delinearize, linearize};
+pub use mapper_impls::node_counter::{get_num_nodes, get_num_nodes_many};
+pub use mapper_impls::pretty_printer::{pretty_print, PrettyOptions};
+pub use mapper_impls::pythonifier::{to_python, PythonDialect, STRICT_PYTHON_PRELUDE};
+pub use mapper_impls::reverse_diff::get_gradient;
+pub use mapper_impls::rust_emitter::{to_rust_fn, DivModSemantics, RustArgType, RustSignature};
+pub use mapper_impls::stats::{ExpressionStats, NodeKind};
+pub use mapper_impls::type_inference::infer_dtype;
 pub use parse::parse_expr as parse;
-pub use primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+pub use path::{find_nodes, find_paths, ExprPath, ExprZipper};
+pub use primitives::{BinaryOpType, DType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+pub use query::{replace_matches, select, QueryMatch, Selector};
+/// See also [`parse()`], which parses the same grammar at runtime.
+///
+/// # Examples
+///
+/// ```rust
+/// use symoxide as sym;
+///
+/// let x = sym::var("x");
+/// let e = sym::expr!(a[i, j] * sin(#x) + 2 if i < n else 0);
+/// assert_eq!(e, sym::parse("a[i, j] * sin(x) + 2 if i < n else 0"));
+/// assert_eq!(sym::expr!("i // 2"), sym::parse("i // 2"));
+/// ```
+///
+/// Syntax errors are reported at compile time:
+///
+/// ```compile_fail
+/// let e = symoxide::expr!(a + * b);
+/// ```
+///
+/// ```compile_fail
+/// let e = symoxide::expr!(a[i if i < n]);
+/// ```
+pub use symoxide_macros::expr;
+/// # Examples
+///
+/// ```rust
+/// use std::rc::Rc;
+/// use symoxide::mappers::fold::UncachedFoldMapper;
+/// use symoxide::{identity_fold_mapper, parse, Expression};
+///
+/// struct Renamer;
+///
+/// #[identity_fold_mapper]
+/// impl UncachedFoldMapper for Renamer {
+///     fn map_variable(&self, name: String) -> Rc<Expression> {
+///         Rc::new(Expression::Variable(format!("{}_new", name)))
+///     }
+/// }
+///
+/// assert_eq!(Renamer.visit(&parse("f(x) + 2")), parse("f_new(x_new) + 2"));
+/// ```
+///
+/// ```compile_fail
+/// use symoxide::mappers::fold::UncachedFoldMapper as Mapper;
+///
+/// struct Identity;
+///
+/// // cannot infer the kind of fold mapper from an alias
+/// #[symoxide::identity_fold_mapper]
+/// impl Mapper for Identity {}
+/// ```
+pub use symoxide_macros::identity_fold_mapper;
 pub use symoxide_macros::{scalar, variables, CachedMapper};
 pub use utils::ExpressionRawPointer;
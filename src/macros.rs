@@ -66,3 +66,143 @@ macro_rules! impl_scalar_to_expr {
         }
     };
 }
+
+
+/// Defines the `map_*` methods of a fold mapper whose output is
+/// `Rc<Expression>` so that they rebuild the visited node from its recursively
+/// mapped children. Only the listed methods are defined, which lets a mapper
+/// override the remaining ones.
+///
+/// `cached` emits the signatures of [`FoldMapper`](`crate::mappers::fold::FoldMapper`)
+/// and `uncached` those of
+/// [`UncachedFoldMapper`](`crate::mappers::fold::UncachedFoldMapper`).
+///
+/// [`identity_fold_mapper`](`crate::identity_fold_mapper`) invokes this macro
+/// with the methods that an impl does not define.
+///
+/// # Example
+/// ```rust
+/// use std::rc::Rc;
+/// use symoxide::mappers::fold::UncachedFoldMapper;
+/// use symoxide::{define_identity_fold_methods, parse, Expression};
+///
+/// struct Renamer;
+///
+/// impl UncachedFoldMapper for Renamer {
+///     type Output = Rc<Expression>;
+///
+///     fn map_variable(&self, name: String) -> Rc<Expression> {
+///         Rc::new(Expression::Variable(format!("{}_new", name)))
+///     }
+///
+///     define_identity_fold_methods!(uncached; map_scalar, map_unary_op, map_binary_op, map_call,
+///                                   map_subscript, map_if);
+/// }
+///
+/// assert_eq!(Renamer.visit(&parse("f(x) + 2")), parse("f_new(x_new) + 2"));
+/// ```
+#[macro_export]
+macro_rules! define_identity_fold_methods {
+    (cached; $($method:ident),* $(,)?) => {
+        $($crate::define_identity_fold_methods!(@cached $method);)*
+    };
+    (uncached; $($method:ident),* $(,)?) => {
+        $($crate::define_identity_fold_methods!(@uncached $method);)*
+    };
+    (@cached map_scalar) => {
+        fn map_scalar(&mut self, value: &$crate::LiteralT) -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::Scalar(*value))
+        }
+    };
+    (@cached map_variable) => {
+        fn map_variable(&mut self, name: String) -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::Variable(name))
+        }
+    };
+    (@cached map_unary_op) => {
+        fn map_unary_op(&mut self, op: $crate::UnaryOpType, x: &std::rc::Rc<$crate::Expression>)
+                        -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::UnaryOp(op, self.visit(x)))
+        }
+    };
+    (@cached map_binary_op) => {
+        fn map_binary_op(&mut self, left: &std::rc::Rc<$crate::Expression>,
+                         op: $crate::BinaryOpType, right: &std::rc::Rc<$crate::Expression>)
+                         -> std::rc::Rc<$crate::Expression> {
+            let (left, right) = (self.visit(left), self.visit(right));
+            std::rc::Rc::new($crate::Expression::BinaryOp(left, op, right))
+        }
+    };
+    (@cached map_call) => {
+        fn map_call(&mut self, call: &std::rc::Rc<$crate::Expression>,
+                    params: &$crate::SmallVecExprT)
+                    -> std::rc::Rc<$crate::Expression> {
+            let params = params.iter().map(|param| self.visit(param)).collect();
+            std::rc::Rc::new($crate::Expression::Call(self.visit(call), params))
+        }
+    };
+    (@cached map_subscript) => {
+        fn map_subscript(&mut self, agg: &std::rc::Rc<$crate::Expression>,
+                         indices: &$crate::SmallVecExprT)
+                         -> std::rc::Rc<$crate::Expression> {
+            let indices = indices.iter().map(|idx| self.visit(idx)).collect();
+            std::rc::Rc::new($crate::Expression::Subscript(self.visit(agg), indices))
+        }
+    };
+    (@cached map_if) => {
+        fn map_if(&mut self, cond: &std::rc::Rc<$crate::Expression>,
+                  then: &std::rc::Rc<$crate::Expression>, else_: &std::rc::Rc<$crate::Expression>)
+                  -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::If(self.visit(cond),
+                                                    self.visit(then),
+                                                    self.visit(else_)))
+        }
+    };
+    (@uncached map_scalar) => {
+        fn map_scalar(&self, value: &$crate::LiteralT) -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::Scalar(*value))
+        }
+    };
+    (@uncached map_variable) => {
+        fn map_variable(&self, name: String) -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::Variable(name))
+        }
+    };
+    (@uncached map_unary_op) => {
+        fn map_unary_op(&self, op: $crate::UnaryOpType, x: &$crate::Expression)
+                        -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::UnaryOp(op, self.visit(x)))
+        }
+    };
+    (@uncached map_binary_op) => {
+        fn map_binary_op(&self, left: &$crate::Expression, op: $crate::BinaryOpType,
+                         right: &$crate::Expression)
+                         -> std::rc::Rc<$crate::Expression> {
+            let (left, right) = (self.visit(left), self.visit(right));
+            std::rc::Rc::new($crate::Expression::BinaryOp(left, op, right))
+        }
+    };
+    (@uncached map_call) => {
+        fn map_call(&self, call: &$crate::Expression, params: &$crate::SmallVecExprT)
+                    -> std::rc::Rc<$crate::Expression> {
+            let params = params.iter().map(|param| self.visit(param)).collect();
+            std::rc::Rc::new($crate::Expression::Call(self.visit(call), params))
+        }
+    };
+    (@uncached map_subscript) => {
+        fn map_subscript(&self, agg: &$crate::Expression, indices: &$crate::SmallVecExprT)
+                         -> std::rc::Rc<$crate::Expression> {
+            let indices = indices.iter().map(|idx| self.visit(idx)).collect();
+            std::rc::Rc::new($crate::Expression::Subscript(self.visit(agg), indices))
+        }
+    };
+    (@uncached map_if) => {
+        fn map_if(&self, cond: &$crate::Expression, then: &$crate::Expression,
+                  else_: &$crate::Expression)
+                  -> std::rc::Rc<$crate::Expression> {
+            std::rc::Rc::new($crate::Expression::If(self.visit(cond),
+                                                    self.visit(then),
+                                                    self.visit(else_)))
+        }
+    };
+}
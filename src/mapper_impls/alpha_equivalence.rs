@@ -0,0 +1,454 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Structural equality up to a renaming of the variables. See
+//! [`are_alpha_equivalent`].
+
+use crate::primitives::{BinaryOpType, Expression};
+use crate::utils::{get_children, ExpressionRawPointer};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Options for [`are_alpha_equivalent_with_options`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AlphaEquivalenceOptions {
+    /// If *true*, the operands of commutative binary operations (`+`, `*`,
+    /// `==`, `!=`, `&`, `|`, `^`, `and`, `or`) may be matched in either
+    /// order.
+    pub commutative: bool,
+}
+
+fn is_commutative(op: BinaryOpType) -> bool {
+    matches!(op,
+             BinaryOpType::Sum
+             | BinaryOpType::Product
+             | BinaryOpType::Equal
+             | BinaryOpType::NotEqual
+             | BinaryOpType::BitwiseOr
+             | BinaryOpType::BitwiseXor
+             | BinaryOpType::BitwiseAnd
+             | BinaryOpType::LogicalAnd
+             | BinaryOpType::LogicalOr)
+}
+
+/// Summary of a sub-expression that is invariant under renamings.
+#[derive(Copy, Clone)]
+struct NodeInfo {
+    /// Hash of the expression's structure that ignores the variable names and,
+    /// if operands may be swapped, the order of commutative operands.
+    hash: u64,
+    /// *true* if the expression has no operands that may be swapped, i.e. it
+    /// can be matched without any choice.
+    is_rigid: bool,
+}
+
+/// The choice points that led to comparing a pair of nodes, most recent
+/// first, as indices into [`AlphaEquivalenceChecker::choices`].
+type ChoiceList = Option<Rc<ChoiceListNode>>;
+
+struct ChoiceListNode {
+    choice: usize,
+    next: ChoiceList,
+}
+
+/// The choice points whose alternatives might resolve a failed match.
+type ConflictSet = BTreeSet<usize>;
+
+fn add_to_conflict_set(conflicts: &mut ConflictSet, mut choices: &ChoiceList) {
+    while let Some(node) = choices {
+        conflicts.insert(node.choice);
+        choices = &node.next;
+    }
+}
+
+type PairT = (Rc<Expression>, Rc<Expression>);
+
+/// A pair of nodes to be matched.
+#[derive(Clone)]
+struct Goal {
+    pair: PairT,
+    choices: ChoiceList,
+}
+
+/// An entry of [`AlphaEquivalenceChecker::trail`], undone when backtracking.
+enum TrailEntry {
+    Renamed(String),
+    Matched((ExpressionRawPointer, ExpressionRawPointer)),
+    PushedFlexibleGoal,
+    PoppedFlexibleGoal(Goal),
+}
+
+/// The operands of a commutative operation matched in order, with the
+/// swapped order left to try if a conflict involving this choice arises.
+struct ChoicePoint {
+    /// Length of the trail when the choice was made.
+    mark: usize,
+    /// The choice points that led to comparing the operation.
+    choices: ChoiceList,
+    swapped: Option<[PairT; 2]>,
+    /// The earlier choice points involved in the conflicts met so far under
+    /// this choice.
+    conflicts: ConflictSet,
+}
+
+fn get_key(expr1: &Rc<Expression>, expr2: &Rc<Expression>)
+           -> (ExpressionRawPointer, ExpressionRawPointer) {
+    (ExpressionRawPointer(expr1.clone()), ExpressionRawPointer(expr2.clone()))
+}
+
+/// Matches two expressions while growing a bijective renaming of their
+/// variables. Pairs of nodes still to be matched are kept as goals, and every
+/// choice of operand order is a choice point. A failed match jumps back to
+/// the most recent choice point involved in the failure and tries its other
+/// alternative, skipping the choices that cannot resolve the failure.
+/// Matched pairs of nodes are memoized, so that shared sub-expressions are
+/// compared once per pair.
+struct AlphaEquivalenceChecker {
+    commutative: bool,
+    forward: HashMap<String, String>,
+    backward: HashMap<String, String>,
+    /// The choice points that led to each renaming in *forward*.
+    renaming_choices: HashMap<String, ChoiceList>,
+    matched: hashbrown::HashSet<(ExpressionRawPointer, ExpressionRawPointer)>,
+    node_infos: hashbrown::HashMap<ExpressionRawPointer, NodeInfo>,
+    /// Goals whose matching involves no choice. They are matched before any
+    /// flexible goal, so that the renamings they force guide the choices.
+    rigid_goals: Vec<Goal>,
+    flexible_goals: Vec<Goal>,
+    choices: Vec<ChoicePoint>,
+    /// Renamings, matches and changes to *flexible_goals* in the order they
+    /// were made.
+    trail: Vec<TrailEntry>,
+}
+
+impl AlphaEquivalenceChecker {
+    fn new(commutative: bool) -> Self {
+        AlphaEquivalenceChecker { commutative,
+                                  forward: HashMap::new(),
+                                  backward: HashMap::new(),
+                                  renaming_choices: HashMap::new(),
+                                  matched: hashbrown::HashSet::new(),
+                                  node_infos: hashbrown::HashMap::new(),
+                                  rigid_goals: vec![],
+                                  flexible_goals: vec![],
+                                  choices: vec![],
+                                  trail: vec![] }
+    }
+
+    fn get_node_info(&mut self, expr: &Rc<Expression>) -> NodeInfo {
+        let key = ExpressionRawPointer(expr.clone());
+        if let Some(info) = self.node_infos.get(&key) {
+            return *info;
+        }
+        let children = get_children(expr);
+        let child_infos: Vec<NodeInfo> = children.iter().map(|x| self.get_node_info(x)).collect();
+        let mut child_hashes: Vec<u64> = child_infos.iter().map(|x| x.hash).collect();
+        let mut is_rigid = child_infos.iter().all(|x| x.is_rigid);
+
+        let mut hasher = DefaultHasher::new();
+        match &**expr {
+            Expression::Scalar(x) => (0u8, format!("{:?}", x)).hash(&mut hasher),
+            Expression::Variable(_) => 1u8.hash(&mut hasher),
+            Expression::UnaryOp(op, _) => (2u8, op).hash(&mut hasher),
+            Expression::BinaryOp(_, op, _) => {
+                (3u8, op).hash(&mut hasher);
+                if self.commutative && is_commutative(*op) {
+                    child_hashes.sort_unstable();
+                    is_rigid = false;
+                }
+            }
+            Expression::Call(function, _) => {
+                4u8.hash(&mut hasher);
+                // functions are not renamed
+                if let Expression::Variable(name) = &**function {
+                    name.hash(&mut hasher);
+                }
+            }
+            Expression::Subscript(..) => 5u8.hash(&mut hasher),
+            Expression::If(..) => 6u8.hash(&mut hasher),
+        }
+        child_hashes.hash(&mut hasher);
+
+        let info = NodeInfo { hash: hasher.finish(),
+                              is_rigid };
+        self.node_infos.insert(key, info);
+        info
+    }
+
+    /// Records that *name1* is renamed to *name2*. Returns the choice points
+    /// involved if this contradicts an earlier renaming.
+    fn rename(&mut self, name1: &str, name2: &str, choices: &ChoiceList)
+              -> Result<(), ConflictSet> {
+        match (self.forward.get(name1), self.backward.get(name2)) {
+            (None, None) => {
+                self.forward.insert(name1.to_string(), name2.to_string());
+                self.backward.insert(name2.to_string(), name1.to_string());
+                self.renaming_choices
+                    .insert(name1.to_string(), choices.clone());
+                self.trail.push(TrailEntry::Renamed(name1.to_string()));
+                Ok(())
+            }
+            (Some(x), Some(y)) if x == name2 && y == name1 => Ok(()),
+            (x, y) => {
+                let mut conflicts = ConflictSet::new();
+                add_to_conflict_set(&mut conflicts, choices);
+                if x.is_some() {
+                    add_to_conflict_set(&mut conflicts, &self.renaming_choices[name1]);
+                }
+                if let Some(y) = y {
+                    add_to_conflict_set(&mut conflicts, &self.renaming_choices[y]);
+                }
+                Err(conflicts)
+            }
+        }
+    }
+
+    fn push_goal(&mut self, goal: Goal) {
+        if self.get_node_info(&goal.pair.0).is_rigid {
+            self.rigid_goals.push(goal);
+        } else {
+            self.flexible_goals.push(goal);
+            self.trail.push(TrailEntry::PushedFlexibleGoal);
+        }
+    }
+
+    fn pop_goal(&mut self) -> Option<Goal> {
+        self.rigid_goals.pop().or_else(|| {
+                                  let goal = self.flexible_goals.pop()?;
+                                  self.trail
+                                      .push(TrailEntry::PoppedFlexibleGoal(goal.clone()));
+                                  Some(goal)
+                              })
+    }
+
+    /// Undoes the renamings, matches and changes to the goals made since the
+    /// trail had length *mark*. Choice points are only made while no rigid
+    /// goals are pending, so those are dropped.
+    fn backtrack(&mut self, mark: usize) {
+        self.rigid_goals.clear();
+        for entry in self.trail.drain(mark..).rev() {
+            match entry {
+                TrailEntry::Renamed(name1) => {
+                    let name2 = self.forward.remove(&name1).unwrap();
+                    self.backward.remove(&name2);
+                    self.renaming_choices.remove(&name1);
+                }
+                TrailEntry::Matched(key) => {
+                    self.matched.remove(&key);
+                }
+                TrailEntry::PushedFlexibleGoal => {
+                    self.flexible_goals.pop();
+                }
+                TrailEntry::PoppedFlexibleGoal(goal) => self.flexible_goals.push(goal),
+            }
+        }
+    }
+
+    /// Checks that the nodes of *goal* can be equal under the renaming,
+    /// extending it if needed, and adds the pairs of their operands as goals.
+    /// Returns the choice points involved if they cannot be equal.
+    fn match_goal(&mut self, goal: Goal) -> Result<(), ConflictSet> {
+        let Goal { pair: (expr1, expr2),
+                   choices, } = goal;
+        let mismatch = || {
+            let mut conflicts = ConflictSet::new();
+            add_to_conflict_set(&mut conflicts, &choices);
+            conflicts
+        };
+        if self.matched.contains(&get_key(&expr1, &expr2)) {
+            return Ok(());
+        }
+        if self.get_node_info(&expr1).hash != self.get_node_info(&expr2).hash {
+            return Err(mismatch());
+        }
+
+        let operands: Option<Vec<PairT>> = match (&*expr1, &*expr2) {
+            (Expression::Scalar(x1), Expression::Scalar(x2)) => (x1 == x2).then(Vec::new),
+            (Expression::Variable(name1), Expression::Variable(name2)) => {
+                self.rename(name1, name2, &choices)?;
+                Some(vec![])
+            }
+            (Expression::UnaryOp(op1, x1), Expression::UnaryOp(op2, x2)) => {
+                (op1 == op2).then(|| vec![(x1.clone(), x2.clone())])
+            }
+            (Expression::BinaryOp(l1, op1, r1), Expression::BinaryOp(l2, op2, r2))
+                if op1 == op2 =>
+            {
+                if self.commutative && is_commutative(*op1) {
+                    // the operand order is chosen once every pending goal
+                    // that leaves no choice has been matched
+                    self.matched.insert(get_key(&expr1, &expr2));
+                    self.trail
+                        .push(TrailEntry::Matched(get_key(&expr1, &expr2)));
+                    let swapped = [(l1.clone(), r2.clone()), (r1.clone(), l2.clone())];
+                    self.choices.push(ChoicePoint { mark: self.trail.len(),
+                                                    choices,
+                                                    swapped: Some(swapped),
+                                                    conflicts: ConflictSet::new() });
+                    self.push_choice_goals(vec![(l1.clone(), l2.clone()),
+                                                (r1.clone(), r2.clone())]);
+                    return Ok(());
+                }
+                Some(vec![(l1.clone(), l2.clone()), (r1.clone(), r2.clone())])
+            }
+            (Expression::Call(function1, params1), Expression::Call(function2, params2)) => {
+                // functions are not renamed
+                let mut pairs: Vec<PairT> = params1.iter()
+                                                   .cloned()
+                                                   .zip(params2.iter().cloned())
+                                                   .collect();
+                let same_function = match (&**function1, &**function2) {
+                    (Expression::Variable(name1), Expression::Variable(name2)) => name1 == name2,
+                    _ => {
+                        pairs.push((function1.clone(), function2.clone()));
+                        true
+                    }
+                };
+                (same_function && params1.len() == params2.len()).then_some(pairs)
+            }
+            (Expression::Subscript(..), Expression::Subscript(..))
+            | (Expression::If(..), Expression::If(..)) => {
+                let (children1, children2) = (get_children(&expr1), get_children(&expr2));
+                (children1.len() == children2.len()).then(|| {
+                                                        children1.into_iter()
+                                                                 .zip(children2)
+                                                                 .collect()
+                                                    })
+            }
+            _ => None,
+        };
+
+        let operands = operands.ok_or_else(mismatch)?;
+        self.matched.insert(get_key(&expr1, &expr2));
+        self.trail
+            .push(TrailEntry::Matched(get_key(&expr1, &expr2)));
+        for pair in operands {
+            self.push_goal(Goal { pair,
+                                  choices: choices.clone() });
+        }
+        Ok(())
+    }
+
+    /// Adds *pairs* as goals that follow from the most recent choice point.
+    fn push_choice_goals(&mut self, pairs: Vec<PairT>) {
+        let ichoice = self.choices.len() - 1;
+        let choices = Some(Rc::new(ChoiceListNode { choice: ichoice,
+                                                    next: self.choices[ichoice].choices
+                                                                               .clone() }));
+        for pair in pairs {
+            self.push_goal(Goal { pair,
+                                  choices: choices.clone() });
+        }
+    }
+
+    /// Jumps back to the most recent choice point in *conflicts* with an
+    /// alternative left and tries it. Returns *false* if there is none.
+    fn backjump(&mut self, mut conflicts: ConflictSet) -> bool {
+        while let Some(ichoice) = conflicts.pop_last() {
+            self.choices.truncate(ichoice + 1);
+            let choice = &mut self.choices[ichoice];
+            choice.conflicts.append(&mut conflicts);
+            match choice.swapped.take() {
+                Some(swapped) => {
+                    let mark = choice.mark;
+                    self.backtrack(mark);
+                    self.push_choice_goals(swapped.into());
+                    return true;
+                }
+                None => conflicts = self.choices.pop().unwrap().conflicts,
+            }
+        }
+        false
+    }
+
+    /// Returns *true* if the renaming can be extended such that *expr1* and
+    /// *expr2* are equal, in which case the renaming is extended.
+    fn solve(&mut self, expr1: &Rc<Expression>, expr2: &Rc<Expression>) -> bool {
+        self.push_goal(Goal { pair: (expr1.clone(), expr2.clone()),
+                              choices: None });
+        while let Some(goal) = self.pop_goal() {
+            if let Err(conflicts) = self.match_goal(goal) {
+                if !self.backjump(conflicts) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Returns a renaming of the variables of *expr1* that makes it structurally
+/// equal to *expr2*, or *None* if no such renaming exists. The renaming is a
+/// bijection between the variables of both expressions, mapping each
+/// variable of *expr1* to its counterpart in *expr2*. The names of called
+/// functions must match exactly.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{are_alpha_equivalent, parse};
+///
+/// let renaming = are_alpha_equivalent(&parse("a[iface_ensm15] * x + x"),
+///                                     &parse("b[_0] * _1 + _1")).unwrap();
+/// assert_eq!(renaming["iface_ensm15"], "_0");
+/// assert_eq!(renaming["x"], "_1");
+/// // x cannot be renamed to both _1 and _2
+/// assert!(are_alpha_equivalent(&parse("x * x"), &parse("_1 * _2")).is_none());
+/// ```
+pub fn are_alpha_equivalent(expr1: &Rc<Expression>, expr2: &Rc<Expression>)
+                            -> Option<HashMap<String, String>> {
+    are_alpha_equivalent_with_options(expr1, expr2, &AlphaEquivalenceOptions::default())
+}
+
+/// Returns a renaming of the variables of *expr1* that makes it equal to
+/// *expr2*, as [`are_alpha_equivalent`], with the equality governed by
+/// *options*.
+///
+/// The operands of a commutative operation are matched in order and, if that
+/// or any later match depending on this order fails, swapped, so every
+/// combination of operand orders is considered. A failed match only revisits
+/// the orders it depends on, and operands whose matching involves no choice
+/// are matched first, as the renamings they force rule out most combinations
+/// early.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{are_alpha_equivalent_with_options, parse, AlphaEquivalenceOptions};
+///
+/// let options = AlphaEquivalenceOptions { commutative: true };
+/// let renaming = are_alpha_equivalent_with_options(&parse("x * (y - 1)"),
+///                                                  &parse("(b - 1) * a"),
+///                                                  &options).unwrap();
+/// assert_eq!(renaming["x"], "a");
+/// assert_eq!(renaming["y"], "b");
+/// ```
+pub fn are_alpha_equivalent_with_options(expr1: &Rc<Expression>, expr2: &Rc<Expression>,
+                                         options: &AlphaEquivalenceOptions)
+                                         -> Option<HashMap<String, String>> {
+    let mut checker = AlphaEquivalenceChecker::new(options.commutative);
+    if checker.solve(expr1, expr2) {
+        Some(checker.forward)
+    } else {
+        None
+    }
+}
+
+// vim: fdm=marker
@@ -0,0 +1,433 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Collection of array accesses and the range of indices they touch.
+//!
+//! An array access is a [`Expression::Subscript`] whose aggregate is a
+//! [`Expression::Variable`]. Given integer bounds on the variables appearing
+//! in the indices (the *domain*), the range of every index is computed
+//! exactly if the index is affine in the domain's variables, and
+//! conservatively via interval arithmetic otherwise. Index arithmetic follows
+//! Python's semantics, as in [`evaluate`](crate::evaluate).
+
+use crate::mappers::walk::WalkMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use crate::CachedMapper;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+// {{{ Interval
+
+/// The integers `lo, lo+1, ..., hi`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Interval {
+    pub fn new(lo: i64, hi: i64) -> Self {
+        assert!(lo <= hi, "Empty interval [{}, {}].", lo, hi);
+        Interval { lo, hi }
+    }
+
+    pub fn point(value: i64) -> Self {
+        Interval { lo: value,
+                   hi: value }
+    }
+
+    /// Returns *true* only if every integer in *self* lies in *other*.
+    pub fn is_subset_of(&self, other: &Interval) -> bool {
+        other.lo <= self.lo && self.hi <= other.hi
+    }
+
+    /// Returns the smallest interval containing both *self* and *other*.
+    pub fn hull(&self, other: &Interval) -> Interval {
+        Interval { lo: self.lo.min(other.lo),
+                   hi: self.hi.max(other.hi) }
+    }
+
+    fn from_candidates(candidates: [Option<i64>; 4]) -> Option<Interval> {
+        let candidates: Option<Vec<i64>> = candidates.into_iter().collect();
+        let candidates = candidates?;
+        Some(Interval { lo: *candidates.iter().min().unwrap(),
+                        hi: *candidates.iter().max().unwrap() })
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+fn floor_div(left: i64, right: i64) -> Option<i64> {
+    let quotient = left.checked_div(right)?;
+    if (left % right != 0) && ((left < 0) != (right < 0)) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+// }}}
+
+// {{{ index range analysis
+
+/// Range of the values an index expression takes over a domain.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IndexRange {
+    pub interval: Interval,
+    /// *true* if both bounds of [`IndexRange::interval`] are attained by some
+    /// point of the domain, *false* if the interval is only an enclosure.
+    pub is_exact: bool,
+}
+
+/// `(constant, coefficients)` of an affine combination of variables.
+type AffineForm = (i64, BTreeMap<String, i64>);
+
+/// Returns the [`AffineForm`] of *expr* if it is an affine combination of
+/// variables with integer coefficients. Results are memoized in *cache*.
+fn get_affine_form(expr: &Rc<Expression>,
+                   cache: &mut HashMap<ExpressionRawPointer, Option<AffineForm>>)
+                   -> Option<AffineForm> {
+    fn combine(left: AffineForm, right: AffineForm, sign: i64) -> Option<AffineForm> {
+        let (mut constant, mut coeffs) = left;
+        constant = constant.checked_add(right.0.checked_mul(sign)?)?;
+        for (name, coeff) in right.1 {
+            let entry = coeffs.entry(name).or_insert(0);
+            *entry = entry.checked_add(coeff.checked_mul(sign)?)?;
+        }
+        Some((constant, coeffs))
+    }
+
+    fn scale(form: AffineForm, factor: i64) -> Option<AffineForm> {
+        let mut coeffs = BTreeMap::new();
+        for (name, coeff) in form.1 {
+            coeffs.insert(name, coeff.checked_mul(factor)?);
+        }
+        Some((form.0.checked_mul(factor)?, coeffs))
+    }
+
+    let key = ExpressionRawPointer(expr.clone());
+    if let Some(form) = cache.get(&key) {
+        return form.clone();
+    }
+    let mut visit = |x: &Rc<Expression>| get_affine_form(x, cache);
+    let form = match &**expr {
+        Expression::Scalar(value) if value.is_integral() => Some((value.as_i64(), BTreeMap::new())),
+        Expression::Variable(name) => Some((0, BTreeMap::from([(name.clone(), 1)]))),
+        Expression::UnaryOp(UnaryOpType::Minus, x) => visit(x).and_then(|x| scale(x, -1)),
+        Expression::BinaryOp(left, op @ (BinaryOpType::Sum | BinaryOpType::Subtract), right) => {
+            let sign = if *op == BinaryOpType::Sum { 1 } else { -1 };
+            match (visit(left), visit(right)) {
+                (Some(left), Some(right)) => combine(left, right, sign),
+                _ => None,
+            }
+        }
+        Expression::BinaryOp(left, BinaryOpType::Product, right) => {
+            match (visit(left), visit(right)) {
+                (Some(left), Some(right)) if left.1.values().all(|coeff| *coeff == 0) => {
+                    scale(right, left.0)
+                }
+                (Some(left), Some(right)) if right.1.values().all(|coeff| *coeff == 0) => {
+                    scale(left, right.0)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    cache.insert(key, form.clone());
+    form
+}
+
+/// Returns an interval enclosing the values of *expr* over *domain*. Results
+/// are memoized in *cache*.
+fn get_enclosing_interval(expr: &Rc<Expression>, domain: &HashMap<String, Interval>,
+                          cache: &mut HashMap<ExpressionRawPointer, Option<Interval>>)
+                          -> Option<Interval> {
+    let key = ExpressionRawPointer(expr.clone());
+    if let Some(interval) = cache.get(&key) {
+        return *interval;
+    }
+    let interval = compute_enclosing_interval(expr, domain, cache);
+    cache.insert(key, interval);
+    interval
+}
+
+fn compute_enclosing_interval(expr: &Expression, domain: &HashMap<String, Interval>,
+                              cache: &mut HashMap<ExpressionRawPointer, Option<Interval>>)
+                              -> Option<Interval> {
+    match expr {
+        Expression::Scalar(value) if value.is_integral() => Some(Interval::point(value.as_i64())),
+        Expression::Variable(name) => domain.get(name).copied(),
+        Expression::UnaryOp(UnaryOpType::Minus, x) => {
+            let x = get_enclosing_interval(x, domain, cache)?;
+            Some(Interval { lo: x.hi.checked_neg()?,
+                            hi: x.lo.checked_neg()? })
+        }
+        Expression::BinaryOp(left, BinaryOpType::Modulo, right) => {
+            // the remainder takes the sign of the divisor, whatever the dividend
+            let r = get_enclosing_interval(right, domain, cache)?;
+            match get_enclosing_interval(left, domain, cache) {
+                _ if r.lo <= 0 => None,
+                Some(l) if l.lo >= 0 && l.hi < r.lo => Some(l),
+                _ => Some(Interval { lo: 0,
+                                     hi: r.hi - 1 }),
+            }
+        }
+        Expression::BinaryOp(left, op, right) => {
+            let l = get_enclosing_interval(left, domain, cache)?;
+            let r = get_enclosing_interval(right, domain, cache)?;
+            match op {
+                BinaryOpType::Sum => Some(Interval { lo: l.lo.checked_add(r.lo)?,
+                                                     hi: l.hi.checked_add(r.hi)? }),
+                BinaryOpType::Subtract => Some(Interval { lo: l.lo.checked_sub(r.hi)?,
+                                                          hi: l.hi.checked_sub(r.lo)? }),
+                BinaryOpType::Product => Interval::from_candidates([l.lo.checked_mul(r.lo),
+                                                                    l.lo.checked_mul(r.hi),
+                                                                    l.hi.checked_mul(r.lo),
+                                                                    l.hi.checked_mul(r.hi)]),
+                BinaryOpType::FloorDiv if r.lo > 0 || r.hi < 0 => {
+                    Interval::from_candidates([floor_div(l.lo, r.lo),
+                                               floor_div(l.lo, r.hi),
+                                               floor_div(l.hi, r.lo),
+                                               floor_div(l.hi, r.hi)])
+                }
+                _ => None,
+            }
+        }
+        Expression::If(_, then, else_) => {
+            let then = get_enclosing_interval(then, domain, cache)?;
+            let else_ = get_enclosing_interval(else_, domain, cache)?;
+            Some(then.hull(&else_))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the range of `constant + sum(coeffs[name] * name)` over *domain*.
+fn get_affine_range(constant: i64, coeffs: &BTreeMap<String, i64>,
+                    domain: &HashMap<String, Interval>)
+                    -> Option<Interval> {
+    let mut interval = Interval::point(constant);
+    for (name, coeff) in coeffs.iter().filter(|(_, coeff)| **coeff != 0) {
+        let bounds = domain.get(name)?;
+        let (lo, hi) = if *coeff > 0 {
+            (bounds.lo, bounds.hi)
+        } else {
+            (bounds.hi, bounds.lo)
+        };
+        interval = Interval { lo: interval.lo.checked_add(lo.checked_mul(*coeff)?)?,
+                              hi: interval.hi.checked_add(hi.checked_mul(*coeff)?)? };
+    }
+    Some(interval)
+}
+
+/// Returns the range of *index* over *domain*, or *None* if it could not be
+/// bounded, for ex. because it depends on a variable outside *domain*.
+pub fn get_index_range(index: &Rc<Expression>, domain: &HashMap<String, Interval>)
+                       -> Option<IndexRange> {
+    // the extrema of an affine function over a box are attained at its corners
+    let affine_range =
+        get_affine_form(index, &mut HashMap::new()).and_then(|(constant, coeffs)| {
+                                                       get_affine_range(constant, &coeffs, domain)
+                                                   });
+    match affine_range {
+        Some(interval) => Some(IndexRange { interval,
+                                            is_exact: true }),
+        None => get_enclosing_interval(index, domain, &mut HashMap::new()).map(|interval| {
+                                                                              IndexRange { interval,
+                                 is_exact: false }
+                                                                          }),
+    }
+}
+
+// }}}
+
+// {{{ array access collection
+
+/// A subscript of the array named [`ArrayAccess::array`].
+#[derive(Clone, Debug)]
+pub struct ArrayAccess {
+    pub array: String,
+    pub indices: Vec<Rc<Expression>>,
+}
+
+impl ArrayAccess {
+    /// Returns the range of every index of the access over *domain*. See
+    /// [`get_index_range`].
+    pub fn get_index_ranges(&self, domain: &HashMap<String, Interval>) -> Vec<Option<IndexRange>> {
+        self.indices
+            .iter()
+            .map(|idx| get_index_range(idx, domain))
+            .collect()
+    }
+}
+
+impl fmt::Display for ArrayAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indices: Vec<String> = self.indices.iter().map(|idx| idx.to_string()).collect();
+        write!(f, "{}[{}]", self.array, indices.join(", "))
+    }
+}
+
+#[derive(CachedMapper)]
+struct ArrayAccessCollector {
+    accesses: Vec<ArrayAccess>,
+    cache: hashbrown::HashMap<ExpressionRawPointer, bool>,
+}
+
+impl WalkMapper for ArrayAccessCollector {
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT) {
+        match &**agg {
+            Expression::Variable(name) => self.accesses
+                                              .push(ArrayAccess { array: name.clone(),
+                                                                  indices: indices.to_vec() }),
+            _ => self.visit(agg),
+        }
+        for idx in indices {
+            self.visit(idx);
+        }
+    }
+}
+
+/// Returns the array accesses in *expr*, in the order of a left-to-right,
+/// depth-first traversal. A subscript node shared within *expr* is reported
+/// once.
+pub fn collect_array_accesses(expr: &Rc<Expression>) -> Vec<ArrayAccess> {
+    let mut collector = ArrayAccessCollector { accesses: vec![],
+                                               cache: hashbrown::HashMap::new() };
+    collector.visit(expr);
+    collector.accesses
+}
+
+// }}}
+
+// {{{ footprints
+
+/// The indices of an array accessed by an expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrayFootprint {
+    pub array: String,
+    /// Range of the accessed indices along every axis, *None* for an axis
+    /// along which an index could not be bounded.
+    pub ranges: Vec<Option<IndexRange>>,
+}
+
+/// Returns the footprint of every array accessed in *expr* over *domain*, in
+/// order of first access. The footprint of an array is the hull of the ranges
+/// of its accesses. Accesses to an array with a different number of indices
+/// get separate footprints.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::mapper_impls::array_access::{get_footprints, Interval};
+/// use symoxide::parse;
+///
+/// let domain = HashMap::from([("i".to_string(), Interval::new(0, 9))]);
+/// let footprints = get_footprints(&parse("a[2*i + 1] - a[i]"), &domain);
+/// let range = footprints[0].ranges[0].unwrap();
+/// assert_eq!(range.interval, Interval::new(0, 19));
+/// assert!(range.is_exact);
+/// ```
+pub fn get_footprints(expr: &Rc<Expression>, domain: &HashMap<String, Interval>)
+                      -> Vec<ArrayFootprint> {
+    let mut footprints: Vec<ArrayFootprint> = vec![];
+    for access in collect_array_accesses(expr) {
+        let ranges = access.get_index_ranges(domain);
+        let footprint =
+            footprints.iter_mut()
+                      .find(|footprint| {
+                          footprint.array == access.array && footprint.ranges.len() == ranges.len()
+                      });
+        match footprint {
+            Some(footprint) => {
+                for (range, new_range) in footprint.ranges.iter_mut().zip(ranges) {
+                    *range = match (*range, new_range) {
+                        (Some(old), Some(new)) => {
+                            Some(IndexRange { interval: old.interval.hull(&new.interval),
+                                              is_exact: old.is_exact && new.is_exact })
+                        }
+                        _ => None,
+                    };
+                }
+            }
+            None => footprints.push(ArrayFootprint { array: access.array,
+                                                     ranges }),
+        }
+    }
+    footprints
+}
+
+/// An array access that may index outside of its array's declared shape.
+#[derive(Clone, Debug)]
+pub struct OutOfBoundsAccess {
+    pub access: ArrayAccess,
+    /// Axes along which the access may be out of bounds. Every axis of the
+    /// access if its number of indices does not match the array's shape.
+    pub axes: Vec<usize>,
+}
+
+/// Returns the accesses in *expr* that may be out of bounds over *domain*.
+/// The shape of every array is given in *shapes*, an index along an axis of
+/// length `n` being valid if it lies in `[0, n-1]`. Indices that could not be
+/// bounded are reported as possibly out of bounds, and arrays missing from
+/// *shapes* are not checked.
+pub fn find_out_of_bounds_accesses(expr: &Rc<Expression>, domain: &HashMap<String, Interval>,
+                                   shapes: &HashMap<String, Vec<i64>>)
+                                   -> Vec<OutOfBoundsAccess> {
+    let mut result = vec![];
+    for access in collect_array_accesses(expr) {
+        let shape = match shapes.get(&access.array) {
+            Some(shape) => shape,
+            None => continue,
+        };
+        let axes: Vec<usize> = if shape.len() != access.indices.len() {
+            (0..access.indices.len()).collect()
+        } else {
+            access.get_index_ranges(domain)
+                  .iter()
+                  .zip(shape)
+                  .enumerate()
+                  .filter(|(_, (range, axis_len))| match range {
+                      Some(range) => !range.interval
+                                           .is_subset_of(&Interval { lo: 0,
+                                                                     hi: **axis_len - 1 }),
+                      None => true,
+                  })
+                  .map(|(iaxis, _)| iaxis)
+                  .collect()
+        };
+        if !axes.is_empty() {
+            result.push(OutOfBoundsAccess { access, axes });
+        }
+    }
+    result
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -0,0 +1,553 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Lowers an expression to a flat list of register machine instructions for
+//! repeated evaluation. See [`compile`].
+
+use crate::functions::{default_registry, FunctionRegistry, NumericFunction};
+use crate::mapper_impls::evaluate::{eval_binary_op, eval_unary_op, promote};
+use crate::mappers::fold::FoldMapper;
+use crate::mappers::walk::WalkMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use crate::CachedMapper;
+use smallvec::SmallVec;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+// {{{ Instruction
+
+/// An instruction of a [`Program`]. All operands refer to indices in the
+/// program's register file.
+#[derive(Copy, Clone)]
+pub enum Instruction {
+    /// `dst = op(x)`
+    UnaryOp {
+        op: UnaryOpType,
+        x: usize,
+        dst: usize,
+    },
+    /// `dst = op(left, right)`
+    BinaryOp {
+        left: usize,
+        op: BinaryOpType,
+        right: usize,
+        dst: usize,
+    },
+    /// `dst = functions[function](*args)` where the argument registers are
+    /// stored as `call_args[args_start..args_start+nargs]` of the program.
+    Call {
+        function: usize,
+        args_start: usize,
+        nargs: usize,
+        dst: usize,
+    },
+    /// `dst = src`
+    Copy { src: usize, dst: usize },
+    /// Continue execution from the instruction at `target`.
+    Jump { target: usize },
+    /// Continue execution from the instruction at `target` if `cond` is
+    /// truthy.
+    JumpIf { cond: usize, target: usize },
+    /// Continue execution from the instruction at `target` if `cond` is not
+    /// truthy.
+    JumpUnless { cond: usize, target: usize },
+}
+
+// }}}
+
+// {{{ Program
+
+/// A compiled expression. See [`compile`].
+///
+/// The register file starts with the program's inputs, followed by the
+/// constants of the expression and the temporaries. It is owned by the caller,
+/// see [`Program::new_registers`], so that a program can be shared across
+/// threads and evaluated re-entrantly.
+pub struct Program {
+    input_names: Vec<String>,
+    instructions: Vec<Instruction>,
+    call_args: Vec<usize>,
+    functions: Vec<(String, NumericFunction)>,
+    output: usize,
+    /// Values of the registers before an evaluation.
+    initial_registers: Vec<LiteralT>,
+}
+
+impl Program {
+    /// Returns the names of the inputs in the order expected by
+    /// [`Program::eval`].
+    pub fn input_names(&self) -> &[String] {
+        &self.input_names
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn num_registers(&self) -> usize {
+        self.initial_registers.len()
+    }
+
+    /// Returns a register file to pass to [`Program::eval`] and
+    /// [`Program::eval_batch`]. It can be re-used across evaluations.
+    pub fn new_registers(&self) -> Vec<LiteralT> {
+        self.initial_registers.clone()
+    }
+
+    /// Initializes the constants of the register file *registers*.
+    fn reset_registers(&self, registers: &mut [LiteralT]) {
+        assert_eq!(registers.len(),
+                   self.num_registers(),
+                   "Expected {} registers, got {}.",
+                   self.num_registers(),
+                   registers.len());
+        let ninputs = self.input_names.len();
+        registers[ninputs..].copy_from_slice(&self.initial_registers[ninputs..]);
+    }
+
+    /// Evaluates the program with `inputs[i]` as the value of the input
+    /// `self.input_names()[i]`, using *registers* as the register file. See
+    /// [`Program::new_registers`].
+    pub fn eval(&self, inputs: &[LiteralT], registers: &mut [LiteralT]) -> LiteralT {
+        assert_eq!(inputs.len(),
+                   self.input_names.len(),
+                   "Expected {} inputs, got {}.",
+                   self.input_names.len(),
+                   inputs.len());
+        self.reset_registers(registers);
+        for (register, input) in registers.iter_mut().zip(inputs.iter()) {
+            *register = promote(*input);
+        }
+        self.run(registers)
+    }
+
+    /// Evaluates the program over a batch of inputs, with `inputs[i][j]` as
+    /// the value of the input `self.input_names()[i]` for the `j`-th
+    /// evaluation, and writes the result of the `j`-th evaluation to
+    /// `out[j]`. *registers* is used as the register file, see
+    /// [`Program::new_registers`].
+    pub fn eval_batch(&self, inputs: &[&[LiteralT]], out: &mut [LiteralT],
+                      registers: &mut [LiteralT]) {
+        assert_eq!(inputs.len(),
+                   self.input_names.len(),
+                   "Expected {} inputs, got {}.",
+                   self.input_names.len(),
+                   inputs.len());
+        for (input, name) in inputs.iter().zip(self.input_names.iter()) {
+            assert_eq!(input.len(),
+                       out.len(),
+                       "Expected {} values for the input '{}', got {}.",
+                       out.len(),
+                       name,
+                       input.len());
+        }
+        self.reset_registers(registers);
+        for (i, result) in out.iter_mut().enumerate() {
+            for (iinput, input) in inputs.iter().enumerate() {
+                registers[iinput] = promote(input[i]);
+            }
+            *result = self.run(registers);
+        }
+    }
+
+    fn run(&self, registers: &mut [LiteralT]) -> LiteralT {
+        let mut pc = 0;
+        while pc < self.instructions.len() {
+            match self.instructions[pc] {
+                Instruction::UnaryOp { op, x, dst } => {
+                    registers[dst] = eval_unary_op(op, registers[x]);
+                }
+                Instruction::BinaryOp { left,
+                                        op,
+                                        right,
+                                        dst, } => {
+                    registers[dst] = eval_binary_op(registers[left], op, registers[right]);
+                }
+                Instruction::Call { function,
+                                    args_start,
+                                    nargs,
+                                    dst, } => {
+                    let args: SmallVec<[LiteralT; 4]> =
+                        self.call_args[args_start..args_start + nargs].iter()
+                                                                      .map(|x| registers[*x])
+                                                                      .collect();
+                    registers[dst] = (self.functions[function].1)(&args);
+                }
+                Instruction::Copy { src, dst } => {
+                    registers[dst] = registers[src];
+                }
+                Instruction::Jump { target } => {
+                    pc = target;
+                    continue;
+                }
+                Instruction::JumpIf { cond, target } => {
+                    if registers[cond].is_truthy() {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Instruction::JumpUnless { cond, target } => {
+                    if !registers[cond].is_truthy() {
+                        pc = target;
+                        continue;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        registers[self.output]
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (iinput, name) in self.input_names.iter().enumerate() {
+            writeln!(f, "r{} = {}", iinput, name)?;
+        }
+        for (iregister, value) in self.initial_registers
+                                      .iter()
+                                      .enumerate()
+                                      .skip(self.input_names.len())
+        {
+            writeln!(f, "r{} = {:?}", iregister, value)?;
+        }
+        for (pc, insn) in self.instructions.iter().enumerate() {
+            let insn_str = match *insn {
+                Instruction::UnaryOp { op, x, dst } => format!("r{} = {}(r{})", dst, op, x),
+                Instruction::BinaryOp { left,
+                                        op,
+                                        right,
+                                        dst, } => {
+                    format!("r{} = {}(r{}, r{})", dst, op, left, right)
+                }
+                Instruction::Call { function,
+                                    args_start,
+                                    nargs,
+                                    dst, } => {
+                    let args: Vec<String> =
+                        self.call_args[args_start..args_start + nargs].iter()
+                                                                      .map(|x| format!("r{}", x))
+                                                                      .collect();
+                    format!("r{} = {}({})",
+                            dst,
+                            self.functions[function].0,
+                            args.join(", "))
+                }
+                Instruction::Copy { src, dst } => format!("r{} = r{}", dst, src),
+                Instruction::Jump { target } => format!("jump {}", target),
+                Instruction::JumpIf { cond, target } => format!("jump {} if r{}", target, cond),
+                Instruction::JumpUnless { cond, target } => {
+                    format!("jump {} unless r{}", target, cond)
+                }
+            };
+            writeln!(f, "{}: {}", pc, insn_str)?;
+        }
+        write!(f, "return r{}", self.output)
+    }
+}
+
+// }}}
+
+// {{{ InputsCollector
+
+#[derive(CachedMapper)]
+struct InputsCollector {
+    inputs: BTreeSet<String>,
+    cache: HashMap<ExpressionRawPointer, bool>,
+}
+
+impl WalkMapper for InputsCollector {
+    fn map_variable(&mut self, name: String) {
+        self.inputs.insert(name);
+    }
+
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) {
+        // function names are not inputs
+        if !matches!(**call, Expression::Variable(_)) {
+            self.visit(call);
+        }
+        for param in params {
+            self.visit(param);
+        }
+    }
+}
+
+// }}}
+
+// {{{ Lowerer
+
+struct Lowerer<'a> {
+    registry: &'a FunctionRegistry,
+    input_registers: HashMap<String, usize>,
+    registers: Vec<LiteralT>,
+    instructions: Vec<Instruction>,
+    call_args: Vec<usize>,
+    functions: Vec<(String, NumericFunction)>,
+    // Expressions lowered within each of the branches currently being lowered.
+    branch_scopes: Vec<Vec<ExpressionRawPointer>>,
+    // Registers holding the results of the calls to pure functions, by the
+    // function and the registers of the arguments.
+    pure_calls: HashMap<(usize, Vec<usize>), usize>,
+    // Pure calls lowered within each of the branches currently being lowered.
+    pure_call_scopes: Vec<Vec<(usize, Vec<usize>)>>,
+    cache: HashMap<ExpressionRawPointer, usize>,
+}
+
+impl CachedMapper<ExpressionRawPointer, usize> for Lowerer<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer) -> Option<&usize> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer, value: usize) {
+        if let Some(scope) = self.branch_scopes.last_mut() {
+            scope.push(ExpressionRawPointer(key.0.clone()));
+        }
+        self.cache.insert(key, value);
+    }
+}
+
+impl Lowerer<'_> {
+    fn new_register(&mut self, initial_value: LiteralT) -> usize {
+        self.registers.push(initial_value);
+        self.registers.len() - 1
+    }
+
+    fn new_temporary(&mut self) -> usize {
+        self.new_register(LiteralT::I64(0))
+    }
+
+    /// Lowers *expr* which is only conditionally executed. Since the
+    /// instructions of a branch might be skipped, the registers computed
+    /// within it cannot be re-used outside it.
+    fn visit_in_branch(&mut self, expr: &Rc<Expression>) -> usize {
+        self.branch_scopes.push(vec![]);
+        self.pure_call_scopes.push(vec![]);
+        let result = self.visit(expr);
+        for key in self.branch_scopes.pop().unwrap() {
+            self.cache.remove(&key);
+        }
+        for key in self.pure_call_scopes.pop().unwrap() {
+            self.pure_calls.remove(&key);
+        }
+        result
+    }
+
+    fn patch_jump(&mut self, ijump: usize) {
+        let new_target = self.instructions.len();
+        match &mut self.instructions[ijump] {
+            Instruction::Jump { target }
+            | Instruction::JumpIf { target, .. }
+            | Instruction::JumpUnless { target, .. } => *target = new_target,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl FoldMapper for Lowerer<'_> {
+    type Output = usize;
+
+    fn map_scalar(&mut self, value: &LiteralT) -> Self::Output {
+        self.new_register(promote(*value))
+    }
+    fn map_variable(&mut self, name: String) -> Self::Output {
+        match self.input_registers.get(&name) {
+            Some(x) => *x,
+            None => panic!("Variable '{}' is not an input of the program.", name),
+        }
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Self::Output {
+        let x = self.visit(x);
+        let dst = self.new_temporary();
+        self.instructions.push(Instruction::UnaryOp { op, x, dst });
+        dst
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
+                     -> Self::Output {
+        let left = self.visit(left);
+        let dst = self.new_temporary();
+        match op {
+            // logical operators short-circuit
+            BinaryOpType::LogicalAnd | BinaryOpType::LogicalOr => {
+                self.instructions.push(Instruction::BinaryOp { left,
+                                                               op,
+                                                               right: left,
+                                                               dst });
+                let ijump = self.instructions.len();
+                self.instructions.push(match op {
+                                           BinaryOpType::LogicalAnd => {
+                                               Instruction::JumpUnless { cond: left,
+                                                                         target: 0 }
+                                           }
+                                           _ => Instruction::JumpIf { cond: left,
+                                                                      target: 0 },
+                                       });
+                let right = self.visit_in_branch(right);
+                self.instructions.push(Instruction::BinaryOp { left,
+                                                               op,
+                                                               right,
+                                                               dst });
+                self.patch_jump(ijump);
+            }
+            _ => {
+                let right = self.visit(right);
+                self.instructions.push(Instruction::BinaryOp { left,
+                                                               op,
+                                                               right,
+                                                               dst });
+            }
+        }
+        dst
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Self::Output {
+        let function = self.registry.expect_callee(call, params);
+        let (name, func, is_pure) = (function.name.clone(), function.eval, function.is_pure);
+        let args: Vec<usize> = params.iter().map(|x| self.visit(x)).collect();
+        let function = match self.functions.iter().position(|(x, _)| *x == name) {
+            Some(x) => x,
+            None => {
+                self.functions.push((name, func));
+                self.functions.len() - 1
+            }
+        };
+        // calls to a pure function with the same arguments are interchangeable
+        let key = (function, args);
+        if is_pure {
+            if let Some(dst) = self.pure_calls.get(&key) {
+                return *dst;
+            }
+        }
+        let args_start = self.call_args.len();
+        self.call_args.extend(key.1.iter().copied());
+        let dst = self.new_temporary();
+        self.instructions.push(Instruction::Call { function,
+                                                   args_start,
+                                                   nargs: params.len(),
+                                                   dst });
+        if is_pure {
+            if let Some(scope) = self.pure_call_scopes.last_mut() {
+                scope.push(key.clone());
+            }
+            self.pure_calls.insert(key, dst);
+        }
+        dst
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, _indices: &SmallVecExprT) -> Self::Output {
+        panic!("Cannot compile the subscript of '{}': subscripts are not supported.",
+               agg)
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
+              -> Self::Output {
+        let cond = self.visit(cond);
+        let dst = self.new_temporary();
+
+        let ijump_to_else = self.instructions.len();
+        self.instructions
+            .push(Instruction::JumpUnless { cond, target: 0 });
+        let then = self.visit_in_branch(then);
+        self.instructions.push(Instruction::Copy { src: then, dst });
+        let ijump_to_end = self.instructions.len();
+        self.instructions.push(Instruction::Jump { target: 0 });
+
+        self.patch_jump(ijump_to_else);
+        let else_ = self.visit_in_branch(else_);
+        self.instructions
+            .push(Instruction::Copy { src: else_, dst });
+        self.patch_jump(ijump_to_end);
+        dst
+    }
+}
+
+// }}}
+
+/// Compiles `expr` into a [`Program`] whose inputs are the variables of
+/// `expr`, in sorted order, excluding the names of the called functions.
+///
+/// The DAG of `expr` is lowered once with every shared sub-expression computed
+/// once, except for the sub-expressions appearing only in conditionally
+/// evaluated operands, i.e. branches of an [`Expression::If`] and the right
+/// operands of logical operators. Calls to
+/// [pure](crate::functions::FunctionInfo::is_pure) functions with the same
+/// arguments are computed once, even if they are different nodes of the DAG.
+/// Calls are limited to the functions in [`default_registry`] and subscripts
+/// are not supported.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{compile, parse, LiteralT};
+///
+/// let program = compile(&parse("2*x + sin(y)"));
+/// assert_eq!(program.input_names(), ["x", "y"]);
+/// let mut registers = program.new_registers();
+/// assert_eq!(program.eval(&[LiteralT::F64(1.0), LiteralT::F64(0.0)], &mut registers),
+///            LiteralT::F64(2.0));
+/// ```
+pub fn compile(expr: &Rc<Expression>) -> Program {
+    let mut collector = InputsCollector { inputs: BTreeSet::new(),
+                                          cache: HashMap::new() };
+    collector.visit(expr);
+    let input_names: Vec<String> = collector.inputs.into_iter().collect();
+    compile_with_inputs(expr, &input_names)
+}
+
+/// Compiles `expr` into a [`Program`] with `input_names` as the program's
+/// inputs. Also see [`compile`].
+pub fn compile_with_inputs<T: ToString>(expr: &Rc<Expression>, input_names: &[T]) -> Program {
+    compile_with_registry(expr, input_names, default_registry())
+}
+
+/// Compiles `expr` into a [`Program`] with `input_names` as the program's
+/// inputs and the calls resolved to the functions in `registry`. Also see
+/// [`compile`].
+pub fn compile_with_registry<T: ToString>(expr: &Rc<Expression>, input_names: &[T],
+                                          registry: &FunctionRegistry)
+                                          -> Program {
+    let input_names: Vec<String> = input_names.iter().map(|x| x.to_string()).collect();
+    let mut lowerer = Lowerer { registry,
+                                input_registers: HashMap::new(),
+                                registers: vec![],
+                                instructions: vec![],
+                                call_args: vec![],
+                                functions: vec![],
+                                branch_scopes: vec![],
+                                pure_calls: HashMap::new(),
+                                pure_call_scopes: vec![],
+                                cache: HashMap::new() };
+    for name in input_names.iter() {
+        let register = lowerer.new_temporary();
+        if lowerer.input_registers
+                  .insert(name.clone(), register)
+                  .is_some()
+        {
+            panic!("Input '{}' repeated.", name);
+        }
+    }
+    let output = lowerer.visit(expr);
+
+    Program { input_names,
+              instructions: lowerer.instructions,
+              call_args: lowerer.call_args,
+              functions: lowerer.functions,
+              output,
+              initial_registers: lowerer.registers }
+}
+
+// vim: fdm=marker
@@ -21,14 +21,18 @@
 use crate::mapper_impls::hasher::{get_hasher, HashCacher};
 use crate::mappers::identity::IdentityMapperWithCustomCacheKey;
 use crate::mappers::CachedMapper;
+use crate::utils::{get_children, with_children};
 use crate::{CachedMapper, Expression};
 use hashbrown::HashMap;
+use std::cell::RefCell;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
 // {{{ HashedExpression
 
-struct HashedExpression {
+/// Cache key of a [`Deduplicator`]: an expression along with its structural
+/// hash.
+pub struct HashedExpression {
     expr: Rc<Expression>,
     hashval: u64,
 }
@@ -49,25 +53,85 @@ impl Eq for HashedExpression {}
 
 // }}}
 
-#[derive(CachedMapper)]
-struct Deduplicator {
-    hasher: HashCacher,
+/// Maps structurally equal sub-expressions to a single node. A deduplicator
+/// can be applied to several root expressions, in which case the nodes of
+/// every returned root are also shared with those returned earlier.
+///
+/// # Example
+/// ```rust
+/// use std::rc::Rc;
+/// use symoxide::{parse, Deduplicator};
+///
+/// let mut deduplicator = Deduplicator::new();
+/// let expr1 = deduplicator.deduplicate(&parse("(x + y) * 2"));
+/// let expr2 = deduplicator.deduplicate(&parse("x + y"));
+/// let expr3 = deduplicator.deduplicate(&parse("x + y"));
+/// assert!(Rc::ptr_eq(&expr2, &expr3));
+/// assert_eq!(expr1, parse("(x + y) * 2"));
+/// ```
+#[derive(CachedMapper, Default)]
+pub struct Deduplicator {
+    hasher: RefCell<HashCacher>,
     cache: HashMap<HashedExpression, Rc<Expression>>,
 }
 
+impl Deduplicator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a deduplicator that reuses the hashes memoized in *hasher*.
+    pub fn with_hasher(hasher: HashCacher) -> Self {
+        Deduplicator { hasher: RefCell::new(hasher),
+                       cache: HashMap::new() }
+    }
+
+    /// Returns *expr* with its structurally equal sub-expressions mapped to
+    /// the same node.
+    pub fn deduplicate(&mut self, expr: &Rc<Expression>) -> Rc<Expression> {
+        self.visit(expr.clone())
+    }
+}
+
 impl IdentityMapperWithCustomCacheKey for Deduplicator {
     type CacheKey = HashedExpression;
 
     fn get_cache_key(&self, expr: Rc<Expression>) -> HashedExpression {
         HashedExpression { expr: expr.clone(),
-                           hashval: self.hasher.get(expr.clone()) }
+                           hashval: self.hasher.borrow_mut().get(expr) }
+    }
+
+    // Overridden to return the first visited node of every class of
+    // structurally equal nodes, rather than a rebuilt copy of it.
+    fn visit(&mut self, expr: Rc<Expression>) -> Rc<Expression> {
+        let cache_key = self.get_cache_key(expr.clone());
+        match self.query_cache(&cache_key) {
+            Some(x) => x.clone(),
+            None => {
+                let children = get_children(&expr).into_iter()
+                                                  .map(|child| self.visit(child))
+                                                  .collect();
+                let result = with_children(&expr, children);
+                self.add_to_cache(cache_key, result.clone());
+                result
+            }
+        }
     }
 }
 
-pub fn deduplicate_nodes(expr: &Expression) -> Rc<Expression> {
-    let rc_expr = Rc::new(expr.clone());
-    let hasher = get_hasher(rc_expr.clone());
-    let mut mapper = Deduplicator { hasher: hasher,
-                                    cache: HashMap::new() };
-    mapper.visit(rc_expr.clone())
+/// Returns *expr* with its structurally equal sub-expressions mapped to the
+/// same node. Nodes without duplicates are returned as is, so that the result
+/// is pointer-equal to *expr* if it contains no duplicates.
+pub fn deduplicate_nodes(expr: &Rc<Expression>) -> Rc<Expression> {
+    let hasher = get_hasher(expr.clone());
+    Deduplicator::with_hasher(hasher).deduplicate(expr)
+}
+
+/// Returns *exprs* with structurally equal sub-expressions mapped to the same
+/// node, within as well as across the roots.
+pub fn deduplicate_many(exprs: &[Rc<Expression>]) -> Vec<Rc<Expression>> {
+    let mut deduplicator = Deduplicator::new();
+    exprs.iter()
+         .map(|expr| deduplicator.deduplicate(expr))
+         .collect()
 }
@@ -1,14 +1,14 @@
 use crate::mappers::combine::CombineMapper;
+use crate::mappers::mapper::{CachePolicy, Mapper, MapperCache};
 use crate::mappers::CachedMapper;
-use crate::primitives::Expression;
-use crate::utils::ExpressionRawPointer;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::{get_children, get_topological_order, ExpressionRawPointer};
 use crate::CachedMapper;
-use crate::LiteralT;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-#[derive(CachedMapper)]
-struct DependenciesGetter {
+#[derive(CachedMapper, Default)]
+pub(crate) struct DependenciesGetter {
     cache: HashMap<ExpressionRawPointer, Rc<HashSet<String>>>,
 }
 
@@ -34,9 +34,9 @@ impl CombineMapper for DependenciesGetter {
     }
 }
 
-pub fn get_dependencies(expr: &Expression) -> HashSet<String> {
+pub fn get_dependencies(expr: &Rc<Expression>) -> HashSet<String> {
     let mut mapper = DependenciesGetter { cache: HashMap::new() };
-    let rc_deps = mapper.visit(&Rc::new(expr.clone()));
+    let rc_deps = mapper.visit(expr);
     let mut result: HashSet<String> = HashSet::new();
     for k in rc_deps.iter() {
         result.insert(k.clone());
@@ -44,3 +44,206 @@ pub fn get_dependencies(expr: &Expression) -> HashSet<String> {
 
     return result;
 }
+
+/// Returns the variables each of *exprs* depends on. Sub-expressions shared
+/// between the roots are analysed once.
+pub fn get_dependencies_many(exprs: &[Rc<Expression>]) -> Vec<HashSet<String>> {
+    let mut mapper = DependenciesGetter { cache: HashMap::new() };
+    exprs.iter()
+         .map(|expr| mapper.visit(expr).iter().cloned().collect())
+         .collect()
+}
+
+// {{{ DependencyInfo
+
+/// An aggregate that is subscripted in an expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubscriptedAggregate {
+    pub name: String,
+    /// Distinct numbers of indices the aggregate is subscripted with, in order
+    /// of first occurrence.
+    pub num_indices: Vec<usize>,
+}
+
+/// Names an expression depends on, classified by the role they play. Every
+/// list is ordered by first occurrence in a left-to-right, depth-first
+/// traversal of the expression.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyInfo {
+    /// Names of the called functions.
+    pub functions: Vec<String>,
+    /// Names of the subscripted aggregates.
+    pub aggregates: Vec<SubscriptedAggregate>,
+    /// Variables used as values, i.e. neither called nor subscripted.
+    pub scalars: Vec<String>,
+    /// Variables used as values within an index expression of a subscript.
+    /// These also appear in [`DependencyInfo::scalars`].
+    pub index_variables: Vec<String>,
+    /// Number of times each name occurs in the expression, counting a shared
+    /// sub-expression once per reference to it. Only populated by
+    /// [`DependencyInfo::compute_with_counts`].
+    pub occurrences: Option<HashMap<String, u64>>,
+}
+
+fn push_unique(names: &mut Vec<String>, seen: &mut HashSet<String>, name: &str) {
+    if !seen.contains(name) {
+        seen.insert(name.to_string());
+        names.push(name.to_string());
+    }
+}
+
+/// Collects a [`DependencyInfo`]. The context is *true* within index
+/// expressions, and a node reached both within and outside of an index
+/// expression is visited once for each.
+struct RoleCollector {
+    info: DependencyInfo,
+    seen_functions: HashSet<String>,
+    seen_scalars: HashSet<String>,
+    seen_index_variables: HashSet<String>,
+    cache: MapperCache<bool, ()>,
+}
+
+impl RoleCollector {
+    fn add_aggregate(&mut self, name: &str, num_indices: usize) {
+        match self.info
+                  .aggregates
+                  .iter_mut()
+                  .find(|aggregate| aggregate.name == name)
+        {
+            Some(aggregate) => {
+                if !aggregate.num_indices.contains(&num_indices) {
+                    aggregate.num_indices.push(num_indices);
+                }
+            }
+            None => self.info
+                        .aggregates
+                        .push(SubscriptedAggregate { name: name.to_string(),
+                                                     num_indices: vec![num_indices] }),
+        }
+    }
+}
+
+impl Mapper for RoleCollector {
+    type Output = ();
+    type Context = bool;
+    type ContextKey = bool;
+
+    fn cache(&mut self) -> &mut MapperCache<bool, ()> {
+        &mut self.cache
+    }
+
+    fn get_context_key(&self, in_index: &bool) -> bool {
+        *in_index
+    }
+
+    fn map_scalar(&mut self, _value: &LiteralT, _in_index: &mut bool) {}
+
+    fn map_variable(&mut self, name: String, in_index: &mut bool) {
+        push_unique(&mut self.info.scalars, &mut self.seen_scalars, &name);
+        if *in_index {
+            push_unique(&mut self.info.index_variables,
+                        &mut self.seen_index_variables,
+                        &name);
+        }
+    }
+
+    fn map_unary_op(&mut self, _op: UnaryOpType, x: &Rc<Expression>, in_index: &mut bool) {
+        self.visit(x, in_index)
+    }
+
+    fn map_binary_op(&mut self, left: &Rc<Expression>, _op: BinaryOpType,
+                     right: &Rc<Expression>, in_index: &mut bool) {
+        self.visit(left, in_index);
+        self.visit(right, in_index);
+    }
+
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT, in_index: &mut bool) {
+        match &**call {
+            Expression::Variable(name) => {
+                push_unique(&mut self.info.functions, &mut self.seen_functions, name)
+            }
+            _ => self.visit(call, in_index),
+        }
+        for param in params {
+            self.visit(param, in_index);
+        }
+    }
+
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT,
+                     in_index: &mut bool) {
+        match &**agg {
+            Expression::Variable(name) => self.add_aggregate(name, indices.len()),
+            _ => self.visit(agg, in_index),
+        }
+        let mut in_subscript_index = true;
+        for idx in indices {
+            self.visit(idx, &mut in_subscript_index);
+        }
+    }
+
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>,
+              in_index: &mut bool) {
+        self.visit(cond, in_index);
+        self.visit(then, in_index);
+        self.visit(else_, in_index);
+    }
+}
+
+/// Returns the number of references to every variable node in *expr*, i.e.
+/// the number of paths from the root to the node.
+fn get_occurrences(expr: &Rc<Expression>) -> HashMap<String, u64> {
+    let mut num_paths: HashMap<ExpressionRawPointer, u64> = HashMap::new();
+    num_paths.insert(ExpressionRawPointer(expr.clone()), 1);
+    let mut occurrences = HashMap::new();
+
+    for node in get_topological_order(expr).into_iter().rev() {
+        let node_num_paths = num_paths[&ExpressionRawPointer(node.clone())];
+        if let Expression::Variable(name) = &*node {
+            let count = occurrences.entry(name.clone()).or_insert(0u64);
+            *count = count.saturating_add(node_num_paths);
+        }
+        for child in get_children(&node) {
+            let child_num_paths = num_paths.entry(ExpressionRawPointer(child)).or_insert(0);
+            *child_num_paths = child_num_paths.saturating_add(node_num_paths);
+        }
+    }
+
+    occurrences
+}
+
+impl DependencyInfo {
+    /// Returns the names *expr* depends on, classified by their roles.
+    ///
+    /// # Example
+    /// ```rust
+    /// use symoxide::{parse, DependencyInfo};
+    ///
+    /// let info = DependencyInfo::compute(&parse("2*foo(bar, baz[1.0, quux])"));
+    /// assert_eq!(info.functions, vec!["foo"]);
+    /// assert_eq!(info.aggregates[0].name, "baz");
+    /// assert_eq!(info.aggregates[0].num_indices, vec![2]);
+    /// assert_eq!(info.scalars, vec!["bar", "quux"]);
+    /// assert_eq!(info.index_variables, vec!["quux"]);
+    /// ```
+    pub fn compute(expr: &Rc<Expression>) -> DependencyInfo {
+        let mut collector = RoleCollector { info: DependencyInfo::default(),
+                                            seen_functions: HashSet::new(),
+                                            seen_scalars: HashSet::new(),
+                                            seen_index_variables: HashSet::new(),
+                                            cache:
+                                                MapperCache::new(CachePolicy::ByPointerAndContext) };
+        collector.visit(expr, &mut false);
+        collector.info
+    }
+
+    /// Like [`DependencyInfo::compute`], but also populates
+    /// [`DependencyInfo::occurrences`].
+    pub fn compute_with_counts(expr: &Rc<Expression>) -> DependencyInfo {
+        DependencyInfo { occurrences: Some(get_occurrences(expr)),
+                         ..DependencyInfo::compute(expr) }
+    }
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -0,0 +1,396 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Structural differences between expressions. See [`diff`] and
+//! [`tree_edit_distance`].
+
+use crate::mapper_impls::equality::EqualityMapper;
+use crate::mapper_impls::pretty_printer::{pretty_print, PrettyOptions};
+use crate::path::ExprPath;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, UnaryOpType};
+use crate::utils::get_children;
+use hashbrown::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// {{{ diff
+
+/// A pair of differing sub-trees found by [`diff`].
+#[derive(Clone)]
+pub struct Difference {
+    /// Position of the sub-trees in both expressions.
+    pub path: ExprPath,
+    pub old: Rc<Expression>,
+    pub new: Rc<Expression>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.path, self.old, self.new)
+    }
+}
+
+/// Options for [`ExprDiff::to_unified`].
+#[derive(Copy, Clone, Debug)]
+pub struct DiffOptions {
+    /// Options with which both expressions are pretty-printed before being
+    /// compared line by line.
+    pub pretty: PrettyOptions,
+    /// Number of unchanged lines printed around every change.
+    pub context: usize,
+    /// If *true*, the diff is coloured with ANSI escape sequences.
+    pub color: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { pretty: PrettyOptions::default(),
+                      context: 3,
+                      color: false }
+    }
+}
+
+/// The structural differences between two expressions. See [`diff`].
+#[derive(Clone)]
+pub struct ExprDiff {
+    pub old: Rc<Expression>,
+    pub new: Rc<Expression>,
+    /// The differing sub-trees, in pre-order.
+    pub differences: Vec<Difference>,
+}
+
+impl ExprDiff {
+    /// Returns *true* if the expressions are structurally equal.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// Returns a unified diff of the pretty-printed expressions. Returns an
+    /// empty string if the printed expressions are equal.
+    pub fn to_unified(&self, options: &DiffOptions) -> String {
+        let old = pretty_print(&self.old, &options.pretty);
+        let new = pretty_print(&self.new, &options.pretty);
+        get_unified_diff(&old.lines().collect::<Vec<_>>(),
+                         &new.lines().collect::<Vec<_>>(),
+                         options)
+    }
+}
+
+impl fmt::Display for ExprDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_unified(&DiffOptions::default()))
+    }
+}
+
+/// Returns *true* if *expr1* and *expr2* differ at most in their operands.
+fn have_same_head(expr1: &Expression, expr2: &Expression) -> bool {
+    match (expr1, expr2) {
+        (Expression::UnaryOp(op1, _), Expression::UnaryOp(op2, _)) => op1 == op2,
+        (Expression::BinaryOp(_, op1, _), Expression::BinaryOp(_, op2, _)) => op1 == op2,
+        (Expression::Call(_, params1), Expression::Call(_, params2)) => {
+            params1.len() == params2.len()
+        }
+        (Expression::Subscript(_, indices1), Expression::Subscript(_, indices2)) => {
+            indices1.len() == indices2.len()
+        }
+        (Expression::If(..), Expression::If(..)) => true,
+        _ => false,
+    }
+}
+
+/// Returns the minimal set of differing sub-trees of *old* and *new*. Both
+/// expressions are walked in parallel: nodes that only differ in their
+/// operands are recursed into, and any other pair of structurally unequal
+/// nodes is reported as a [`Difference`].
+///
+/// # Example
+/// ```rust
+/// use symoxide::{diff, parse};
+///
+/// let d = diff(&parse("f(x, y + 1) * z"), &parse("f(x, y - 1) * w"));
+/// let differences: Vec<String> = d.differences.iter().map(|x| x.to_string()).collect();
+/// assert_eq!(differences, vec!["/0/2: y + 1 -> y - 1", "/1: z -> w"]);
+/// assert_eq!(d.to_string(),
+///            "--- old\n+++ new\n@@ -1 +1 @@\n-f(x, y + 1) * z\n+f(x, y - 1) * w\n");
+/// ```
+pub fn diff(old: &Rc<Expression>, new: &Rc<Expression>) -> ExprDiff {
+    fn rec(old: &Rc<Expression>, new: &Rc<Expression>, path: &mut Vec<usize>,
+           equality_mapper: &mut EqualityMapper, differences: &mut Vec<Difference>) {
+        if equality_mapper.visit(old.clone(), new.clone()) {
+            return;
+        }
+        if have_same_head(old, new) {
+            for (index, (child1, child2)) in get_children(old).iter()
+                                                              .zip(get_children(new).iter())
+                                                              .enumerate()
+            {
+                path.push(index);
+                rec(child1, child2, path, equality_mapper, differences);
+                path.pop();
+            }
+        } else {
+            differences.push(Difference { path: ExprPath::from(path.clone()),
+                                          old: old.clone(),
+                                          new: new.clone() });
+        }
+    }
+
+    let mut differences = vec![];
+    rec(old,
+        new,
+        &mut vec![],
+        &mut EqualityMapper::new(),
+        &mut differences);
+    ExprDiff { old: old.clone(),
+               new: new.clone(),
+               differences }
+}
+
+// }}}
+
+// {{{ unified text diff
+
+#[derive(Copy, Clone, PartialEq)]
+enum LineOp {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// Returns the edit script turning *old* into *new*, as triples of the
+/// operation and the positions in *old* and *new* before the operation.
+fn get_line_edits(old: &[&str], new: &[&str]) -> Vec<(LineOp, usize, usize)> {
+    // lcs[i][j]: length of the longest common subsequence of old[i..], new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((LineOp::Keep, i, j));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push((LineOp::Remove, i, j));
+            i += 1;
+        } else {
+            edits.push((LineOp::Add, i, j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// Formats a hunk range as `start[,len]`, following GNU diff.
+fn format_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn get_unified_diff(old: &[&str], new: &[&str], options: &DiffOptions) -> String {
+    let paint = |code: &str, line: String| {
+        if options.color {
+            format!("\x1b[{}m{}\x1b[0m\n", code, line)
+        } else {
+            format!("{}\n", line)
+        }
+    };
+
+    let edits = get_line_edits(old, new);
+    let changes: Vec<usize> = (0..edits.len()).filter(|&i| edits[i].0 != LineOp::Keep)
+                                              .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // group the changes closer than 2*context lines into the same hunk
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for &i in &changes {
+        match hunks.last_mut() {
+            Some((_, end)) if i - *end <= 2 * options.context + 1 => *end = i,
+            _ => hunks.push((i, i)),
+        }
+    }
+
+    let mut result = paint("1", "--- old".to_string()) + &paint("1", "+++ new".to_string());
+    for (first, last) in hunks {
+        let begin = first.saturating_sub(options.context);
+        let end = (last + options.context + 1).min(edits.len());
+        let hunk = &edits[begin..end];
+        let old_len = hunk.iter().filter(|(op, ..)| *op != LineOp::Add).count();
+        let new_len = hunk.iter().filter(|(op, ..)| *op != LineOp::Remove).count();
+        let (_, old_start, new_start) = hunk[0];
+        result += &paint("36",
+                         format!("@@ -{} +{} @@",
+                                 format_range(old_start, old_len),
+                                 format_range(new_start, new_len)));
+        for &(op, i, j) in hunk {
+            result += &match op {
+                LineOp::Keep => paint("0", format!(" {}", old[i])),
+                LineOp::Remove => paint("31", format!("-{}", old[i])),
+                LineOp::Add => paint("32", format!("+{}", new[j])),
+            };
+        }
+    }
+    result
+}
+
+// }}}
+
+// {{{ tree edit distance
+
+/// The part of a node compared by [`tree_edit_distance`], i.e. everything
+/// but its operands.
+#[derive(PartialEq)]
+enum Label {
+    Scalar(LiteralT),
+    Variable(String),
+    UnaryOp(UnaryOpType),
+    BinaryOp(BinaryOpType),
+    Call,
+    Subscript,
+    If,
+}
+
+impl Label {
+    fn of(expr: &Expression) -> Self {
+        match expr {
+            Expression::Scalar(x) => Label::Scalar(*x),
+            Expression::Variable(name) => Label::Variable(name.clone()),
+            Expression::UnaryOp(op, _) => Label::UnaryOp(*op),
+            Expression::BinaryOp(_, op, _) => Label::BinaryOp(*op),
+            Expression::Call(..) => Label::Call,
+            Expression::Subscript(..) => Label::Subscript,
+            Expression::If(..) => Label::If,
+        }
+    }
+}
+
+/// An expression tree with its nodes numbered in post-order.
+struct PostorderTree {
+    labels: Vec<Label>,
+    /// Index of the leftmost leaf descendant of every node.
+    leftmost: Vec<usize>,
+    /// The root and the nodes that are not the leftmost child of their parent,
+    /// in increasing order.
+    keyroots: Vec<usize>,
+}
+
+impl PostorderTree {
+    fn new(expr: &Expression) -> Self {
+        fn rec(expr: &Expression, labels: &mut Vec<Label>, leftmost: &mut Vec<usize>) -> usize {
+            let mut first_leaf = None;
+            for child in get_children(expr) {
+                let child_leftmost = rec(&child, labels, leftmost);
+                first_leaf.get_or_insert(child_leftmost);
+            }
+            labels.push(Label::of(expr));
+            leftmost.push(first_leaf.unwrap_or(labels.len() - 1));
+            *leftmost.last().unwrap()
+        }
+
+        let (mut labels, mut leftmost) = (vec![], vec![]);
+        rec(expr, &mut labels, &mut leftmost);
+
+        // the keyroot of a leftmost path is its highest node
+        let mut highest: HashMap<usize, usize> = HashMap::new();
+        for (node, &leaf) in leftmost.iter().enumerate() {
+            highest.insert(leaf, node);
+        }
+        let mut keyroots: Vec<usize> = highest.into_values().collect();
+        keyroots.sort_unstable();
+
+        PostorderTree { labels,
+                        leftmost,
+                        keyroots }
+    }
+}
+
+/// Returns the minimum number of node insertions, deletions and relabellings
+/// that turn the tree of *expr1* into the tree of *expr2*. Computed with the
+/// Zhang-Shasha algorithm over the tree representations, so shared
+/// sub-expressions are counted once per use.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, tree_edit_distance};
+///
+/// assert_eq!(tree_edit_distance(&parse("x + y"), &parse("x + y")), 0);
+/// // relabel y -> z
+/// assert_eq!(tree_edit_distance(&parse("x + y"), &parse("x + z")), 1);
+/// // insert the call node and its callee
+/// assert_eq!(tree_edit_distance(&parse("x + y"), &parse("x + sin(y)")), 2);
+/// ```
+pub fn tree_edit_distance(expr1: &Rc<Expression>, expr2: &Rc<Expression>) -> usize {
+    let tree1 = PostorderTree::new(expr1);
+    let tree2 = PostorderTree::new(expr2);
+    let (n1, n2) = (tree1.labels.len(), tree2.labels.len());
+    let mut tree_dist = vec![vec![0usize; n2]; n1];
+
+    for &i in &tree1.keyroots {
+        for &j in &tree2.keyroots {
+            let (l1, l2) = (tree1.leftmost[i], tree2.leftmost[j]);
+            // forest_dist[x][y]: distance between the forests l1..l1+x and
+            // l2..l2+y
+            let mut forest_dist = vec![vec![0usize; j - l2 + 2]; i - l1 + 2];
+            for (x, row) in forest_dist.iter_mut().enumerate() {
+                row[0] = x;
+            }
+            for (y, dist) in forest_dist[0].iter_mut().enumerate() {
+                *dist = y;
+            }
+            for x in 1..=(i - l1 + 1) {
+                for y in 1..=(j - l2 + 1) {
+                    let (node1, node2) = (l1 + x - 1, l2 + y - 1);
+                    let delete_or_insert =
+                        (forest_dist[x - 1][y] + 1).min(forest_dist[x][y - 1] + 1);
+                    if tree1.leftmost[node1] == l1 && tree2.leftmost[node2] == l2 {
+                        let relabel = usize::from(tree1.labels[node1] != tree2.labels[node2]);
+                        forest_dist[x][y] =
+                            delete_or_insert.min(forest_dist[x - 1][y - 1] + relabel);
+                        tree_dist[node1][node2] = forest_dist[x][y];
+                    } else {
+                        let x_prev = tree1.leftmost[node1] - l1;
+                        let y_prev = tree2.leftmost[node2] - l2;
+                        forest_dist[x][y] = delete_or_insert.min(forest_dist[x_prev][y_prev]
+                                                                 + tree_dist[node1][node2]);
+                    }
+                }
+            }
+        }
+    }
+    tree_dist[n1 - 1][n2 - 1]
+}
+
+// }}}
+
+// vim: fdm=marker
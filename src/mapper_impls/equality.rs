@@ -19,18 +19,22 @@
 // SOFTWARE.
 
 use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
-use crate::utils::ExpressionRawPointer;
+use crate::utils::{get_children, ExpressionRawPointer};
 use hashbrown::HashMap;
 use std::rc::Rc;
 
-struct EqualityMapper {
+pub(crate) struct EqualityMapper {
     cache: HashMap<(ExpressionRawPointer, ExpressionRawPointer), bool>,
 }
 
 // {{{ EqualityMapper
 
 impl EqualityMapper {
-    fn visit(&mut self, expr1: Rc<Expression>, expr2: Rc<Expression>) -> bool {
+    pub(crate) fn new() -> Self {
+        EqualityMapper { cache: HashMap::new() }
+    }
+
+    pub(crate) fn visit(&mut self, expr1: Rc<Expression>, expr2: Rc<Expression>) -> bool {
         let cache_key = (ExpressionRawPointer(expr1.clone()), ExpressionRawPointer(expr2.clone()));
         match self.cache.get(&cache_key) {
             Some(x) => *x,
@@ -143,11 +147,44 @@ impl EqualityMapper {
 
 // }}}
 
-pub fn are_structurally_equal(expr1: &Expression, expr2: &Expression) -> bool {
+/// Returns whether *expr1* and *expr2* are structurally equal.
+pub fn are_structurally_equal(expr1: &Rc<Expression>, expr2: &Rc<Expression>) -> bool {
+    EqualityMapper::new().visit(expr1.clone(), expr2.clone())
+}
+
+/// Returns whether the nodes *expr1* and *expr2* are structurally equal, for
+/// callers that only hold references to the nodes, for ex. [`PartialEq`]. Only
+/// the children of the nodes are compared via [`EqualityMapper`].
+pub(crate) fn are_nodes_structurally_equal(expr1: &Expression, expr2: &Expression) -> bool {
     if std::ptr::eq(expr1, expr2) {
-        true
-    } else {
-        let mut mapper = EqualityMapper { cache: HashMap::new() };
-        mapper.visit(Rc::new(expr1.clone()), Rc::new(expr2.clone()))
+        return true;
     }
+    let are_labels_equal = match (expr1, expr2) {
+        (Expression::Scalar(x1), Expression::Scalar(x2)) => x1 == x2,
+        (Expression::Variable(name1), Expression::Variable(name2)) => name1 == name2,
+        (Expression::UnaryOp(op1, _), Expression::UnaryOp(op2, _)) => op1 == op2,
+        (Expression::BinaryOp(_, op1, _), Expression::BinaryOp(_, op2, _)) => op1 == op2,
+        (Expression::Call(_, params1), Expression::Call(_, params2)) => {
+            params1.len() == params2.len()
+        }
+        (Expression::Subscript(_, indices1), Expression::Subscript(_, indices2)) => {
+            indices1.len() == indices2.len()
+        }
+        (Expression::If(..), Expression::If(..)) => true,
+        _ => false,
+    };
+    let mut mapper = EqualityMapper::new();
+    are_labels_equal
+    && get_children(expr1).into_iter()
+                          .zip(get_children(expr2))
+                          .all(|(child1, child2)| mapper.visit(child1, child2))
+}
+
+/// Returns whether the expressions of each pair in *pairs* are structurally
+/// equal. Comparisons of sub-expressions are memoized across the pairs.
+pub fn are_structurally_equal_many(pairs: &[(Rc<Expression>, Rc<Expression>)]) -> Vec<bool> {
+    let mut mapper = EqualityMapper::new();
+    pairs.iter()
+         .map(|(expr1, expr2)| mapper.visit(expr1.clone(), expr2.clone()))
+         .collect()
 }
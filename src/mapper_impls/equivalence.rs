@@ -0,0 +1,287 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Probabilistic equivalence testing of expressions by evaluating them at
+//! random points. See [`probably_equivalent`].
+
+use crate::mapper_impls::array_access::Interval;
+use crate::mapper_impls::dependency::DependencyInfo;
+use crate::mapper_impls::evaluate::{try_evaluate, EvaluationError};
+use crate::primitives::{Expression, LiteralT};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+// {{{ options
+
+/// The values a variable is sampled from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Domain {
+    /// Integers in the interval, sampled as [`LiteralT::I64`]s. Arithmetic on
+    /// them is exact, see [`evaluate`](crate::evaluate).
+    Integer(Interval),
+    /// Real numbers in `[lo, hi)`, sampled as [`LiteralT::F64`]s.
+    Real { lo: f64, hi: f64 },
+}
+
+/// Options for [`probably_equivalent`].
+#[derive(Clone, Debug)]
+pub struct EquivalenceOptions {
+    /// Domains of the variables, by name.
+    pub domains: HashMap<String, Domain>,
+    /// Domain of the variables missing from
+    /// [`EquivalenceOptions::domains`] that are not used within indices.
+    pub default_domain: Domain,
+    /// Domain of the variables missing from
+    /// [`EquivalenceOptions::domains`] that are used within indices, see
+    /// [`DependencyInfo::index_variables`].
+    pub default_index_domain: Domain,
+    /// Number of points at which the expressions are compared.
+    pub num_samples: usize,
+    /// Relative tolerance for comparing non-integral values.
+    pub rtol: f64,
+    /// Absolute tolerance for comparing non-integral values.
+    pub atol: f64,
+    /// Seed of the random number generator. The sampled points are a
+    /// function of the seed, the domains and the variable names only.
+    pub seed: u64,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> Self {
+        EquivalenceOptions { domains: HashMap::new(),
+                             default_domain: Domain::Real { lo: -10.0,
+                                                            hi: 10.0 },
+                             default_index_domain: Domain::Integer(Interval::new(-10, 10)),
+                             num_samples: 100,
+                             rtol: 1e-9,
+                             atol: 1e-12,
+                             seed: 0 }
+    }
+}
+
+// }}}
+
+// {{{ random number generation
+
+/// The SplitMix64 generator. Small, fast and good enough for sampling test
+/// points.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn sample(&mut self, domain: &Domain) -> LiteralT {
+        match domain {
+            Domain::Integer(interval) => {
+                let size = (interval.hi as i128 - interval.lo as i128 + 1) as u128;
+                let offset = (self.next_u64() as u128 % size) as i128;
+                LiteralT::I64((interval.lo as i128 + offset) as i64)
+            }
+            Domain::Real { lo, hi } => {
+                // 53 random bits -> [0, 1)
+                let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+                LiteralT::F64(lo + (hi - lo) * unit)
+            }
+        }
+    }
+}
+
+// }}}
+
+// {{{ EquivalenceError
+
+/// An assignment of the variables at which two expressions evaluate to
+/// different values, or at which only one of them can be evaluated. See
+/// [`probably_equivalent`].
+#[derive(Clone, Debug)]
+pub struct Counterexample {
+    pub assignment: BTreeMap<String, LiteralT>,
+    pub value1: Result<LiteralT, EvaluationError>,
+    pub value2: Result<LiteralT, EvaluationError>,
+}
+
+fn format_value(value: &Result<LiteralT, EvaluationError>) -> String {
+    match value {
+        Ok(x) => x.to_string(),
+        Err(e) => format!("<{}>", e),
+    }
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let assignment: Vec<String> = self.assignment
+                                          .iter()
+                                          .map(|(name, value)| format!("{} = {}", name, value))
+                                          .collect();
+        write!(f,
+               "{} != {} at {{{}}}",
+               format_value(&self.value1),
+               format_value(&self.value2),
+               assignment.join(", "))
+    }
+}
+
+/// The reason [`probably_equivalent`] did not find two expressions to be
+/// equivalent.
+#[derive(Clone, Debug)]
+pub enum EquivalenceError {
+    /// A point at which the expressions differ.
+    Counterexample(Counterexample),
+    /// Neither expression could be evaluated at any of the tried points, for
+    /// ex. because both subscript arrays. Holds the errors at the last point.
+    Inconclusive {
+        error1: EvaluationError,
+        error2: EvaluationError,
+    },
+}
+
+impl EquivalenceError {
+    /// Returns the counterexample, or *None* if the test was inconclusive.
+    pub fn counterexample(&self) -> Option<&Counterexample> {
+        match self {
+            EquivalenceError::Counterexample(counterexample) => Some(counterexample),
+            EquivalenceError::Inconclusive { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for EquivalenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EquivalenceError::Counterexample(counterexample) => write!(f, "{}", counterexample),
+            EquivalenceError::Inconclusive { error1, error2 } => {
+                write!(f,
+                       "Could not evaluate either expression at any of the sampled points: \
+                           {} {}",
+                       error1, error2)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EquivalenceError {}
+
+// }}}
+
+fn are_close(value1: LiteralT, value2: LiteralT, options: &EquivalenceOptions) -> bool {
+    if value1.is_integral() && value2.is_integral() {
+        return value1.as_i64() == value2.as_i64();
+    }
+    let (x1, x2) = (value1.as_f64(), value2.as_f64());
+    if x1.is_nan() || x2.is_nan() {
+        x1.is_nan() && x2.is_nan()
+    } else if x1.is_infinite() || x2.is_infinite() {
+        x1 == x2
+    } else {
+        (x1 - x2).abs() <= options.atol + options.rtol * x1.abs().max(x2.abs())
+    }
+}
+
+/// Returns `Ok(())` if *expr1* and *expr2* evaluate to the same value at
+/// `options.num_samples` random points, and the first point at which they
+/// differ otherwise. The variables of both expressions are sampled from
+/// their domains in *options*. Integral values are compared exactly, other
+/// values within the tolerances in *options*.
+///
+/// A point at which only one of the expressions can be evaluated, e.g.
+/// because of an integer division by zero, is a counterexample. Points at
+/// which neither can be evaluated are not counted as samples. Up to
+/// `10 * options.num_samples` points are tried, and if neither expression
+/// can be evaluated at any of them, [`EquivalenceError::Inconclusive`] is
+/// returned.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::mapper_impls::array_access::Interval;
+/// use symoxide::mapper_impls::equivalence::{Domain, EquivalenceError, EquivalenceOptions};
+/// use symoxide::{parse, probably_equivalent};
+///
+/// let options = EquivalenceOptions {
+///     domains: HashMap::from([("i".to_string(), Domain::Integer(Interval::new(-50, 50)))]),
+///     ..EquivalenceOptions::default()
+/// };
+/// assert!(probably_equivalent(&parse("(x + 1)**2"), &parse("x*x + 2*x + 1"), &options).is_ok());
+/// assert!(probably_equivalent(&parse("(2*i + 1) // 2"), &parse("i"), &options).is_ok());
+/// // the expressions differ whenever i % 4 == 3
+/// let error = probably_equivalent(&parse("(i + 1) % 4"), &parse("i % 4 + 1"), &options)
+///             .unwrap_err();
+/// assert_eq!(error.counterexample().unwrap().assignment["i"].as_i64() % 4, 3);
+/// // subscripts cannot be evaluated
+/// let error = probably_equivalent(&parse("a[i] + 1"), &parse("1 + a[i]"), &options)
+///             .unwrap_err();
+/// assert!(matches!(error, EquivalenceError::Inconclusive { .. }));
+/// ```
+pub fn probably_equivalent(expr1: &Rc<Expression>, expr2: &Rc<Expression>,
+                           options: &EquivalenceOptions)
+                           -> Result<(), EquivalenceError> {
+    let infos = [DependencyInfo::compute(expr1),
+                 DependencyInfo::compute(expr2)];
+    let variables: BTreeSet<&String> = infos.iter().flat_map(|info| &info.scalars).collect();
+    let index_variables: BTreeSet<&String> = infos.iter()
+                                                  .flat_map(|info| &info.index_variables)
+                                                  .collect();
+    let mut rng = SplitMix64(options.seed);
+    let mut num_samples = 0;
+    let mut last_errors = None;
+
+    for _ in 0..(10 * options.num_samples) {
+        if num_samples == options.num_samples {
+            break;
+        }
+        let assignment: BTreeMap<String, LiteralT> =
+            variables.iter()
+                     .map(|name| {
+                         let default_domain = match index_variables.contains(name) {
+                             true => &options.default_index_domain,
+                             false => &options.default_domain,
+                         };
+                         let domain = options.domains.get(*name).unwrap_or(default_domain);
+                         ((*name).clone(), rng.sample(domain))
+                     })
+                     .collect();
+        let env: HashMap<String, LiteralT> = assignment.clone().into_iter().collect();
+        match (try_evaluate(expr1, &env), try_evaluate(expr2, &env)) {
+            (Err(error1), Err(error2)) => last_errors = Some((error1, error2)),
+            (Ok(value1), Ok(value2)) if are_close(value1, value2, options) => num_samples += 1,
+            (value1, value2) => {
+                return Err(EquivalenceError::Counterexample(Counterexample { assignment,
+                                                                             value1,
+                                                                             value2 }))
+            }
+        }
+    }
+
+    match last_errors {
+        Some((error1, error2)) if num_samples == 0 => {
+            Err(EquivalenceError::Inconclusive { error1, error2 })
+        }
+        _ => Ok(()),
+    }
+}
+
+// vim: fdm=marker
@@ -0,0 +1,424 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Numerical evaluation of expressions.
+//!
+//! Integral operands are evaluated as `i64`s and floating point operands as
+//! `f64`s. Mixed operands are promoted to `f64`. Since the semantics of
+//! [`BinaryOpType`] are left to the downstream user, the evaluator picks
+//! Python's semantics: [`BinaryOpType::Divide`] is a true division,
+//! [`BinaryOpType::FloorDiv`] and [`BinaryOpType::Modulo`] round towards
+//! negative infinity, and comparisons, logical operators evaluate to `0` or
+//! `1`.
+//!
+//! [`try_evaluate`] reports the expressions that cannot be evaluated, e.g. an
+//! integer division by zero, as an [`EvaluationError`], while [`evaluate`]
+//! panics on them.
+
+use crate::functions::{default_registry, CallError, FunctionRegistry};
+use crate::mappers::fold::FoldMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use smallvec::SmallVec;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// {{{ EvaluationError
+
+/// The reason an expression cannot be evaluated. See [`try_evaluate`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvaluationError {
+    /// An integral [`BinaryOpType::FloorDiv`] or [`BinaryOpType::Modulo`]
+    /// with a zero divisor.
+    DivisionByZero { op: BinaryOpType, left: i64 },
+    /// A bitwise operation on a floating point value.
+    UndefinedUnaryOp { op: UnaryOpType, x: LiteralT },
+    /// A bitwise operation on floating point operands.
+    UndefinedBinaryOp {
+        op: BinaryOpType,
+        left: LiteralT,
+        right: LiteralT,
+    },
+    /// A variable missing from the evaluation environment.
+    UnboundVariable(String),
+    /// A subscript of the aggregate, subscripts are not supported.
+    Subscript(String),
+    /// A call to a function that is not in the registry.
+    InvalidCall(CallError),
+}
+
+impl fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvaluationError::DivisionByZero { op, left } => {
+                let symbol = if *op == BinaryOpType::FloorDiv {
+                    "//"
+                } else {
+                    "%"
+                };
+                write!(f, "Integer division by zero in '{} {} 0'.", left, symbol)
+            }
+            EvaluationError::UndefinedUnaryOp { op, x } => {
+                write!(f,
+                       "{} is not defined for the floating point value '{}'.",
+                       op, x)
+            }
+            EvaluationError::UndefinedBinaryOp { op, left, right } => {
+                write!(f,
+                       "{} is not defined for the floating point operands '{}', '{}'.",
+                       op, left, right)
+            }
+            EvaluationError::UnboundVariable(name) => {
+                write!(f,
+                       "Variable '{}' not found in the evaluation environment.",
+                       name)
+            }
+            EvaluationError::Subscript(agg) => {
+                write!(f,
+                       "Cannot evaluate the subscript of '{}': subscripts are not supported.",
+                       agg)
+            }
+            EvaluationError::InvalidCall(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for EvaluationError {}
+
+// }}}
+
+// {{{ scalar arithmetic
+
+pub(crate) fn truth(x: bool) -> LiteralT {
+    LiteralT::I64(x as i64)
+}
+
+/// Returns *x* as an `i64` if it is integral and as an `f64` otherwise, i.e.
+/// in the data-type of the results of [`eval_unary_op`] and
+/// [`eval_binary_op`].
+pub(crate) fn promote(x: LiteralT) -> LiteralT {
+    if x.is_integral() {
+        LiteralT::I64(x.as_i64())
+    } else {
+        LiteralT::F64(x.as_f64())
+    }
+}
+
+/// Returns the result of applying `op` on `x`.
+///
+/// # Panics
+///
+/// If `op` is not defined for `x`, see [`try_eval_unary_op`].
+pub fn eval_unary_op(op: UnaryOpType, x: LiteralT) -> LiteralT {
+    match try_eval_unary_op(op, x) {
+        Ok(x) => x,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Returns the result of applying `op` on `x`, or an error if `op` is not
+/// defined for `x`.
+pub fn try_eval_unary_op(op: UnaryOpType, x: LiteralT) -> Result<LiteralT, EvaluationError> {
+    match op {
+        UnaryOpType::Minus => {
+            if x.is_integral() {
+                Ok(LiteralT::I64(x.as_i64().wrapping_neg()))
+            } else {
+                Ok(LiteralT::F64(-x.as_f64()))
+            }
+        }
+        UnaryOpType::BitwiseNot => {
+            if x.is_integral() {
+                Ok(LiteralT::I64(!x.as_i64()))
+            } else {
+                Err(EvaluationError::UndefinedUnaryOp { op, x })
+            }
+        }
+        UnaryOpType::LogicalNot => Ok(truth(!x.is_truthy())),
+    }
+}
+
+/// Returns the result of applying `op` on the operands `left` and `right`.
+///
+/// # Panics
+///
+/// If `op` is not defined for the operands, see [`try_eval_binary_op`].
+pub fn eval_binary_op(left: LiteralT, op: BinaryOpType, right: LiteralT) -> LiteralT {
+    match try_eval_binary_op(left, op, right) {
+        Ok(x) => x,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Returns the result of applying `op` on the operands `left` and `right`,
+/// or an error if `op` is not defined for them, e.g. for an integer division
+/// by zero.
+pub fn try_eval_binary_op(left: LiteralT, op: BinaryOpType, right: LiteralT)
+                          -> Result<LiteralT, EvaluationError> {
+    if left.is_integral() && right.is_integral() {
+        eval_integral_binary_op(left.as_i64(), op, right.as_i64())
+    } else {
+        eval_float_binary_op(left.as_f64(), op, right.as_f64())
+    }
+}
+
+fn eval_integral_binary_op(left: i64, op: BinaryOpType, right: i64)
+                           -> Result<LiteralT, EvaluationError> {
+    let value = match op {
+        BinaryOpType::Sum => LiteralT::I64(left.wrapping_add(right)),
+        BinaryOpType::Subtract => LiteralT::I64(left.wrapping_sub(right)),
+        BinaryOpType::Product => LiteralT::I64(left.wrapping_mul(right)),
+        BinaryOpType::Divide => LiteralT::F64(left as f64 / right as f64),
+        BinaryOpType::FloorDiv => {
+            if right == 0 {
+                return Err(EvaluationError::DivisionByZero { op, left });
+            }
+            let quotient = left.wrapping_div(right);
+            if (left.wrapping_rem(right) != 0) && ((left < 0) != (right < 0)) {
+                LiteralT::I64(quotient - 1)
+            } else {
+                LiteralT::I64(quotient)
+            }
+        }
+        BinaryOpType::Modulo => {
+            if right == 0 {
+                return Err(EvaluationError::DivisionByZero { op, left });
+            }
+            let remainder = left.wrapping_rem(right);
+            if (remainder != 0) && ((remainder < 0) != (right < 0)) {
+                LiteralT::I64(remainder + right)
+            } else {
+                LiteralT::I64(remainder)
+            }
+        }
+
+        BinaryOpType::Equal => truth(left == right),
+        BinaryOpType::NotEqual => truth(left != right),
+        BinaryOpType::Greater => truth(left > right),
+        BinaryOpType::GreaterEqual => truth(left >= right),
+        BinaryOpType::Less => truth(left < right),
+        BinaryOpType::LessEqual => truth(left <= right),
+
+        BinaryOpType::BitwiseOr => LiteralT::I64(left | right),
+        BinaryOpType::BitwiseXor => LiteralT::I64(left ^ right),
+        BinaryOpType::BitwiseAnd => LiteralT::I64(left & right),
+
+        BinaryOpType::LogicalAnd => truth((left != 0) && (right != 0)),
+        BinaryOpType::LogicalOr => truth((left != 0) || (right != 0)),
+
+        BinaryOpType::LeftShift => LiteralT::I64(left.wrapping_shl(right as u32)),
+        BinaryOpType::RightShift => LiteralT::I64(left.wrapping_shr(right as u32)),
+
+        BinaryOpType::Exponent => {
+            if right >= 0 {
+                LiteralT::I64(left.wrapping_pow(right.min(u32::MAX as i64) as u32))
+            } else {
+                LiteralT::F64((left as f64).powf(right as f64))
+            }
+        }
+    };
+    Ok(value)
+}
+
+fn eval_float_binary_op(left: f64, op: BinaryOpType, right: f64)
+                        -> Result<LiteralT, EvaluationError> {
+    let value = match op {
+        BinaryOpType::Sum => LiteralT::F64(left + right),
+        BinaryOpType::Subtract => LiteralT::F64(left - right),
+        BinaryOpType::Product => LiteralT::F64(left * right),
+        BinaryOpType::Divide => LiteralT::F64(left / right),
+        BinaryOpType::FloorDiv => LiteralT::F64((left / right).floor()),
+        BinaryOpType::Modulo => {
+            let remainder = left % right;
+            if (remainder != 0.0) && ((remainder < 0.0) != (right < 0.0)) {
+                LiteralT::F64(remainder + right)
+            } else {
+                LiteralT::F64(remainder)
+            }
+        }
+
+        BinaryOpType::Equal => truth(left == right),
+        BinaryOpType::NotEqual => truth(left != right),
+        BinaryOpType::Greater => truth(left > right),
+        BinaryOpType::GreaterEqual => truth(left >= right),
+        BinaryOpType::Less => truth(left < right),
+        BinaryOpType::LessEqual => truth(left <= right),
+
+        BinaryOpType::LogicalAnd => truth((left != 0.0) && (right != 0.0)),
+        BinaryOpType::LogicalOr => truth((left != 0.0) || (right != 0.0)),
+
+        BinaryOpType::Exponent => LiteralT::F64(left.powf(right)),
+
+        BinaryOpType::BitwiseOr
+        | BinaryOpType::BitwiseXor
+        | BinaryOpType::BitwiseAnd
+        | BinaryOpType::LeftShift
+        | BinaryOpType::RightShift => {
+            return Err(EvaluationError::UndefinedBinaryOp { op,
+                                                            left: LiteralT::F64(left),
+                                                            right: LiteralT::F64(right) })
+        }
+    };
+    Ok(value)
+}
+
+// }}}
+
+// {{{ Evaluator
+
+struct Evaluator<'a> {
+    env: &'a HashMap<String, LiteralT>,
+    registry: &'a FunctionRegistry,
+    cache: HashMap<ExpressionRawPointer, Result<LiteralT, EvaluationError>>,
+}
+
+impl CachedMapper<ExpressionRawPointer, Result<LiteralT, EvaluationError>> for Evaluator<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer)
+                   -> Option<&Result<LiteralT, EvaluationError>> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer,
+                    value: Result<LiteralT, EvaluationError>) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl FoldMapper for Evaluator<'_> {
+    type Output = Result<LiteralT, EvaluationError>;
+
+    fn map_scalar(&mut self, value: &LiteralT) -> Self::Output {
+        Ok(promote(*value))
+    }
+    fn map_variable(&mut self, name: String) -> Self::Output {
+        match self.env.get(&name) {
+            Some(x) => Ok(promote(*x)),
+            None => Err(EvaluationError::UnboundVariable(name)),
+        }
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Self::Output {
+        try_eval_unary_op(op, self.visit(x)?)
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
+                     -> Self::Output {
+        // logical operators short-circuit
+        match op {
+            BinaryOpType::LogicalAnd => {
+                let left_value = self.visit(left)?;
+                Ok(truth(left_value.is_truthy() && self.visit(right)?.is_truthy()))
+            }
+            BinaryOpType::LogicalOr => {
+                let left_value = self.visit(left)?;
+                Ok(truth(left_value.is_truthy() || self.visit(right)?.is_truthy()))
+            }
+            _ => {
+                let left_value = self.visit(left)?;
+                let right_value = self.visit(right)?;
+                try_eval_binary_op(left_value, op, right_value)
+            }
+        }
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Self::Output {
+        let function = self.registry
+                           .get_callee(call, params)
+                           .map_err(EvaluationError::InvalidCall)?;
+        let args = params.iter()
+                         .map(|x| self.visit(x))
+                         .collect::<Result<SmallVec<[LiteralT; 4]>, _>>()?;
+        Ok((function.eval)(&args))
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, _indices: &SmallVecExprT) -> Self::Output {
+        Err(EvaluationError::Subscript(agg.to_string()))
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
+              -> Self::Output {
+        if self.visit(cond)?.is_truthy() {
+            self.visit(then)
+        } else {
+            self.visit(else_)
+        }
+    }
+}
+
+// }}}
+
+/// Returns the value of `expr` with its variables substituted from `env`.
+/// Every shared sub-expression of `expr` is evaluated only once.
+///
+/// # Panics
+///
+/// If `expr` cannot be evaluated, see [`try_evaluate`].
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::{evaluate, parse, LiteralT};
+///
+/// let env = HashMap::from([("x".to_string(), LiteralT::I32(7))]);
+/// assert_eq!(evaluate(&parse("x // 2"), &env), LiteralT::I64(3));
+/// ```
+pub fn evaluate(expr: &Rc<Expression>, env: &HashMap<String, LiteralT>) -> LiteralT {
+    evaluate_with_registry(expr, env, default_registry())
+}
+
+/// Returns the value of `expr` with its variables substituted from `env` and
+/// its calls evaluated with the functions in `registry`. Also see
+/// [`evaluate`].
+pub fn evaluate_with_registry(expr: &Rc<Expression>, env: &HashMap<String, LiteralT>,
+                              registry: &FunctionRegistry)
+                              -> LiteralT {
+    match try_evaluate_with_registry(expr, env, registry) {
+        Ok(x) => x,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Same as [`evaluate`], but returns an [`EvaluationError`] if `expr` cannot
+/// be evaluated instead of panicking.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::mapper_impls::evaluate::EvaluationError;
+/// use symoxide::{parse, try_evaluate, BinaryOpType, LiteralT};
+///
+/// let env = HashMap::from([("x".to_string(), LiteralT::I32(0))]);
+/// assert_eq!(try_evaluate(&parse("x + 1"), &env), Ok(LiteralT::I64(1)));
+/// assert_eq!(try_evaluate(&parse("1 // x"), &env),
+///            Err(EvaluationError::DivisionByZero { op: BinaryOpType::FloorDiv, left: 1 }));
+/// ```
+pub fn try_evaluate(expr: &Rc<Expression>, env: &HashMap<String, LiteralT>)
+                    -> Result<LiteralT, EvaluationError> {
+    try_evaluate_with_registry(expr, env, default_registry())
+}
+
+/// Same as [`evaluate_with_registry`], but returns an [`EvaluationError`] if
+/// `expr` cannot be evaluated instead of panicking.
+pub fn try_evaluate_with_registry(expr: &Rc<Expression>, env: &HashMap<String, LiteralT>,
+                                  registry: &FunctionRegistry)
+                                  -> Result<LiteralT, EvaluationError> {
+    let mut mapper = Evaluator { env,
+                                 registry,
+                                 cache: HashMap::new() };
+    mapper.visit(expr)
+}
+
+// vim: fdm=marker
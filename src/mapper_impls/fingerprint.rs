@@ -0,0 +1,375 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Deterministic structural fingerprints of expressions, suitable as
+//! persistent cache keys.
+//!
+//! Unlike [`HashCacher`](crate::mapper_impls::hasher::HashCacher), whose values
+//! depend on the standard library's hasher and on `Display` implementations,
+//! a [`Fingerprint`] is the SHA-256 digest of the canonical encoding below and
+//! is identical across processes, platforms and compiler versions. Any change
+//! to the encoding comes with a bump of [`FINGERPRINT_VERSION`].
+//!
+//! # Canonical encoding (version 1)
+//!
+//! Every node is encoded as `[version, tag, payload...]` and its fingerprint
+//! is the SHA-256 digest of that encoding. Child nodes appear in the payload
+//! via their 32-byte fingerprints, so that each node of a DAG is hashed once.
+//! All integers are little-endian.
+//!
+//! | Node                      | Tag | Payload                                         |
+//! |---------------------------|-----|-------------------------------------------------|
+//! | `Scalar(value)`           | 1   | dtype code (`u8`), value bytes                  |
+//! | `Variable(name)`          | 2   | byte length (`u64`), UTF-8 bytes of `name`      |
+//! | `UnaryOp(op, x)`          | 3   | op code (`u8`), `x`                             |
+//! | `BinaryOp(l, op, r)`      | 4   | op code (`u8`), `l`, `r`                        |
+//! | `Call(f, params)`         | 5   | number of params (`u64`), `f`, `params...`      |
+//! | `Subscript(agg, indices)` | 6   | number of indices (`u64`), `agg`, `indices...`  |
+//! | `If(cond, then, else)`    | 7   | `cond`, `then`, `else`                          |
+//!
+//! Dtype codes are `U8: 0, U16: 1, U32: 2, U64: 3, I8: 4, I16: 5, I32: 6,
+//! I64: 7, F32: 8, F64: 9`, and a literal's value is encoded in the width of
+//! its dtype, floats via their IEEE-754 bit pattern. Consequently, literals of
+//! different types (`1_i32` vs `1_i64`) and `0.0` vs `-0.0` have distinct
+//! fingerprints. Operator codes are listed in [`get_unary_op_code`] and
+//! [`get_binary_op_code`].
+
+use crate::mappers::fold::FoldMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, DType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use crate::CachedMapper;
+use hashbrown::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Version of the canonical encoding hashed into every [`Fingerprint`].
+pub const FINGERPRINT_VERSION: u8 = 1;
+
+// {{{ SHA-256
+
+const SHA256_K: [u32; 64] =
+    [0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+     0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+     0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+     0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+     0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+     0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+     0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+     0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+     0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+     0xc67178f2];
+
+const SHA256_H0: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f,
+                             0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0)
+                        .wrapping_add(w[i - 7])
+                        .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1)
+                  .wrapping_add(ch)
+                  .wrapping_add(SHA256_K[i])
+                  .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (x, y) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *x = x.wrapping_add(y);
+    }
+}
+
+/// Returns the SHA-256 digest of *message* (FIPS 180-4).
+fn sha256(message: &[u8]) -> [u8; 32] {
+    let mut state = SHA256_H0;
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((message.len() as u64) * 8).to_be_bytes());
+    for block in padded.chunks_exact(64) {
+        sha256_compress(&mut state, block);
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+// }}}
+
+// {{{ Fingerprint
+
+/// A 256-bit structural digest of an expression. See the [module-level
+/// documentation](self) for the encoding it is computed over.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    /// Returns the lowercase hexadecimal representation of the digest.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Fingerprint({})", self.to_hex())
+    }
+}
+
+/// Returns the code of *dtype* in the canonical encoding.
+pub fn get_dtype_code(dtype: DType) -> u8 {
+    match dtype {
+        DType::U8 => 0,
+        DType::U16 => 1,
+        DType::U32 => 2,
+        DType::U64 => 3,
+        DType::I8 => 4,
+        DType::I16 => 5,
+        DType::I32 => 6,
+        DType::I64 => 7,
+        DType::F32 => 8,
+        DType::F64 => 9,
+    }
+}
+
+/// Returns the code of *op* in the canonical encoding.
+pub fn get_unary_op_code(op: UnaryOpType) -> u8 {
+    match op {
+        UnaryOpType::LogicalNot => 0,
+        UnaryOpType::BitwiseNot => 1,
+        UnaryOpType::Minus => 2,
+    }
+}
+
+/// Returns the code of *op* in the canonical encoding.
+pub fn get_binary_op_code(op: BinaryOpType) -> u8 {
+    match op {
+        BinaryOpType::Sum => 0,
+        BinaryOpType::Subtract => 1,
+        BinaryOpType::Product => 2,
+        BinaryOpType::Divide => 3,
+        BinaryOpType::FloorDiv => 4,
+        BinaryOpType::Modulo => 5,
+        BinaryOpType::Equal => 6,
+        BinaryOpType::NotEqual => 7,
+        BinaryOpType::Greater => 8,
+        BinaryOpType::GreaterEqual => 9,
+        BinaryOpType::Less => 10,
+        BinaryOpType::LessEqual => 11,
+        BinaryOpType::BitwiseOr => 12,
+        BinaryOpType::BitwiseXor => 13,
+        BinaryOpType::BitwiseAnd => 14,
+        BinaryOpType::LogicalAnd => 15,
+        BinaryOpType::LogicalOr => 16,
+        BinaryOpType::LeftShift => 17,
+        BinaryOpType::RightShift => 18,
+        BinaryOpType::Exponent => 19,
+    }
+}
+
+fn get_literal_bytes(value: &LiteralT) -> Vec<u8> {
+    match *value {
+        LiteralT::U8(x) => x.to_le_bytes().to_vec(),
+        LiteralT::U16(x) => x.to_le_bytes().to_vec(),
+        LiteralT::U32(x) => x.to_le_bytes().to_vec(),
+        LiteralT::U64(x) => x.to_le_bytes().to_vec(),
+        LiteralT::I8(x) => x.to_le_bytes().to_vec(),
+        LiteralT::I16(x) => x.to_le_bytes().to_vec(),
+        LiteralT::I32(x) => x.to_le_bytes().to_vec(),
+        LiteralT::I64(x) => x.to_le_bytes().to_vec(),
+        LiteralT::F32(x) => x.to_bits().to_le_bytes().to_vec(),
+        LiteralT::F64(x) => x.to_bits().to_le_bytes().to_vec(),
+    }
+}
+
+// }}}
+
+// {{{ Fingerprinter
+
+/// Computes [`Fingerprint`]s of expressions, memoizing the fingerprint of
+/// every visited node. Reusing a fingerprinter across expressions that share
+/// sub-expressions hashes the shared nodes only once.
+#[derive(CachedMapper, Default)]
+pub struct Fingerprinter {
+    cache: HashMap<ExpressionRawPointer, Fingerprint>,
+}
+
+impl Fingerprinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the fingerprint of *expr*.
+    pub fn fingerprint(&mut self, expr: &Rc<Expression>) -> Fingerprint {
+        self.visit(expr)
+    }
+
+    fn digest(&self, tag: u8, payload: &[u8], children: &[Fingerprint]) -> Fingerprint {
+        let mut encoding = Vec::with_capacity(2 + payload.len() + 32 * children.len());
+        encoding.push(FINGERPRINT_VERSION);
+        encoding.push(tag);
+        encoding.extend_from_slice(payload);
+        for child in children {
+            encoding.extend_from_slice(&child.0);
+        }
+        Fingerprint(sha256(&encoding))
+    }
+
+    fn digest_with_operands(&mut self, tag: u8, head: &Rc<Expression>, operands: &SmallVecExprT)
+                            -> Fingerprint {
+        let mut children = vec![self.visit(head)];
+        children.extend(operands.iter().map(|operand| self.visit(operand)));
+        self.digest(tag, &(operands.len() as u64).to_le_bytes(), &children)
+    }
+}
+
+impl FoldMapper for Fingerprinter {
+    type Output = Fingerprint;
+
+    fn map_scalar(&mut self, value: &LiteralT) -> Fingerprint {
+        let mut payload = vec![get_dtype_code(value.dtype())];
+        payload.extend(get_literal_bytes(value));
+        self.digest(1, &payload, &[])
+    }
+
+    fn map_variable(&mut self, name: String) -> Fingerprint {
+        let mut payload = (name.len() as u64).to_le_bytes().to_vec();
+        payload.extend_from_slice(name.as_bytes());
+        self.digest(2, &payload, &[])
+    }
+
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Fingerprint {
+        let x_fp = self.visit(x);
+        self.digest(3, &[get_unary_op_code(op)], &[x_fp])
+    }
+
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
+                     -> Fingerprint {
+        let left_fp = self.visit(left);
+        let right_fp = self.visit(right);
+        self.digest(4, &[get_binary_op_code(op)], &[left_fp, right_fp])
+    }
+
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Fingerprint {
+        self.digest_with_operands(5, call, params)
+    }
+
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT) -> Fingerprint {
+        self.digest_with_operands(6, agg, indices)
+    }
+
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
+              -> Fingerprint {
+        let cond_fp = self.visit(cond);
+        let then_fp = self.visit(then);
+        let else_fp = self.visit(else_);
+        self.digest(7, &[], &[cond_fp, then_fp, else_fp])
+    }
+}
+
+// }}}
+
+/// Returns the structural [`Fingerprint`] of *expr*.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{fingerprint, parse};
+///
+/// assert_eq!(fingerprint(&parse("x + 1")), fingerprint(&parse("x + 1")));
+/// assert_ne!(fingerprint(&parse("x + 1")), fingerprint(&parse("x + 1.0")));
+/// ```
+pub fn fingerprint(expr: &Rc<Expression>) -> Fingerprint {
+    Fingerprinter::new().fingerprint(expr)
+}
+
+// {{{ tests
+
+#[cfg(test)]
+mod tests {
+    use super::sha256;
+
+    fn to_hex(digest: [u8; 32]) -> String {
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        // test vectors of FIPS 180-4, along with the lengths around the block
+        // boundary at which the padding spills into a second block
+        let long_message = "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmno\
+                            ijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+        let vectors =
+            [("".to_string(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+             ("abc".to_string(),
+              "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+             ("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq".to_string(),
+              "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+             ("a".repeat(55), "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318"),
+             ("a".repeat(64), "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"),
+             (long_message.to_string(),
+              "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1"),
+             ("a".repeat(1_000_000),
+              "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0")];
+        for (message, digest) in vectors {
+            assert_eq!(to_hex(sha256(message.as_bytes())),
+                       digest,
+                       "{} bytes",
+                       message.len());
+        }
+    }
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -0,0 +1,263 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Forward-mode automatic differentiation via dual numbers.
+
+use crate::functions::{default_registry, FunctionRegistry};
+use crate::mapper_impls::evaluate::{eval_binary_op, eval_unary_op, truth};
+use crate::mappers::fold::FoldMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use smallvec::{smallvec, SmallVec};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub type TangentsT = SmallVec<[f64; 4]>;
+
+/// A value along with its directional derivatives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dual {
+    pub value: LiteralT,
+    /// `tangents[i]` is the derivative of `value` along the `i`-th direction.
+    pub tangents: TangentsT,
+}
+
+// {{{ DualEvaluator
+
+struct DualEvaluator<'a> {
+    env: &'a HashMap<String, LiteralT>,
+    seeds: &'a HashMap<String, TangentsT>,
+    registry: &'a FunctionRegistry,
+    ndirections: usize,
+    cache: HashMap<ExpressionRawPointer, Dual>,
+}
+
+impl CachedMapper<ExpressionRawPointer, Dual> for DualEvaluator<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer) -> Option<&Dual> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer, value: Dual) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl DualEvaluator<'_> {
+    fn constant(&self, value: LiteralT) -> Dual {
+        Dual { value,
+               tangents: smallvec![0.0; self.ndirections] }
+    }
+
+    /// Returns the dual with *value* as its value and `a*x.tangents +
+    /// b*y.tangents` as its tangents.
+    fn linear_combination(&self, value: LiteralT, a: f64, x: &Dual, b: f64, y: &Dual) -> Dual {
+        Dual { value,
+               tangents: x.tangents
+                          .iter()
+                          .zip(y.tangents.iter())
+                          .map(|(tx, ty)| a * tx + b * ty)
+                          .collect() }
+    }
+}
+
+impl FoldMapper for DualEvaluator<'_> {
+    type Output = Dual;
+
+    fn map_scalar(&mut self, value: &LiteralT) -> Self::Output {
+        self.constant(*value)
+    }
+    fn map_variable(&mut self, name: String) -> Self::Output {
+        let value = match self.env.get(&name) {
+            Some(x) => *x,
+            None => panic!("Variable '{}' not found in the evaluation environment.",
+                           name),
+        };
+        match self.seeds.get(&name) {
+            Some(tangents) => Dual { value,
+                                     tangents: tangents.clone() },
+            None => self.constant(value),
+        }
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Self::Output {
+        let x = self.visit(x);
+        let value = eval_unary_op(op, x.value);
+        match op {
+            UnaryOpType::Minus => Dual { value,
+                                         tangents: x.tangents.iter().map(|t| -t).collect() },
+            UnaryOpType::BitwiseNot | UnaryOpType::LogicalNot => self.constant(value),
+        }
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
+                     -> Self::Output {
+        // logical operators short-circuit
+        match op {
+            BinaryOpType::LogicalAnd => {
+                let left_value = self.visit(left).value;
+                let value = truth(left_value.is_truthy() && self.visit(right).value.is_truthy());
+                return self.constant(value);
+            }
+            BinaryOpType::LogicalOr => {
+                let left_value = self.visit(left).value;
+                let value = truth(left_value.is_truthy() || self.visit(right).value.is_truthy());
+                return self.constant(value);
+            }
+            _ => {}
+        }
+
+        let l = self.visit(left);
+        let r = self.visit(right);
+        let value = eval_binary_op(l.value, op, r.value);
+        let (lval, rval) = (l.value.as_f64(), r.value.as_f64());
+        match op {
+            BinaryOpType::Sum => self.linear_combination(value, 1.0, &l, 1.0, &r),
+            BinaryOpType::Subtract => self.linear_combination(value, 1.0, &l, -1.0, &r),
+            BinaryOpType::Product => self.linear_combination(value, rval, &l, lval, &r),
+            BinaryOpType::Divide => {
+                self.linear_combination(value, 1.0 / rval, &l, -lval / (rval * rval), &r)
+            }
+            BinaryOpType::Modulo => {
+                // l % r = l - r*floor(l/r) with a piecewise constant floor(l/r)
+                self.linear_combination(value, 1.0, &l, -(lval / rval).floor(), &r)
+            }
+            BinaryOpType::Exponent => {
+                let dleft = rval * lval.powf(rval - 1.0);
+                if r.tangents.iter().all(|t| *t == 0.0) {
+                    // avoid log of non-positive bases when the exponent is a
+                    // constant
+                    self.linear_combination(value, dleft, &l, 0.0, &r)
+                } else {
+                    let dright = lval.powf(rval) * lval.ln();
+                    self.linear_combination(value, dleft, &l, dright, &r)
+                }
+            }
+            BinaryOpType::FloorDiv
+            | BinaryOpType::Equal
+            | BinaryOpType::NotEqual
+            | BinaryOpType::Greater
+            | BinaryOpType::GreaterEqual
+            | BinaryOpType::Less
+            | BinaryOpType::LessEqual
+            | BinaryOpType::BitwiseOr
+            | BinaryOpType::BitwiseXor
+            | BinaryOpType::BitwiseAnd
+            | BinaryOpType::LeftShift
+            | BinaryOpType::RightShift => self.constant(value),
+            BinaryOpType::LogicalAnd | BinaryOpType::LogicalOr => unreachable!(),
+        }
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Self::Output {
+        let function = self.registry.expect_callee(call, params);
+        let partials_fn = match function.partials {
+            Some(x) => x,
+            None => panic!("No derivative rule known for '{}'.", function.name),
+        };
+        let args: Vec<Dual> = params.iter().map(|x| self.visit(x)).collect();
+        let arg_values: SmallVec<[LiteralT; 4]> = args.iter().map(|x| x.value).collect();
+        let partials = partials_fn(&arg_values);
+
+        let mut tangents: TangentsT = smallvec![0.0; self.ndirections];
+        for (arg, partial) in args.iter().zip(partials.iter()) {
+            for (t, targ) in tangents.iter_mut().zip(arg.tangents.iter()) {
+                *t += partial * targ;
+            }
+        }
+        Dual { value: (function.eval)(&arg_values),
+               tangents }
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, _indices: &SmallVecExprT) -> Self::Output {
+        panic!("Cannot evaluate the subscript of '{}': subscripts are not supported.",
+               agg)
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
+              -> Self::Output {
+        if self.visit(cond).value.is_truthy() {
+            self.visit(then)
+        } else {
+            self.visit(else_)
+        }
+    }
+}
+
+// }}}
+
+/// Evaluates `expr` along with its derivatives in the directions described by
+/// `seeds`. `seeds[name][i]` is the derivative of the variable `name` along
+/// the `i`-th direction. Variables missing from `seeds` are treated as
+/// constants. Variable values are looked up from `env` and calls are
+/// differentiated with the derivative rules of [`default_registry`], as in
+/// [`evaluate`](crate::evaluate).
+///
+/// Operations that are piecewise constant in their operands, such as
+/// comparisons and [`BinaryOpType::FloorDiv`], have zero derivatives.
+pub fn evaluate_dual(expr: &Rc<Expression>, env: &HashMap<String, LiteralT>,
+                     seeds: &HashMap<String, TangentsT>)
+                     -> Dual {
+    evaluate_dual_with_registry(expr, env, seeds, default_registry())
+}
+
+/// Same as [`evaluate_dual`], but evaluates the calls along with their
+/// derivatives using the functions in `registry`.
+pub fn evaluate_dual_with_registry(expr: &Rc<Expression>, env: &HashMap<String, LiteralT>,
+                                   seeds: &HashMap<String, TangentsT>,
+                                   registry: &FunctionRegistry)
+                                   -> Dual {
+    let ndirections = match seeds.values().next() {
+        Some(x) => x.len(),
+        None => 0,
+    };
+    if let Some((name, _)) = seeds.iter().find(|(_, x)| x.len() != ndirections) {
+        panic!("Seed of '{}' does not have {} directions.",
+               name, ndirections);
+    }
+    let mut mapper = DualEvaluator { env,
+                                     seeds,
+                                     registry,
+                                     ndirections,
+                                     cache: HashMap::new() };
+    mapper.visit(expr)
+}
+
+/// Returns the value of `expr` and its gradient w.r.t. the variables `wrt`.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::{evaluate_with_gradient, parse, LiteralT};
+///
+/// let env = HashMap::from([("x".to_string(), LiteralT::F64(3.0)),
+///                          ("y".to_string(), LiteralT::F64(2.0))]);
+/// let (value, grad) = evaluate_with_gradient(&parse("x*x*y"), &env, &["x", "y"]);
+/// assert_eq!(value, LiteralT::F64(18.0));
+/// assert_eq!(grad, [12.0, 9.0]);
+/// ```
+pub fn evaluate_with_gradient<T: ToString>(expr: &Rc<Expression>,
+                                           env: &HashMap<String, LiteralT>, wrt: &[T])
+                                           -> (LiteralT, Vec<f64>) {
+    let mut seeds: HashMap<String, TangentsT> = HashMap::new();
+    for (i, name) in wrt.iter().enumerate() {
+        let mut tangents: TangentsT = smallvec![0.0; wrt.len()];
+        tangents[i] = 1.0;
+        seeds.insert(name.to_string(), tangents);
+    }
+    let result = evaluate_dual(expr, env, &seeds);
+    (result.value, result.tangents.into_vec())
+}
+
+// vim: fdm=marker
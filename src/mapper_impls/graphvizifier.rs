@@ -21,18 +21,25 @@
 use crate::mappers::fold::FoldMapper;
 use crate::mappers::CachedMapper;
 use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
-use crate::utils::ExpressionRawPointer;
+use crate::utils::{get_children, get_topological_order, ExpressionRawPointer};
 use crate::CachedMapper;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use pytools_rs::{
     make_unique_name_gen, show_dot as show_dot_code, ConvertibleToDotOutputT, UniqueNameGenerator,
 };
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::rc::Rc;
 
 #[derive(CachedMapper)]
 struct Graphvizifier {
+    options: DotOptions,
+    depths: HashMap<ExpressionRawPointer, usize>,
     vng: UniqueNameGenerator,
-    node_descrs: Vec<String>,
+    /// `(node_name, label)` of the nodes in the order they were visited.
+    node_descrs: Vec<(String, String)>,
     edge_descrs: Vec<String>,
     cache: HashMap<ExpressionRawPointer, String>,
 }
@@ -75,27 +82,46 @@ fn pprint_uop(op: &UnaryOpType) -> &str {
     }
 }
 
+impl Graphvizifier {
+    /// Visits the operand `expr`, unless it lies beyond
+    /// [`DotOptions::max_depth`] in which case a placeholder node is emitted.
+    fn visit_operand(&mut self, expr: &Rc<Expression>) -> String {
+        match self.options.max_depth {
+            Some(max_depth) if self.depths[&ExpressionRawPointer(expr.clone())] > max_depth => {
+                let node_name = self.vng.get("expr");
+                self.node_descrs
+                    .push((node_name.clone(), "...".to_string()));
+                node_name
+            }
+            _ => self.visit(expr),
+        }
+    }
+}
+
 impl FoldMapper for Graphvizifier {
     type Output = String;
 
     fn map_scalar(&mut self, value: &LiteralT) -> Self::Output {
         let node_name = self.vng.get("expr");
-        self.node_descrs
-            .push(format!("{} [label=\"{}\"]", node_name, value));
+        let label = if self.options.show_literal_types {
+            format!("{} ({:?})", value, value.dtype())
+        } else {
+            value.to_string()
+        };
+        self.node_descrs.push((node_name.clone(), label));
         node_name.to_string()
     }
     fn map_variable(&mut self, name: String) -> Self::Output {
         let node_name = self.vng.get("expr");
-        self.node_descrs
-            .push(format!("{} [label=\"{}\"]", node_name, name));
+        self.node_descrs.push((node_name.clone(), name.to_string()));
         node_name.to_string()
     }
     fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Self::Output {
         let node_name = self.vng.get("expr");
-        let x_name = self.visit(x);
+        let x_name = self.visit_operand(x);
 
         self.node_descrs
-            .push(format!("{} [label=\"{}\"]", node_name, pprint_uop(&op)));
+            .push((node_name.clone(), pprint_uop(&op).to_string()));
         self.edge_descrs
             .push(format!("{} -> {}", x_name, node_name));
         node_name.to_string()
@@ -103,11 +129,11 @@ impl FoldMapper for Graphvizifier {
     fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
                      -> Self::Output {
         let node_name = self.vng.get("expr");
-        let left_node_name = self.visit(left);
-        let right_node_name = self.visit(right);
+        let left_node_name = self.visit_operand(left);
+        let right_node_name = self.visit_operand(right);
 
         self.node_descrs
-            .push(format!("{} [label=\"{}\"]", node_name, pprint_binop(&op)));
+            .push((node_name.clone(), pprint_binop(&op).to_string()));
         self.edge_descrs
             .push(format!("{} -> {}", left_node_name, node_name));
         self.edge_descrs
@@ -116,20 +142,19 @@ impl FoldMapper for Graphvizifier {
     }
     fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Self::Output {
         let node_name = self.vng.get("expr");
-        let call_node_name = self.visit(call);
+        let call_node_name = self.visit_operand(call);
         let params_strs: Vec<String> = params.iter()
                                              .enumerate()
                                              .map(|(i, _)| format!("arg{}", i))
                                              .collect();
         let label = format!("Fn({})", params_strs.join(", "));
 
-        self.node_descrs
-            .push(format!("{} [label=\"{}\"]", node_name, label));
+        self.node_descrs.push((node_name.clone(), label));
         self.edge_descrs
             .push(format!("{} -> {} [label=\"Fn\"]", call_node_name, node_name));
 
         for (iparam, param) in params.iter().enumerate() {
-            let param_node_name = self.visit(param);
+            let param_node_name = self.visit_operand(param);
             self.edge_descrs.push(format!("{} -> {} [label=\"arg{}\"]",
                                           param_node_name, node_name, iparam));
         }
@@ -143,14 +168,13 @@ impl FoldMapper for Graphvizifier {
                                                .collect();
         let label = format!("A[{}]", indices_strs.join(", "));
 
-        self.node_descrs
-            .push(format!("{} [label=\"{}\"]", node_name, label));
-        let agg_node_name = self.visit(agg);
+        self.node_descrs.push((node_name.clone(), label));
+        let agg_node_name = self.visit_operand(agg);
         self.edge_descrs
             .push(format!("{} -> {} [label=\"A\"]", agg_node_name, node_name));
 
         for (i_idx, idx) in indices.iter().enumerate() {
-            let idx_node_name = self.visit(idx);
+            let idx_node_name = self.visit_operand(idx);
             self.edge_descrs
                 .push(format!("{} -> {} [label=\"i{}\"]", idx_node_name, node_name, i_idx));
         }
@@ -160,12 +184,12 @@ impl FoldMapper for Graphvizifier {
     fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
               -> Self::Output {
         let node_name = self.vng.get("expr");
-        let cond_node_name = self.visit(cond);
-        let then_node_name = self.visit(then);
-        let else_node_name = self.visit(else_);
+        let cond_node_name = self.visit_operand(cond);
+        let then_node_name = self.visit_operand(then);
+        let else_node_name = self.visit_operand(else_);
 
         self.node_descrs
-            .push(format!("{} [label=\"X if Y else Z\"]", node_name));
+            .push((node_name.clone(), "X if Y else Z".to_string()));
         self.edge_descrs
             .push(format!("{} -> {}", cond_node_name, node_name));
         self.edge_descrs
@@ -176,22 +200,228 @@ impl FoldMapper for Graphvizifier {
     }
 }
 
-pub fn show_dot<T: ConvertibleToDotOutputT>(expr: &Expression, output_to: T) {
-    let mut mapper = Graphvizifier { vng: make_unique_name_gen([]),
+// {{{ options
+
+/// Direction in which [`to_dot`] lays out the ranks of the graph.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RankDir {
+    /// Operands are placed above the operations consuming them.
+    TopToBottom,
+    /// Operands are placed to the left of the operations consuming them.
+    LeftToRight,
+}
+
+/// Options controlling the DOT code emitted by [`to_dot`].
+#[derive(Clone)]
+pub struct DotOptions {
+    /// Layout direction of the graph.
+    pub rankdir: RankDir,
+    /// If *true*, every sub-expression with more than one parent is drawn in a
+    /// cluster along with its operands that are not already part of another
+    /// cluster. Clusters are claimed starting from the root.
+    pub cluster_shared: bool,
+    /// If *true*, nodes are filled with a colour depending on the kind of the
+    /// expression, i.e. scalars, variables, operations, calls, subscripts and
+    /// if-then-else expressions are coloured differently.
+    pub color_by_kind: bool,
+    /// If *true*, scalar nodes are labeled with their data-type, for ex.
+    /// `2 (I32)` instead of `2`.
+    pub show_literal_types: bool,
+    /// Nodes to be highlighted. Nodes are compared by identity, see
+    /// [`ExpressionRawPointer`].
+    pub highlight: Vec<Rc<Expression>>,
+    /// If set, sub-expressions that are farther than `max_depth` edges from the
+    /// root are elided. Each elided operand is drawn as a `...` node.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        DotOptions { rankdir: RankDir::TopToBottom,
+                     cluster_shared: false,
+                     color_by_kind: false,
+                     show_literal_types: false,
+                     highlight: vec![],
+                     max_depth: None }
+    }
+}
+
+// }}}
+
+// {{{ graph analysis
+
+/// Returns the length of the shortest path from `expr` to each of its
+/// sub-expressions.
+fn get_depths(expr: &Rc<Expression>) -> HashMap<ExpressionRawPointer, usize> {
+    let mut depths = HashMap::new();
+    let mut queue = VecDeque::new();
+    depths.insert(ExpressionRawPointer(expr.clone()), 0);
+    queue.push_back((expr.clone(), 0));
+
+    while let Some((node, depth)) = queue.pop_front() {
+        for child in get_children(&node) {
+            let key = ExpressionRawPointer(child.clone());
+            if !depths.contains_key(&key) {
+                depths.insert(key, depth + 1);
+                queue.push_back((child, depth + 1));
+            }
+        }
+    }
+    depths
+}
+
+/// Returns the cluster index of every sub-expression of `expr` that belongs to
+/// a cluster of shared sub-expressions. See [`DotOptions::cluster_shared`].
+fn get_clusters(expr: &Rc<Expression>) -> HashMap<ExpressionRawPointer, usize> {
+    fn claim(expr: &Rc<Expression>, icluster: usize,
+             clusters: &mut HashMap<ExpressionRawPointer, usize>) {
+        let key = ExpressionRawPointer(expr.clone());
+        if !clusters.contains_key(&key) {
+            clusters.insert(key, icluster);
+            for child in get_children(expr) {
+                claim(&child, icluster, clusters);
+            }
+        }
+    }
+
+    let order = get_topological_order(expr);
+    let mut nparents: HashMap<ExpressionRawPointer, usize> = HashMap::new();
+    for node in order.iter() {
+        let children: HashSet<ExpressionRawPointer> = get_children(node).into_iter()
+                                                                        .map(ExpressionRawPointer)
+                                                                        .collect();
+        for child in children {
+            *nparents.entry(child).or_insert(0) += 1;
+        }
+    }
+
+    let mut clusters = HashMap::new();
+    let mut ncluster = 0;
+    for node in order.iter().rev() {
+        let key = ExpressionRawPointer(node.clone());
+        if nparents.get(&key).copied().unwrap_or(0) > 1 && !clusters.contains_key(&key) {
+            claim(node, ncluster, &mut clusters);
+            ncluster += 1;
+        }
+    }
+    clusters
+}
+
+fn get_kind_color(expr: &Expression) -> &str {
+    match expr {
+        Expression::Scalar(_) => "khaki",
+        Expression::Variable(_) => "lightblue",
+        Expression::UnaryOp(..) | Expression::BinaryOp(..) => "palegreen",
+        Expression::Call(..) => "plum",
+        Expression::Subscript(..) => "lightsalmon",
+        Expression::If(..) => "lightgrey",
+    }
+}
+
+// }}}
+
+/// Returns the [DOT](https://graphviz.org/doc/info/lang.html) code for the
+/// DAG of `expr`. Edges point from the operands to the operations consuming
+/// them. See [`DotOptions`] for the available customizations.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, to_dot, DotOptions};
+///
+/// let expr = parse("x + 1");
+/// let dot_code = to_dot(&expr, &DotOptions::default());
+/// assert!(dot_code.starts_with("digraph {"));
+/// assert!(dot_code.contains("[label=\"+\"]"));
+/// ```
+pub fn to_dot(expr: &Rc<Expression>, options: &DotOptions) -> String {
+    let mut mapper = Graphvizifier { options: options.clone(),
+                                     depths: get_depths(expr),
+                                     vng: make_unique_name_gen([]),
                                      node_descrs: vec![],
                                      edge_descrs: vec![],
                                      cache: HashMap::new() };
-    mapper.visit(&Rc::new(expr.clone()));
+    mapper.visit(expr);
+
+    let name_to_expr: HashMap<String, Rc<Expression>> =
+        mapper.cache
+              .iter()
+              .map(|(key, name)| (name.clone(), key.0.clone()))
+              .collect();
+    let highlighted: HashSet<ExpressionRawPointer> =
+        options.highlight
+               .iter()
+               .map(|x| ExpressionRawPointer(x.clone()))
+               .collect();
+    let clusters = if options.cluster_shared {
+        get_clusters(expr)
+    } else {
+        HashMap::new()
+    };
+
+    let mut unclustered_nodes = vec![];
+    let mut clustered_nodes: BTreeMap<usize, Vec<String>> = BTreeMap::new();
 
-    let nodes_str = mapper.node_descrs
-                          .iter()
-                          .fold("\n".to_string(), |acc, x| format!("{}\n{}", acc, x));
+    for (name, label) in mapper.node_descrs.iter() {
+        let mut attrs = vec![format!("label=\"{}\"", label)];
+        let mut icluster = None;
 
-    let edges_str = mapper.edge_descrs
-                          .iter()
-                          .fold("\n".to_string(), |acc, x| format!("{}\n{}", acc, x));
+        match name_to_expr.get(name) {
+            Some(node) => {
+                let key = ExpressionRawPointer(node.clone());
+                if options.color_by_kind {
+                    attrs.push(format!("style=filled, fillcolor={}", get_kind_color(node)));
+                }
+                if highlighted.contains(&key) {
+                    attrs.push("color=red, penwidth=3".to_string());
+                }
+                icluster = clusters.get(&key).copied();
+            }
+            None => {
+                // elided sub-expression
+                attrs.push("style=dashed".to_string());
+            }
+        }
+
+        let node_descr = format!("{} [{}]", name, attrs.join(", "));
+        match icluster {
+            Some(icluster) => clustered_nodes.entry(icluster)
+                                             .or_default()
+                                             .push(node_descr),
+            None => unclustered_nodes.push(node_descr),
+        }
+    }
+
+    let rankdir = match options.rankdir {
+        RankDir::TopToBottom => "TB",
+        RankDir::LeftToRight => "LR",
+    };
+
+    let mut lines = vec!["digraph {".to_string(), format!("  rankdir={};", rankdir)];
+    lines.extend(unclustered_nodes.into_iter().map(|x| format!("  {}", x)));
+    for (icluster, node_descrs) in clustered_nodes {
+        lines.push(format!("  subgraph cluster_{} {{", icluster));
+        lines.push("    style=dashed;".to_string());
+        lines.extend(node_descrs.into_iter().map(|x| format!("    {}", x)));
+        lines.push("  }".to_string());
+    }
+    lines.extend(mapper.edge_descrs.iter().map(|x| format!("  {}", x)));
+    lines.push("}".to_string());
+    lines.push("".to_string());
 
-    let dot_code = format!("digraph {{\n {}\n\n {}\n}}", nodes_str, edges_str);
-    // println!("{}", dot_code);
+    lines.join("\n")
+}
+
+/// Writes the DOT code of `expr` to the file at `path`. See [`to_dot`].
+pub fn write_dot<P: AsRef<Path>>(expr: &Rc<Expression>, options: &DotOptions, path: P)
+                                 -> io::Result<()> {
+    fs::write(path, to_dot(expr, options))
+}
+
+/// Visualizes the DAG of `expr` via Graphviz. See [`to_dot`] to obtain the
+/// DOT code without invoking a viewer.
+pub fn show_dot<T: ConvertibleToDotOutputT>(expr: &Expression, output_to: T) {
+    let dot_code = to_dot(&Rc::new(expr.clone()), &DotOptions::default());
     show_dot_code(dot_code, output_to);
 }
+
+// vim: fdm=marker
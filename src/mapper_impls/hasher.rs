@@ -8,21 +8,23 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
 use std::rc::Rc;
 
-#[derive(CachedMapper)]
+/// Memoizes hashes of sub-expressions by node identity. A hasher can outlive
+/// the expression it was created for: expressions not seen before are hashed
+/// on demand, reusing the hashes of their already visited sub-expressions.
+#[derive(CachedMapper, Default)]
 pub struct HashCacher {
     cache: HashMap<ExpressionRawPointer, u64>,
 }
 
 impl HashCacher {
-    pub fn get(&self, key: Rc<Expression>) -> u64 {
-        let cache_key = ExpressionRawPointer(key.clone());
-        match self.cache.get(&cache_key) {
-            Some(x) => *x,
-            None => panic!(concat!("Expression '{}' not in cache of hashed sub-expressions.",
-                                   "This is most-likely an indication of using an",
-                                   "invalid HashCacher instance."),
-                           key),
-        }
+    pub fn new() -> HashCacher {
+        HashCacher::default()
+    }
+
+    /// Returns the hash of *key*, hashing any of its nodes that were not
+    /// visited before.
+    pub fn get(&mut self, key: Rc<Expression>) -> u64 {
+        self.visit(&key)
     }
 }
 
@@ -94,7 +96,7 @@ impl FoldMapper for HashCacher {
 }
 
 pub fn get_hasher(expr: Rc<Expression>) -> HashCacher {
-    let mut hash_cacher = HashCacher { cache: HashMap::new() };
-    hash_cacher.visit(&expr.clone());
+    let mut hash_cacher = HashCacher::new();
+    hash_cacher.visit(&expr);
     hash_cacher
 }
@@ -0,0 +1,322 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::functions::{default_registry, FunctionRegistry, PrintTarget};
+use crate::mapper_impls::stringifier::{
+    get_binary_op_precedence, PREC_ATOM, PREC_EXP, PREC_IF, PREC_LNOT, PREC_PROD, PREC_UNARY,
+};
+use crate::mappers::fold::FoldMapperWithContext;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use hashbrown::HashMap;
+use std::rc::Rc;
+
+// {{{ options
+
+/// Symbol placed between the operands of a [`BinaryOpType::Product`] by
+/// [`to_latex`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MulSymbol {
+    /// Operands are juxtaposed, i.e. `a b`. A `\cdot` is still emitted if the
+    /// right operand starts with a digit and a right operand starting with a
+    /// `-` is parenthesized.
+    Space,
+    /// `a \cdot b`
+    Cdot,
+    /// `a \times b`
+    Times,
+}
+
+/// Options controlling the LaTeX code emitted by [`to_latex`].
+#[derive(Clone, Debug)]
+pub struct LatexOptions {
+    /// Symbol used for multiplication.
+    pub mul_symbol: MulSymbol,
+    /// If *true*, variables named after Greek letters, for ex. `alpha` or
+    /// `Omega_12`, are printed as `\alpha` or `\Omega_{12}`.
+    pub greek_letters: bool,
+}
+
+impl Default for LatexOptions {
+    fn default() -> Self {
+        LatexOptions { mul_symbol: MulSymbol::Space,
+                       greek_letters: true }
+    }
+}
+
+// }}}
+
+const GREEK_LETTERS: [&str; 35] = ["alpha",
+                                   "beta",
+                                   "gamma",
+                                   "delta",
+                                   "epsilon",
+                                   "varepsilon",
+                                   "zeta",
+                                   "eta",
+                                   "theta",
+                                   "vartheta",
+                                   "iota",
+                                   "kappa",
+                                   "lambda",
+                                   "mu",
+                                   "nu",
+                                   "xi",
+                                   "pi",
+                                   "rho",
+                                   "sigma",
+                                   "tau",
+                                   "upsilon",
+                                   "phi",
+                                   "varphi",
+                                   "chi",
+                                   "psi",
+                                   "omega",
+                                   "Gamma",
+                                   "Delta",
+                                   "Theta",
+                                   "Lambda",
+                                   "Xi",
+                                   "Pi",
+                                   "Sigma",
+                                   "Phi",
+                                   "Omega"];
+
+fn guard_with_paren(my_str: String, my_prec: u8, outer_prec: &u8) -> String {
+    if *outer_prec > my_prec {
+        format!("\\left({}\\right)", my_str)
+    } else {
+        my_str
+    }
+}
+
+struct Latexifier<'a> {
+    options: &'a LatexOptions,
+    registry: &'a FunctionRegistry,
+    cache: HashMap<(ExpressionRawPointer, u8), String>,
+}
+
+impl CachedMapper<(ExpressionRawPointer, u8), String> for Latexifier<'_> {
+    fn query_cache(&self, key: &(ExpressionRawPointer, u8)) -> Option<&String> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: (ExpressionRawPointer, u8), value: String) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl Latexifier<'_> {
+    fn print_name(&self, name: &str) -> String {
+        let (stem, subscript) = match name.split_once('_') {
+            Some((stem, subscript)) if !stem.is_empty() && !subscript.is_empty() => {
+                (stem, Some(subscript))
+            }
+            _ => (name, None),
+        };
+        let stem = if self.options.greek_letters && GREEK_LETTERS.contains(&stem) {
+            format!("\\{}", stem)
+        } else {
+            stem.to_string()
+        };
+        match subscript {
+            Some(x) if x.chars().count() == 1 => format!("{}_{}", stem, x),
+            Some(x) => format!("{}_{{{}}}", stem, x.replace('_', "\\_")),
+            None => stem,
+        }
+    }
+}
+
+impl FoldMapperWithContext for Latexifier<'_> {
+    type Context = u8;
+    type Output = String;
+    type CacheKey = (ExpressionRawPointer, u8);
+
+    fn get_cache_key(&self, expr: &Rc<Expression>, outer_prec: &Self::Context) -> Self::CacheKey {
+        (ExpressionRawPointer(expr.clone()), *outer_prec)
+    }
+
+    fn map_scalar(&mut self, value: &LiteralT, _outer_prec: &Self::Context) -> Self::Output {
+        format!("{}", value)
+    }
+    fn map_variable(&mut self, name: String, _outer_prec: &Self::Context) -> Self::Output {
+        self.print_name(&name)
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>, outer_prec: &Self::Context)
+                    -> Self::Output {
+        let (op_str, my_prec) = match op {
+            UnaryOpType::LogicalNot => ("\\neg ", PREC_LNOT),
+            UnaryOpType::BitwiseNot => ("\\sim ", PREC_UNARY),
+            UnaryOpType::Minus => ("-", PREC_UNARY),
+        };
+
+        guard_with_paren(format!("{}{}", op_str, self.visit(x, &my_prec)),
+                         my_prec,
+                         outer_prec)
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>,
+                     outer_prec: &Self::Context)
+                     -> Self::Output {
+        match op {
+            // \frac and \lfloor group their operands, so they bind like a
+            // unary operation.
+            BinaryOpType::Divide => {
+                let my_str = format!("\\frac{{{}}}{{{}}}",
+                                     self.visit(left, &0),
+                                     self.visit(right, &0));
+                return guard_with_paren(my_str, PREC_UNARY, outer_prec);
+            }
+            BinaryOpType::FloorDiv => {
+                let my_str = format!("\\left\\lfloor \\frac{{{}}}{{{}}} \\right\\rfloor",
+                                     self.visit(left, &0),
+                                     self.visit(right, &0));
+                return guard_with_paren(my_str, PREC_UNARY, outer_prec);
+            }
+            BinaryOpType::Exponent => {
+                let my_str = format!("{{{}}}^{{{}}}",
+                                     self.visit(left, &(PREC_EXP + 1)),
+                                     self.visit(right, &0));
+                return guard_with_paren(my_str, PREC_EXP, outer_prec);
+            }
+            _ => {}
+        }
+
+        let my_prec = get_binary_op_precedence(op);
+        let left_str = self.visit(left, &my_prec);
+        let mut right_str = self.visit(right, &(my_prec + 1));
+
+        let op_str = match op {
+            BinaryOpType::Sum => "+",
+            BinaryOpType::Subtract => "-",
+            BinaryOpType::Product => match self.options.mul_symbol {
+                MulSymbol::Space if right_str.starts_with(|c: char| c.is_ascii_digit()) => "\\cdot",
+                // juxtaposing a negated operand would read as a subtraction
+                MulSymbol::Space if right_str.starts_with('-') => {
+                    right_str = format!("\\left({}\\right)", right_str);
+                    ""
+                }
+                MulSymbol::Space => "",
+                MulSymbol::Cdot => "\\cdot",
+                MulSymbol::Times => "\\times",
+            },
+            BinaryOpType::Modulo => "\\bmod",
+
+            BinaryOpType::Equal => "=",
+            BinaryOpType::NotEqual => "\\neq",
+            BinaryOpType::Greater => ">",
+            BinaryOpType::GreaterEqual => "\\geq",
+            BinaryOpType::Less => "<",
+            BinaryOpType::LessEqual => "\\leq",
+
+            BinaryOpType::BitwiseOr => "\\mathbin{|}",
+            BinaryOpType::BitwiseXor => "\\oplus",
+            BinaryOpType::BitwiseAnd => "\\mathbin{\\&}",
+
+            BinaryOpType::LogicalAnd => "\\wedge",
+            BinaryOpType::LogicalOr => "\\vee",
+
+            BinaryOpType::LeftShift => "\\ll",
+            BinaryOpType::RightShift => "\\gg",
+
+            BinaryOpType::Divide | BinaryOpType::FloorDiv | BinaryOpType::Exponent => {
+                unreachable!()
+            }
+        };
+
+        let my_str = if op_str.is_empty() {
+            format!("{} {}", left_str, right_str)
+        } else {
+            format!("{} {} {}", left_str, op_str, right_str)
+        };
+        guard_with_paren(my_str, my_prec, outer_prec)
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT,
+                _outer_prec: &Self::Context)
+                -> Self::Output {
+        let rec_str: Vec<String> = params.iter().map(|x| self.visit(x, &0)).collect();
+        let args = rec_str.join(", ");
+
+        let callee = match &**call {
+            Expression::Variable(name) => {
+                match self.registry
+                          .get(name)
+                          .and_then(|f| f.printed_names.get(&PrintTarget::LaTeX))
+                {
+                    Some(latex_name) if latex_name == "\\sqrt" => {
+                        return format!("\\sqrt{{{}}}", args);
+                    }
+                    Some(latex_name) => latex_name.clone(),
+                    None => format!("\\operatorname{{{}}}", self.print_name(name)),
+                }
+            }
+            _ => self.visit(call, &PREC_ATOM),
+        };
+        format!("{}\\left({}\\right)", callee, args)
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT,
+                     _outer_prec: &Self::Context)
+                     -> Self::Output {
+        let rec_str: Vec<String> = indices.iter().map(|x| self.visit(x, &0)).collect();
+        format!("{{{}}}_{{{}}}",
+                self.visit(agg, &PREC_ATOM),
+                rec_str.join(", "))
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>,
+              outer_prec: &Self::Context)
+              -> Self::Output {
+        let my_str = format!("\\begin{{cases}} {} & \\text{{if }} {} \\\\ {} & \
+                              \\text{{otherwise}} \\end{{cases}}",
+                             self.visit(then, &PREC_IF),
+                             self.visit(cond, &PREC_IF),
+                             self.visit(else_, &PREC_IF));
+        // the cases environment is delimited on the left only, so parenthesize
+        // it in every context tighter than a product.
+        guard_with_paren(my_str, PREC_PROD, outer_prec)
+    }
+}
+
+/// Returns the LaTeX code (in math mode) for `expr`. Calls to functions in the
+/// [default registry](crate::functions::default_registry) are printed with
+/// their LaTeX macros, other calls are printed via `\operatorname`.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, to_latex, LatexOptions};
+///
+/// let expr = parse("alpha * sin(x) / (1 + x**2)");
+/// assert_eq!(to_latex(&expr, &LatexOptions::default()),
+///            "\\frac{\\alpha \\sin\\left(x\\right)}{1 + {x}^{2}}");
+/// ```
+pub fn to_latex(expr: &Rc<Expression>, options: &LatexOptions) -> String {
+    to_latex_with_registry(expr, options, default_registry())
+}
+
+/// Returns the LaTeX code for `expr` with the calls printed using the LaTeX
+/// names of the functions in `registry`. Also see [`to_latex`].
+pub fn to_latex_with_registry(expr: &Rc<Expression>, options: &LatexOptions,
+                              registry: &FunctionRegistry)
+                              -> String {
+    let mut mapper = Latexifier { options,
+                                  registry,
+                                  cache: HashMap::new() };
+    mapper.visit(expr, &0)
+}
+
+// vim: fdm=marker
@@ -0,0 +1,409 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Conversion between multi-dimensional subscripts and subscripts of flat
+//! buffers.
+//!
+//! [`linearize`] rewrites `a[i, j, k]` as `a[i*s0 + j*s1 + k*s2]`, where
+//! `s0, s1, s2` are the strides of `a`'s [`ArrayLayout`].
+//! [`delinearize`] performs the inverse rewrite on flat indices that are
+//! affine combinations of sub-expressions.
+
+use crate::mapper_impls::array_access::{get_index_range, Interval};
+use crate::mappers::identity::IdentityMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// {{{ ArrayLayout
+
+/// Shape and strides (in number of elements) of an array.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrayLayout {
+    pub shape: Vec<i64>,
+    pub strides: Vec<i64>,
+}
+
+impl ArrayLayout {
+    /// Returns the layout of an array of *shape* whose last axis is
+    /// contiguous.
+    pub fn row_major(shape: &[i64]) -> Self {
+        let mut strides = vec![1; shape.len()];
+        for iaxis in (0..shape.len().saturating_sub(1)).rev() {
+            strides[iaxis] = strides[iaxis + 1] * shape[iaxis + 1];
+        }
+        ArrayLayout { shape: shape.to_vec(),
+                      strides }
+    }
+
+    /// Returns the layout of an array of *shape* whose first axis is
+    /// contiguous.
+    pub fn column_major(shape: &[i64]) -> Self {
+        let mut strides = vec![1; shape.len()];
+        for iaxis in 1..shape.len() {
+            strides[iaxis] = strides[iaxis - 1] * shape[iaxis - 1];
+        }
+        ArrayLayout { shape: shape.to_vec(),
+                      strides }
+    }
+
+    pub fn with_strides(shape: &[i64], strides: &[i64]) -> Self {
+        if shape.len() != strides.len() {
+            panic!("Got {} strides for an array of {} dimensions.",
+                   strides.len(),
+                   shape.len());
+        }
+        ArrayLayout { shape: shape.to_vec(),
+                      strides: strides.to_vec() }
+    }
+}
+
+// }}}
+
+// {{{ helpers
+
+fn get_int_literal(value: i64) -> Rc<Expression> {
+    match i32::try_from(value) {
+        Ok(value) => Rc::new(Expression::Scalar(LiteralT::I32(value))),
+        Err(_) => Rc::new(Expression::Scalar(LiteralT::I64(value))),
+    }
+}
+
+/// Returns `constant + sum(coeff * term)`, omitting zero terms and unit
+/// coefficients.
+fn build_linear_combination(constant: i64, terms: &[(i64, Rc<Expression>)]) -> Rc<Expression> {
+    let mut result: Option<Rc<Expression>> = None;
+    for (coeff, term) in terms.iter().filter(|(coeff, _)| *coeff != 0) {
+        let scaled = |magnitude: i64| {
+            if magnitude == 1 {
+                term.clone()
+            } else {
+                Rc::new(Expression::BinaryOp(term.clone(),
+                                             BinaryOpType::Product,
+                                             get_int_literal(magnitude)))
+            }
+        };
+        result =
+            Some(match result {
+                     None if *coeff < 0 && *coeff != i64::MIN => {
+                         Rc::new(Expression::UnaryOp(UnaryOpType::Minus, scaled(-coeff)))
+                     }
+                     None => scaled(*coeff),
+                     Some(acc) if *coeff < 0 && *coeff != i64::MIN => {
+                         Rc::new(Expression::BinaryOp(acc, BinaryOpType::Subtract, scaled(-coeff)))
+                     }
+                     Some(acc) => {
+                         Rc::new(Expression::BinaryOp(acc, BinaryOpType::Sum, scaled(*coeff)))
+                     }
+                 });
+    }
+
+    match result {
+        None => get_int_literal(constant),
+        Some(acc) if constant < 0 && constant != i64::MIN => {
+            Rc::new(Expression::BinaryOp(acc, BinaryOpType::Subtract, get_int_literal(-constant)))
+        }
+        Some(acc) if constant != 0 => {
+            Rc::new(Expression::BinaryOp(acc, BinaryOpType::Sum, get_int_literal(constant)))
+        }
+        Some(acc) => acc,
+    }
+}
+
+type LinearTerms = (i64, Vec<(i64, Rc<Expression>)>);
+
+/// Returns `(constant, [(coeff, term), ...])` such that *expr* equals
+/// `constant + sum(coeff * term)`. Sub-expressions that are not sums,
+/// differences, negations or products with a constant are terms of their
+/// own. Structurally equal terms are merged.
+fn get_linear_terms(expr: &Rc<Expression>) -> LinearTerms {
+    fn add_terms(acc: &mut LinearTerms, other: LinearTerms, factor: i64) -> Option<()> {
+        acc.0 = acc.0.checked_add(other.0.checked_mul(factor)?)?;
+        for (coeff, term) in other.1 {
+            let coeff = coeff.checked_mul(factor)?;
+            match acc.1.iter_mut().find(|(_, acc_term)| *acc_term == term) {
+                Some((acc_coeff, _)) => *acc_coeff = acc_coeff.checked_add(coeff)?,
+                None => acc.1.push((coeff, term)),
+            }
+        }
+        Some(())
+    }
+
+    fn rec(expr: &Rc<Expression>) -> Option<LinearTerms> {
+        let mut acc = (0, vec![]);
+        match &**expr {
+            Expression::Scalar(value) if value.is_integral() => acc.0 = value.as_i64(),
+            Expression::UnaryOp(UnaryOpType::Minus, x) => add_terms(&mut acc, rec(x)?, -1)?,
+            Expression::BinaryOp(left, BinaryOpType::Sum, right) => {
+                add_terms(&mut acc, rec(left)?, 1)?;
+                add_terms(&mut acc, rec(right)?, 1)?;
+            }
+            Expression::BinaryOp(left, BinaryOpType::Subtract, right) => {
+                add_terms(&mut acc, rec(left)?, 1)?;
+                add_terms(&mut acc, rec(right)?, -1)?;
+            }
+            Expression::BinaryOp(left, BinaryOpType::Product, right) => {
+                let (left, right) = (rec(left)?, rec(right)?);
+                if left.1.is_empty() {
+                    add_terms(&mut acc, right, left.0)?;
+                } else if right.1.is_empty() {
+                    add_terms(&mut acc, left, right.0)?;
+                } else {
+                    acc.1.push((1, expr.clone()));
+                }
+            }
+            _ => acc.1.push((1, expr.clone())),
+        }
+        Some(acc)
+    }
+
+    rec(expr).unwrap_or_else(|| (0, vec![(1, expr.clone())]))
+}
+
+// }}}
+
+// {{{ linearize
+
+struct Linearizer<'a> {
+    layouts: &'a HashMap<String, ArrayLayout>,
+    cache: hashbrown::HashMap<ExpressionRawPointer, Rc<Expression>>,
+}
+
+impl CachedMapper<ExpressionRawPointer, Rc<Expression>> for Linearizer<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer) -> Option<&Rc<Expression>> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer, value: Rc<Expression>) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl IdentityMapper for Linearizer<'_> {
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT) -> Rc<Expression> {
+        let rec_indices: SmallVecExprT =
+            indices.iter().map(|idx| self.visit(idx.clone())).collect();
+        let layout = match &**agg {
+            Expression::Variable(name) => self.layouts.get(name).map(|layout| (name, layout)),
+            _ => None,
+        };
+        match layout {
+            Some((name, layout)) => {
+                if layout.strides.len() != rec_indices.len() {
+                    panic!("Array '{}' has {} dimensions, but is subscripted with {} indices.",
+                           name,
+                           layout.strides.len(),
+                           rec_indices.len());
+                }
+                let mut constant: i64 = 0;
+                let mut terms = vec![];
+                for (stride, idx) in layout.strides.iter().zip(rec_indices) {
+                    // constants whose offset overflows stay symbolic
+                    let offset = match &*idx {
+                        Expression::Scalar(value) if value.is_integral() => {
+                            stride.checked_mul(value.as_i64())
+                                  .and_then(|offset| constant.checked_add(offset))
+                        }
+                        _ => None,
+                    };
+                    match offset {
+                        Some(offset) => constant = offset,
+                        None => terms.push((*stride, idx)),
+                    }
+                }
+                let flat_index = build_linear_combination(constant, &terms);
+                Rc::new(Expression::Subscript(agg.clone(), [flat_index].into_iter().collect()))
+            }
+            None => Rc::new(Expression::Subscript(self.visit(agg.clone()), rec_indices)),
+        }
+    }
+}
+
+/// Returns *expr* with the subscripts of every array in *layouts* rewritten
+/// as subscripts of a flat buffer. Panics if such an array is subscripted
+/// with a number of indices different from its number of dimensions.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::mapper_impls::linearize::ArrayLayout;
+/// use symoxide::{linearize, parse};
+///
+/// let layouts = HashMap::from([("a".to_string(), ArrayLayout::row_major(&[4, 5, 6]))]);
+/// assert_eq!(linearize(&parse("a[i, j, k] + b[i]"), &layouts),
+///            parse("a[i*30 + j*6 + k] + b[i]"));
+/// ```
+pub fn linearize(expr: &Rc<Expression>, layouts: &HashMap<String, ArrayLayout>) -> Rc<Expression> {
+    let mut mapper = Linearizer { layouts,
+                                  cache: hashbrown::HashMap::new() };
+    mapper.visit(expr.clone())
+}
+
+// }}}
+
+// {{{ delinearize
+
+/// Returns the indices along every axis of *layout* whose linearization is
+/// *flat_index*, or *None* if some term's coefficient is not a multiple of any
+/// stride. Every term goes to the axis with the largest stride dividing its
+/// coefficient. The constant is split across axes by truncating division,
+/// except along the smallest-stride axis with terms, which borrows by floor
+/// division so that `a[6*j - 1]` becomes `a[j - 1, 5]` rather than `a[j, -1]`.
+fn get_delinearized_indices(flat_index: &Rc<Expression>, layout: &ArrayLayout)
+                            -> Option<SmallVecExprT> {
+    let (constant, terms) = get_linear_terms(flat_index);
+    let mut axes_by_stride: Vec<usize> = (0..layout.strides.len()).collect();
+    axes_by_stride.sort_by_key(|iaxis| std::cmp::Reverse(layout.strides[*iaxis].unsigned_abs()));
+
+    let mut axis_terms: Vec<Vec<(i64, Rc<Expression>)>> = vec![vec![]; layout.strides.len()];
+    for (coeff, term) in terms.into_iter().filter(|(coeff, _)| *coeff != 0) {
+        let iaxis = *axes_by_stride.iter().find(|iaxis| {
+                                               let stride = layout.strides[**iaxis];
+                                               stride != 0 && coeff.checked_rem(stride) == Some(0)
+                                           })?;
+        axis_terms[iaxis].push((coeff.checked_div(layout.strides[iaxis])?, term));
+    }
+
+    let borrowing_axis = axes_by_stride.iter()
+                                       .rev()
+                                       .find(|iaxis| !axis_terms[**iaxis].is_empty());
+    let mut axis_constants = vec![0; layout.strides.len()];
+    let mut remainder = constant;
+    for iaxis in axes_by_stride.iter().copied() {
+        let stride = layout.strides[iaxis];
+        if stride == 0 {
+            continue;
+        }
+        if borrowing_axis == Some(&iaxis) {
+            axis_constants[iaxis] = remainder.checked_div_euclid(stride)?;
+            remainder = remainder.checked_rem_euclid(stride)?;
+        } else {
+            axis_constants[iaxis] = remainder.checked_div(stride)?;
+            remainder = remainder.checked_rem(stride)?;
+        }
+    }
+    if remainder != 0 {
+        return None;
+    }
+
+    Some(axis_terms.iter()
+                   .zip(axis_constants)
+                   .map(|(terms, constant)| build_linear_combination(constant, terms))
+                   .collect())
+}
+
+struct Delinearizer<'a> {
+    layouts: &'a HashMap<String, ArrayLayout>,
+    domain: Option<&'a HashMap<String, Interval>>,
+    cache: hashbrown::HashMap<ExpressionRawPointer, Rc<Expression>>,
+}
+
+impl CachedMapper<ExpressionRawPointer, Rc<Expression>> for Delinearizer<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer) -> Option<&Rc<Expression>> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer, value: Rc<Expression>) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl Delinearizer<'_> {
+    /// Returns *true* only if every index in *indices* provably lies within its
+    /// axis. Without a domain, only indices bounded irrespective of the values
+    /// of their variables qualify, for ex. constants or `i % 6`.
+    fn is_in_bounds(&self, indices: &SmallVecExprT, layout: &ArrayLayout) -> bool {
+        let empty_domain = HashMap::new();
+        let domain = self.domain.unwrap_or(&empty_domain);
+        indices.iter()
+               .zip(layout.shape.iter())
+               .all(|(idx, axis_len)| {
+                   let axis = Interval { lo: 0,
+                                         hi: axis_len - 1 };
+                   get_index_range(idx, domain).is_some_and(|range| {
+                                                   range.interval.is_subset_of(&axis)
+                                               })
+               })
+    }
+}
+
+impl IdentityMapper for Delinearizer<'_> {
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT) -> Rc<Expression> {
+        let rec_indices: SmallVecExprT =
+            indices.iter().map(|idx| self.visit(idx.clone())).collect();
+        let layout = match &**agg {
+            Expression::Variable(name) if rec_indices.len() == 1 => self.layouts.get(name),
+            _ => None,
+        };
+        let delinearized = layout.and_then(|layout| {
+                                     get_delinearized_indices(&rec_indices[0], layout)
+                                         .filter(|new_idxs| self.is_in_bounds(new_idxs, layout))
+                                 });
+        match delinearized {
+            Some(new_indices) => Rc::new(Expression::Subscript(agg.clone(), new_indices)),
+            None => Rc::new(Expression::Subscript(self.visit(agg.clone()), rec_indices)),
+        }
+    }
+}
+
+/// Returns *expr* with the single-index subscripts of every array in *layouts*
+/// rewritten as multi-dimensional subscripts, such that linearizing the result
+/// with *layouts* gives back an expression equal in value to *expr*.
+///
+/// The flat index is split into a sum of terms with integer coefficients, and
+/// every term is assigned to the axis with the largest stride dividing its
+/// coefficient. Negative constant offsets borrow from the smallest-stride axis
+/// with terms, for ex. `a[6*j - 1]` becomes `a[0, j - 1, 5]` for a row-major
+/// `4 x 5 x 6` array. A subscript is only rewritten if every recovered index
+/// provably lies within its axis, given the bounds on the variables in
+/// *domain*. Without *domain*, only constant indices and indices bounded
+/// irrespective of their variables, for ex. `i % 6`, are recovered.
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::mapper_impls::array_access::Interval;
+/// use symoxide::mapper_impls::linearize::ArrayLayout;
+/// use symoxide::{delinearize, parse};
+///
+/// let layouts = HashMap::from([("a".to_string(), ArrayLayout::row_major(&[4, 5, 6]))]);
+/// let domain = HashMap::from([("i".to_string(), Interval::new(0, 3)),
+///                             ("j".to_string(), Interval::new(0, 3)),
+///                             ("k".to_string(), Interval::new(0, 5))]);
+/// assert_eq!(delinearize(&parse("a[30*i + k + 6*(j + 1)]"), &layouts, Some(&domain)),
+///            parse("a[i, j + 1, k]"));
+/// // k might exceed the last axis
+/// assert_eq!(delinearize(&parse("a[30*i + k + 6*(j + 1)]"), &layouts, None),
+///            parse("a[30*i + k + 6*(j + 1)]"));
+/// assert_eq!(delinearize(&parse("a[36 + k % 6]"), &layouts, None),
+///            parse("a[1, 1, k % 6]"));
+/// ```
+pub fn delinearize(expr: &Rc<Expression>, layouts: &HashMap<String, ArrayLayout>,
+                   domain: Option<&HashMap<String, Interval>>)
+                   -> Rc<Expression> {
+    let mut mapper = Delinearizer { layouts,
+                                    domain,
+                                    cache: hashbrown::HashMap::new() };
+    mapper.visit(expr.clone())
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -1,8 +1,24 @@
+pub mod alpha_equivalence;
+pub mod array_access;
+pub mod compiler;
 pub mod deduplicator;
 pub mod dependency;
+pub mod diff;
 pub mod equality;
+pub mod equivalence;
+pub mod evaluate;
+pub mod fingerprint;
+pub mod forward_diff;
 pub mod graphvizifier;
 pub mod hasher;
+pub mod latexifier;
+pub mod linearize;
 pub mod node_counter;
+pub mod pretty_printer;
+pub mod pythonifier;
 pub mod reprifier;
+pub mod reverse_diff;
+pub mod rust_emitter;
+pub mod stats;
 pub mod stringifier;
+pub mod type_inference;
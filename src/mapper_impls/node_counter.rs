@@ -21,7 +21,7 @@
 use crate::mappers::walk::WalkMapper;
 use crate::mappers::CachedMapper;
 use crate::primitives::Expression;
-use crate::utils::ExpressionRawPointer;
+use crate::utils::{get_children, ExpressionRawPointer};
 use crate::CachedMapper;
 use hashbrown::HashMap;
 use std::rc::Rc;
@@ -39,9 +39,57 @@ impl WalkMapper for NodeCounter {
     }
 }
 
-pub fn get_num_nodes(expr: &Expression) -> u32 {
+pub fn get_num_nodes(expr: &Rc<Expression>) -> u32 {
     let mut node_counter = NodeCounter { num_nodes: 0,
                                          cache: HashMap::new() };
-    node_counter.visit(&Rc::new(expr.clone()));
+    node_counter.visit(expr);
     node_counter.num_nodes
 }
+
+/// Counts the nodes of the DAGs rooted at the visited expressions, sharing the
+/// bookkeeping between roots with common sub-expressions. Every root is walked
+/// once, marking the visited nodes with the number of the walk, so that the
+/// memory is linear in the number of distinct nodes and no visited set is
+/// cleared between walks.
+#[derive(Default)]
+pub(crate) struct SharedNodeCounter {
+    /// Number of the latest walk that visited each node.
+    stamps: HashMap<ExpressionRawPointer, u32>,
+    /// Number of nodes of the roots walked so far.
+    num_nodes: HashMap<ExpressionRawPointer, u32>,
+    num_walks: u32,
+}
+
+impl SharedNodeCounter {
+    pub(crate) fn get_num_nodes(&mut self, expr: &Rc<Expression>) -> u32 {
+        if let Some(num_nodes) = self.num_nodes.get(&ExpressionRawPointer(expr.clone())) {
+            return *num_nodes;
+        }
+        self.num_walks += 1;
+        let mut num_nodes = 0;
+        let mut stack = vec![expr.clone()];
+        while let Some(node) = stack.pop() {
+            let stamp = self.stamps
+                            .entry(ExpressionRawPointer(node.clone()))
+                            .or_insert(0);
+            if *stamp == self.num_walks {
+                continue;
+            }
+            *stamp = self.num_walks;
+            num_nodes += 1;
+            stack.extend(get_children(&node));
+        }
+        self.num_nodes
+            .insert(ExpressionRawPointer(expr.clone()), num_nodes);
+        num_nodes
+    }
+}
+
+/// Returns the number of nodes in the DAG of each of *exprs*. A sub-expression
+/// shared between several roots is counted towards each of them.
+pub fn get_num_nodes_many(exprs: &[Rc<Expression>]) -> Vec<u32> {
+    let mut node_counter = SharedNodeCounter::default();
+    exprs.iter()
+         .map(|expr| node_counter.get_num_nodes(expr))
+         .collect()
+}
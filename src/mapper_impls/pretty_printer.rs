@@ -0,0 +1,385 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A [Wadler-style](https://homepages.inf.ed.ac.uk/wadler/papers/prettier/prettier.pdf)
+//! pretty printer. See [`pretty_print`].
+
+use crate::mapper_impls::stringifier::{
+    get_binary_op_precedence, PREC_ATOM, PREC_EXP, PREC_IF, PREC_LNOT, PREC_UNARY,
+};
+use crate::mappers::fold::FoldMapperWithContext;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::{get_children, get_topological_order, ExpressionRawPointer};
+use crate::CachedMapper;
+use hashbrown::{HashMap, HashSet};
+use std::rc::Rc;
+
+// {{{ documents
+
+/// A document that can be laid out at different widths.
+enum Doc {
+    Text(String),
+    /// A possible line break, printed as the contained string if the
+    /// enclosing group is not broken.
+    Line(&'static str),
+    Concat(Vec<Rc<Doc>>),
+    /// Increases the indentation of the line breaks of the nested document.
+    Nest(usize, Rc<Doc>),
+    /// A document whose line breaks are either all broken or none of them.
+    Group(Rc<Doc>),
+}
+
+fn text<T: ToString>(x: T) -> Rc<Doc> {
+    Rc::new(Doc::Text(x.to_string()))
+}
+
+/// A line break that is printed as a space in flat mode.
+fn line() -> Rc<Doc> {
+    Rc::new(Doc::Line(" "))
+}
+
+/// A line break that is printed as nothing in flat mode.
+fn softline() -> Rc<Doc> {
+    Rc::new(Doc::Line(""))
+}
+
+fn concat<T: IntoIterator<Item = Rc<Doc>>>(docs: T) -> Rc<Doc> {
+    Rc::new(Doc::Concat(docs.into_iter().collect()))
+}
+
+fn nest(indent: usize, doc: Rc<Doc>) -> Rc<Doc> {
+    Rc::new(Doc::Nest(indent, doc))
+}
+
+fn group(doc: Rc<Doc>) -> Rc<Doc> {
+    Rc::new(Doc::Group(doc))
+}
+
+/// Returns *true* if the rest of the line starting with `doc` in flat mode,
+/// followed by `rest` (top of the stack at the end) fits within `width`
+/// columns.
+fn fits(width: isize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut remaining = width;
+    let mut stack: Vec<(bool, &Doc)> = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+
+    while remaining >= 0 {
+        let (flat, doc) = match stack.pop() {
+            Some(x) => x,
+            None => match rest.next() {
+                Some((_, flat, doc)) => (*flat, *doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(x) => remaining -= x.chars().count() as isize,
+            Doc::Line(x) if flat => remaining -= x.len() as isize,
+            Doc::Line(_) => return true,
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|x| (flat, &**x))),
+            Doc::Nest(_, x) => stack.push((flat, x)),
+            Doc::Group(x) => stack.push((flat, x)),
+        }
+    }
+    false
+}
+
+/// Returns the layout of `doc` that fits within `width` columns wherever
+/// possible.
+fn layout(doc: &Doc, width: usize) -> String {
+    let mut result = String::new();
+    let mut column = 0;
+    // (indentation, is_flat, doc)
+    let mut stack: Vec<(usize, bool, &Doc)> = vec![(0, false, doc)];
+
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(x) => {
+                result.push_str(x);
+                column += x.chars().count();
+            }
+            Doc::Line(x) if flat => {
+                result.push_str(x);
+                column += x.len();
+            }
+            Doc::Line(_) => {
+                result.push('\n');
+                result.push_str(&" ".repeat(indent));
+                column = indent;
+            }
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|x| (indent, flat, &**x))),
+            Doc::Nest(i, x) => stack.push((indent + i, flat, x)),
+            Doc::Group(x) => {
+                let flat = flat || fits(width as isize - column as isize, x, &stack);
+                stack.push((indent, flat, x));
+            }
+        }
+    }
+    result
+}
+
+// }}}
+
+/// Options for [`pretty_print`].
+#[derive(Copy, Clone, Debug)]
+pub struct PrettyOptions {
+    /// Number of columns that the printed lines should not exceed wherever
+    /// possible.
+    pub width: usize,
+    /// Number of spaces by which continuation lines are indented.
+    pub indent: usize,
+    /// If *true*, every non-leaf sub-expression that is used more than once
+    /// is printed once as a `let` binding and referred to by its name.
+    pub let_bindings: bool,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions { width: 80,
+                        indent: 4,
+                        let_bindings: false }
+    }
+}
+
+#[derive(CachedMapper)]
+struct PrettyPrinter {
+    indent: usize,
+    /// Names of the sub-expressions printed as `let` bindings.
+    bindings: HashMap<ExpressionRawPointer, String>,
+    cache: HashMap<(ExpressionRawPointer, u8), Rc<Doc>>,
+}
+
+impl PrettyPrinter {
+    fn visit_operand(&mut self, expr: &Rc<Expression>, outer_prec: u8) -> Rc<Doc> {
+        match self.bindings.get(&ExpressionRawPointer(expr.clone())) {
+            Some(name) => text(name),
+            None => self.visit(expr, &outer_prec),
+        }
+    }
+
+    fn guard_with_paren(&self, doc: Rc<Doc>, my_prec: u8, outer_prec: &u8) -> Rc<Doc> {
+        if *outer_prec > my_prec {
+            group(concat([text("("),
+                          nest(self.indent, concat([softline(), doc])),
+                          softline(),
+                          text(")")]))
+        } else {
+            doc
+        }
+    }
+
+    /// Returns the document for `prefix(args[0], args[1], ...)` with
+    /// `open`, `close` as the delimiters of the arguments.
+    fn delimited(&mut self, prefix: Rc<Doc>, open: &str, args: &SmallVecExprT, close: &str)
+                 -> Rc<Doc> {
+        let mut arg_docs = vec![];
+        for (iarg, arg) in args.iter().enumerate() {
+            if iarg > 0 {
+                arg_docs.push(text(","));
+                arg_docs.push(line());
+            }
+            arg_docs.push(self.visit_operand(arg, 0));
+        }
+        group(concat([prefix,
+                      text(open),
+                      nest(self.indent, concat([softline(), concat(arg_docs)])),
+                      softline(),
+                      text(close)]))
+    }
+}
+
+impl FoldMapperWithContext for PrettyPrinter {
+    type Context = u8;
+    type Output = Rc<Doc>;
+    type CacheKey = (ExpressionRawPointer, u8);
+
+    fn get_cache_key(&self, expr: &Rc<Expression>, outer_prec: &Self::Context) -> Self::CacheKey {
+        (ExpressionRawPointer(expr.clone()), *outer_prec)
+    }
+
+    fn map_scalar(&mut self, value: &LiteralT, _outer_prec: &Self::Context) -> Self::Output {
+        text(value)
+    }
+    fn map_variable(&mut self, name: String, _outer_prec: &Self::Context) -> Self::Output {
+        text(name)
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>, outer_prec: &Self::Context)
+                    -> Self::Output {
+        let (op_str, my_prec) = match op {
+            UnaryOpType::LogicalNot => ("not ", PREC_LNOT),
+            UnaryOpType::BitwiseNot => ("~", PREC_UNARY),
+            UnaryOpType::Minus => ("-", PREC_UNARY),
+        };
+        let doc = concat([text(op_str), self.visit_operand(x, my_prec)]);
+        self.guard_with_paren(doc, my_prec, outer_prec)
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>,
+                     outer_prec: &Self::Context)
+                     -> Self::Output {
+        let op_str = match op {
+            BinaryOpType::Sum => "+",
+            BinaryOpType::Subtract => "-",
+            BinaryOpType::Product => "*",
+            BinaryOpType::Divide => "/",
+            BinaryOpType::FloorDiv => "//",
+            BinaryOpType::Modulo => "%",
+
+            BinaryOpType::Equal => "==",
+            BinaryOpType::NotEqual => "!=",
+            BinaryOpType::Greater => ">",
+            BinaryOpType::GreaterEqual => ">=",
+            BinaryOpType::Less => "<",
+            BinaryOpType::LessEqual => "<=",
+
+            BinaryOpType::BitwiseOr => "|",
+            BinaryOpType::BitwiseXor => "^",
+            BinaryOpType::BitwiseAnd => "&",
+
+            BinaryOpType::LogicalAnd => "and",
+            BinaryOpType::LogicalOr => "or",
+
+            BinaryOpType::LeftShift => "<<",
+            BinaryOpType::RightShift => ">>",
+
+            BinaryOpType::Exponent => "**",
+        };
+        let my_prec = get_binary_op_precedence(op);
+        let (left_prec, right_prec) = match op {
+            // right-to-left associative
+            BinaryOpType::Exponent => (PREC_EXP + 1, PREC_EXP),
+            // left-to-right associative
+            _ => (my_prec, my_prec + 1),
+        };
+
+        // break before the operator
+        let doc = group(concat([self.visit_operand(left, left_prec),
+                                nest(self.indent,
+                                     concat([line(),
+                                             text(format!("{} ", op_str)),
+                                             self.visit_operand(right, right_prec)]))]));
+        self.guard_with_paren(doc, my_prec, outer_prec)
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT,
+                _outer_prec: &Self::Context)
+                -> Self::Output {
+        let callee = self.visit_operand(call, PREC_ATOM);
+        self.delimited(callee, "(", params, ")")
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT,
+                     _outer_prec: &Self::Context)
+                     -> Self::Output {
+        let agg = self.visit_operand(agg, PREC_ATOM);
+        self.delimited(agg, "[", indices, "]")
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>,
+              outer_prec: &Self::Context)
+              -> Self::Output {
+        let inner_prec = PREC_IF + 1;
+        let doc = group(concat([self.visit_operand(then, inner_prec),
+                                nest(self.indent,
+                                     concat([line(),
+                                             text("if "),
+                                             self.visit_operand(cond, inner_prec),
+                                             line(),
+                                             text("else "),
+                                             self.visit_operand(else_, inner_prec)]))]));
+        self.guard_with_paren(doc, PREC_IF, outer_prec)
+    }
+}
+
+/// Returns the sub-expressions of `expr` that are printed as `let` bindings,
+/// in the order of their definitions.
+fn get_shared_subexpressions(expr: &Rc<Expression>) -> Vec<Rc<Expression>> {
+    let order = get_topological_order(expr);
+    let mut nuses: HashMap<ExpressionRawPointer, usize> = HashMap::new();
+    for node in order.iter() {
+        for child in get_children(node) {
+            *nuses.entry(ExpressionRawPointer(child)).or_insert(0) += 1;
+        }
+    }
+
+    order.into_iter()
+         .filter(|node| {
+             !matches!(**node, Expression::Scalar(_) | Expression::Variable(_))
+             && nuses.get(&ExpressionRawPointer(node.clone()))
+                     .copied()
+                     .unwrap_or(0)
+                > 1
+         })
+         .collect()
+}
+
+/// Returns `expr` printed with line breaks such that the lines fit within
+/// `options.width` columns wherever possible. Lines are broken before binary
+/// operators, between call arguments and between subscript indices.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, pretty_print, PrettyOptions};
+///
+/// let expr = parse("f(alpha + beta, gamma) * (x if x > 0 else -x)");
+/// let options = PrettyOptions { width: 30,
+///                               ..PrettyOptions::default() };
+/// assert_eq!(pretty_print(&expr, &options),
+///            "f(alpha + beta, gamma)\n    * (x if x > 0 else -x)");
+/// ```
+pub fn pretty_print(expr: &Rc<Expression>, options: &PrettyOptions) -> String {
+    let shared = if options.let_bindings {
+        get_shared_subexpressions(expr)
+    } else {
+        vec![]
+    };
+
+    let mut mapper = PrettyPrinter { indent: options.indent,
+                                     bindings: HashMap::new(),
+                                     cache: HashMap::new() };
+    let mut lines = vec![];
+
+    if !shared.is_empty() {
+        let mut taken_names: HashSet<String> = HashSet::new();
+        for node in get_topological_order(expr) {
+            if let Expression::Variable(name) = &*node {
+                taken_names.insert(name.clone());
+            }
+        }
+
+        let mut counter = 0;
+        for node in shared {
+            let name = loop {
+                let name = format!("_t{}", counter);
+                counter += 1;
+                if !taken_names.contains(&name) {
+                    break name;
+                }
+            };
+            let doc = group(concat([text(format!("let {} =", name)),
+                                    nest(options.indent,
+                                         concat([line(), mapper.visit(&node, &0)])),
+                                    text(";")]));
+            lines.push(layout(&doc, options.width));
+            mapper.bindings.insert(ExpressionRawPointer(node), name);
+        }
+    }
+
+    lines.push(layout(&mapper.visit_operand(expr, 0), options.width));
+    lines.join("\n")
+}
+
+// vim: fdm=marker
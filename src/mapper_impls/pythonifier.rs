@@ -0,0 +1,477 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::functions::{default_registry, FunctionRegistry, PrintTarget};
+use crate::mapper_impls::stringifier::{
+    get_binary_op_precedence, guard_with_paren, PREC_ATOM, PREC_CMP, PREC_IF, PREC_LNOT, PREC_UNARY,
+};
+use crate::mappers::fold::FoldMapperWithContext;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use hashbrown::HashMap;
+use std::rc::Rc;
+
+/// Flavors of Python code emitted by [`to_python`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PythonDialect {
+    /// Code that is vectorized over NumPy arrays, expects NumPy imported as
+    /// `np`. If-then-else expressions are printed via `np.where`, logical
+    /// operations via `np.logical_and` and friends, and calls via the NumPy
+    /// names of the functions.
+    NumPy,
+    /// Code that is `eval`-ed in a namespace populated by executing
+    /// [`STRICT_PYTHON_PRELUDE`] and evaluates to the same value as
+    /// [`evaluate`](crate::evaluate). The arithmetic operations are printed
+    /// as calls to the helpers of the prelude, which wrap integers around on
+    /// 64-bit overflows and return infinities or NaNs where Python raises,
+    /// for ex. on a division by a float zero or in `math.log(0)`. Comparisons
+    /// and logical operations evaluate to the integers 0 or 1, logical
+    /// operations short-circuit and only the taken branch of an if-then-else
+    /// expression is evaluated. Where [`evaluate`](crate::evaluate) fails,
+    /// for ex. on an integer division by zero, the code raises.
+    StrictPython,
+}
+
+/// Python code defining the helpers used by the code printed in
+/// [`PythonDialect::StrictPython`].
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, to_python, PythonDialect, STRICT_PYTHON_PRELUDE};
+///
+/// assert_eq!(to_python(&parse("x // 2.0 + 1"), PythonDialect::StrictPython),
+///            "_sym_add(_sym_floordiv(x, 2.0), 1)");
+/// assert!(STRICT_PYTHON_PRELUDE.contains("def _sym_floordiv(a, b):"));
+/// ```
+pub const STRICT_PYTHON_PRELUDE: &str = r#"import math
+
+
+def _sym_int(x):
+    # wraps an integer around to the range of an i64
+    return (x + 0x8000000000000000) % 0x10000000000000000 - 0x8000000000000000
+
+
+def _sym_are_ints(a, b):
+    return isinstance(a, int) and isinstance(b, int)
+
+
+def _sym_neg(x):
+    return _sym_int(-x) if isinstance(x, int) else -x
+
+
+def _sym_add(a, b):
+    return _sym_int(a + b) if _sym_are_ints(a, b) else float(a) + float(b)
+
+
+def _sym_sub(a, b):
+    return _sym_int(a - b) if _sym_are_ints(a, b) else float(a) - float(b)
+
+
+def _sym_mul(a, b):
+    return _sym_int(a * b) if _sym_are_ints(a, b) else float(a) * float(b)
+
+
+def _sym_div(a, b):
+    a, b = float(a), float(b)
+    if b != 0.0:
+        return a / b
+    if a == 0.0 or math.isnan(a):
+        return math.nan
+    return math.copysign(math.inf, a) * math.copysign(1.0, b)
+
+
+def _sym_floor(x):
+    return float(math.floor(x)) if math.isfinite(x) else x
+
+
+def _sym_floordiv(a, b):
+    if _sym_are_ints(a, b):
+        return _sym_int(a // b)
+    return _sym_floor(_sym_div(a, b))
+
+
+def _sym_mod(a, b):
+    if _sym_are_ints(a, b):
+        return a % b
+    a, b = float(a), float(b)
+    if b == 0.0 or math.isinf(a) or math.isnan(a) or math.isnan(b):
+        return math.nan
+    r = math.fmod(a, b)
+    if r != 0.0 and (r < 0.0) != (b < 0.0):
+        r += b
+    return r
+
+
+def _sym_pow(a, b):
+    if _sym_are_ints(a, b) and b >= 0:
+        return _sym_int(pow(a, min(b, 0xFFFFFFFF), 0x10000000000000000))
+    a, b = float(a), float(b)
+    try:
+        return math.pow(a, b)
+    except OverflowError:
+        # negative only for a negative base and an odd exponent
+        return -math.inf if a < 0.0 and b % 2.0 == 1.0 else math.inf
+    except ValueError:
+        if a == 0.0:
+            # zero to a negative power
+            return math.copysign(math.inf, a) if b % 2.0 == 1.0 else math.inf
+        return math.nan
+
+
+def _sym_lshift(a, b):
+    return _sym_int(a << (b & 63))
+
+
+def _sym_rshift(a, b):
+    return a >> (b & 63)
+
+
+def _sym_compare(op, a, b):
+    return int(op(a, b)) if _sym_are_ints(a, b) else int(op(float(a), float(b)))
+
+
+def _sym_eq(a, b):
+    return _sym_compare(lambda x, y: x == y, a, b)
+
+
+def _sym_ne(a, b):
+    return _sym_compare(lambda x, y: x != y, a, b)
+
+
+def _sym_gt(a, b):
+    return _sym_compare(lambda x, y: x > y, a, b)
+
+
+def _sym_ge(a, b):
+    return _sym_compare(lambda x, y: x >= y, a, b)
+
+
+def _sym_lt(a, b):
+    return _sym_compare(lambda x, y: x < y, a, b)
+
+
+def _sym_le(a, b):
+    return _sym_compare(lambda x, y: x <= y, a, b)
+
+
+def _sym_call(f, *args):
+    if f is abs:
+        return _sym_int(abs(args[0])) if isinstance(args[0], int) else abs(float(args[0]))
+    if getattr(f, "__module__", None) != "math":
+        return f(*args)
+    args = [float(x) for x in args]
+    try:
+        return float(f(*args))
+    except ValueError:
+        # a domain error, -inf at the poles of the logarithms and NaN otherwise
+        if f in (math.log, math.log10) and args[0] == 0.0:
+            return -math.inf
+        return math.nan
+    except OverflowError:
+        if f in (math.sinh, math.floor, math.ceil):
+            return math.copysign(math.inf, args[0])
+        return math.inf
+"#;
+
+/// Returns the name of the helper in [`STRICT_PYTHON_PRELUDE`] computing
+/// `op`, or *None* if Python's operator has the semantics of
+/// [`evaluate`](crate::evaluate).
+fn get_strict_python_helper(op: BinaryOpType) -> Option<&'static str> {
+    let name = match op {
+        BinaryOpType::Sum => "_sym_add",
+        BinaryOpType::Subtract => "_sym_sub",
+        BinaryOpType::Product => "_sym_mul",
+        BinaryOpType::Divide => "_sym_div",
+        BinaryOpType::FloorDiv => "_sym_floordiv",
+        BinaryOpType::Modulo => "_sym_mod",
+
+        BinaryOpType::Equal => "_sym_eq",
+        BinaryOpType::NotEqual => "_sym_ne",
+        BinaryOpType::Greater => "_sym_gt",
+        BinaryOpType::GreaterEqual => "_sym_ge",
+        BinaryOpType::Less => "_sym_lt",
+        BinaryOpType::LessEqual => "_sym_le",
+
+        BinaryOpType::LeftShift => "_sym_lshift",
+        BinaryOpType::RightShift => "_sym_rshift",
+        BinaryOpType::Exponent => "_sym_pow",
+
+        BinaryOpType::BitwiseOr
+        | BinaryOpType::BitwiseXor
+        | BinaryOpType::BitwiseAnd
+        | BinaryOpType::LogicalAnd
+        | BinaryOpType::LogicalOr => return None,
+    };
+    Some(name)
+}
+
+struct Pythonifier<'a> {
+    dialect: PythonDialect,
+    registry: &'a FunctionRegistry,
+    cache: HashMap<(ExpressionRawPointer, u8), String>,
+}
+
+impl CachedMapper<(ExpressionRawPointer, u8), String> for Pythonifier<'_> {
+    fn query_cache(&self, key: &(ExpressionRawPointer, u8)) -> Option<&String> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: (ExpressionRawPointer, u8), value: String) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl Pythonifier<'_> {
+    fn module(&self) -> &str {
+        match self.dialect {
+            PythonDialect::NumPy => "np",
+            PythonDialect::StrictPython => "math",
+        }
+    }
+}
+
+impl FoldMapperWithContext for Pythonifier<'_> {
+    type Context = u8;
+    type Output = String;
+    type CacheKey = (ExpressionRawPointer, u8);
+
+    fn get_cache_key(&self, expr: &Rc<Expression>, outer_prec: &Self::Context) -> Self::CacheKey {
+        (ExpressionRawPointer(expr.clone()), *outer_prec)
+    }
+
+    fn map_scalar(&mut self, value: &LiteralT, outer_prec: &Self::Context) -> Self::Output {
+        let my_str = if value.is_integral() {
+            match self.dialect {
+                PythonDialect::NumPy => value.to_string(),
+                // literals are evaluated as i64s
+                PythonDialect::StrictPython => value.as_i64().to_string(),
+            }
+        } else {
+            let x = value.as_f64();
+            if x.is_nan() {
+                format!("{}.nan", self.module())
+            } else if x.is_infinite() && x > 0.0 {
+                format!("{}.inf", self.module())
+            } else if x.is_infinite() {
+                format!("-{}.inf", self.module())
+            } else {
+                // Debug always prints a decimal point or an exponent
+                match (value, self.dialect) {
+                    (LiteralT::F32(x), PythonDialect::NumPy) => format!("{:?}", x),
+                    _ => format!("{:?}", x),
+                }
+            }
+        };
+
+        if my_str.starts_with('-') {
+            guard_with_paren(my_str, PREC_UNARY, outer_prec)
+        } else {
+            my_str
+        }
+    }
+    fn map_variable(&mut self, name: String, _outer_prec: &Self::Context) -> Self::Output {
+        name
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>, outer_prec: &Self::Context)
+                    -> Self::Output {
+        match (op, self.dialect) {
+            (UnaryOpType::LogicalNot, PythonDialect::NumPy) => {
+                format!("np.logical_not({})", self.visit(x, &0))
+            }
+            (UnaryOpType::LogicalNot, PythonDialect::StrictPython) => {
+                format!("int(not {})", self.visit(x, &PREC_LNOT))
+            }
+            (UnaryOpType::BitwiseNot, _) => guard_with_paren(format!("~{}",
+                                                                     self.visit(x, &PREC_UNARY)),
+                                                             PREC_UNARY,
+                                                             outer_prec),
+            (UnaryOpType::Minus, PythonDialect::StrictPython) => {
+                format!("_sym_neg({})", self.visit(x, &0))
+            }
+            (UnaryOpType::Minus, PythonDialect::NumPy) => {
+                guard_with_paren(format!("-{}", self.visit(x, &PREC_UNARY)),
+                                 PREC_UNARY,
+                                 outer_prec)
+            }
+        }
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>,
+                     outer_prec: &Self::Context)
+                     -> Self::Output {
+        let my_prec = get_binary_op_precedence(op);
+
+        match (op, self.dialect) {
+            (BinaryOpType::LogicalAnd | BinaryOpType::LogicalOr, PythonDialect::NumPy) => {
+                let name = if op == BinaryOpType::LogicalAnd {
+                    "logical_and"
+                } else {
+                    "logical_or"
+                };
+                return format!("np.{}({}, {})",
+                               name,
+                               self.visit(left, &0),
+                               self.visit(right, &0));
+            }
+            (BinaryOpType::LogicalAnd | BinaryOpType::LogicalOr, PythonDialect::StrictPython) => {
+                let op_str = if op == BinaryOpType::LogicalAnd {
+                    "and"
+                } else {
+                    "or"
+                };
+                return format!("int(bool({} {} {}))",
+                               self.visit(left, &my_prec),
+                               op_str,
+                               self.visit(right, &(my_prec + 1)));
+            }
+            (BinaryOpType::FloorDiv, PythonDialect::NumPy) => {
+                return format!("np.floor_divide({}, {})",
+                               self.visit(left, &0),
+                               self.visit(right, &0));
+            }
+            (_, PythonDialect::StrictPython) => {
+                if let Some(helper) = get_strict_python_helper(op) {
+                    return format!("{}({}, {})",
+                                   helper,
+                                   self.visit(left, &0),
+                                   self.visit(right, &0));
+                }
+            }
+            _ => {}
+        }
+
+        let op_str = match op {
+            BinaryOpType::Sum => "+",
+            BinaryOpType::Subtract => "-",
+            BinaryOpType::Product => "*",
+            BinaryOpType::Divide => "/",
+            BinaryOpType::FloorDiv => "//",
+            BinaryOpType::Modulo => "%",
+
+            BinaryOpType::Equal => "==",
+            BinaryOpType::NotEqual => "!=",
+            BinaryOpType::Greater => ">",
+            BinaryOpType::GreaterEqual => ">=",
+            BinaryOpType::Less => "<",
+            BinaryOpType::LessEqual => "<=",
+
+            BinaryOpType::BitwiseOr => "|",
+            BinaryOpType::BitwiseXor => "^",
+            BinaryOpType::BitwiseAnd => "&",
+
+            BinaryOpType::LeftShift => "<<",
+            BinaryOpType::RightShift => ">>",
+
+            BinaryOpType::Exponent => "**",
+
+            BinaryOpType::LogicalAnd | BinaryOpType::LogicalOr => unreachable!(),
+        };
+
+        let (left_prec, right_prec) = match op {
+            // right-to-left associative
+            BinaryOpType::Exponent => (my_prec + 1, my_prec),
+            // Python chains comparisons, i.e. 'a < b < c' is not '(a < b) < c'
+            _ if my_prec == PREC_CMP => (my_prec + 1, my_prec + 1),
+            // left-to-right associative
+            _ => (my_prec, my_prec + 1),
+        };
+        let my_str = format!("{} {} {}",
+                             self.visit(left, &left_prec),
+                             op_str,
+                             self.visit(right, &right_prec));
+        guard_with_paren(my_str, my_prec, outer_prec)
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT,
+                _outer_prec: &Self::Context)
+                -> Self::Output {
+        let target = match self.dialect {
+            PythonDialect::NumPy => PrintTarget::NumPy,
+            PythonDialect::StrictPython => PrintTarget::Python,
+        };
+        let (callee, is_registered) = match &**call {
+            Expression::Variable(name) => match self.registry.get(name) {
+                Some(function) => (function.printed_name(target).to_string(), true),
+                None => (name.clone(), false),
+            },
+            _ => (self.visit(call, &PREC_ATOM), false),
+        };
+        let rec_str: Vec<String> = params.iter().map(|x| self.visit(x, &0)).collect();
+        if is_registered && self.dialect == PythonDialect::StrictPython {
+            // math functions return floats and do not raise, as in evaluate
+            format!("_sym_call({}, {})", callee, rec_str.join(", "))
+        } else {
+            format!("{}({})", callee, rec_str.join(", "))
+        }
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT,
+                     _outer_prec: &Self::Context)
+                     -> Self::Output {
+        let rec_str: Vec<String> = indices.iter().map(|x| self.visit(x, &0)).collect();
+        format!("{}[{}]", self.visit(agg, &PREC_ATOM), rec_str.join(", "))
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>,
+              outer_prec: &Self::Context)
+              -> Self::Output {
+        match self.dialect {
+            PythonDialect::NumPy => format!("np.where({}, {}, {})",
+                                            self.visit(cond, &0),
+                                            self.visit(then, &0),
+                                            self.visit(else_, &0)),
+            PythonDialect::StrictPython => {
+                let inner_prec = &(PREC_IF + 1);
+                let my_str = format!("{} if {} else {}",
+                                     self.visit(then, inner_prec),
+                                     self.visit(cond, inner_prec),
+                                     self.visit(else_, inner_prec));
+                guard_with_paren(my_str, PREC_IF, outer_prec)
+            }
+        }
+    }
+}
+
+/// Returns the Python code for `expr` in `dialect`. Calls to functions in the
+/// [default registry](crate::functions::default_registry) are printed using
+/// their Python or NumPy names, other calls are printed verbatim.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, to_python, PythonDialect};
+///
+/// let expr = parse("sin(x) if x // 2 < y else 0");
+/// assert_eq!(to_python(&expr, PythonDialect::NumPy),
+///            "np.where(np.floor_divide(x, 2) < y, np.sin(x), 0)");
+/// assert_eq!(to_python(&expr, PythonDialect::StrictPython),
+///            "_sym_call(math.sin, x) if _sym_lt(_sym_floordiv(x, 2), y) else 0");
+/// ```
+pub fn to_python(expr: &Rc<Expression>, dialect: PythonDialect) -> String {
+    to_python_with_registry(expr, dialect, default_registry())
+}
+
+/// Returns the Python code for `expr` with its calls printed using the names
+/// of the functions in `registry`. Also see [`to_python`].
+pub fn to_python_with_registry(expr: &Rc<Expression>, dialect: PythonDialect,
+                               registry: &FunctionRegistry)
+                               -> String {
+    let mut mapper = Pythonifier { dialect,
+                                   registry,
+                                   cache: HashMap::new() };
+    mapper.visit(expr, &0)
+}
+
+// vim: fdm=marker
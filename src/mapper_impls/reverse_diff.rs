@@ -0,0 +1,240 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Reverse-mode (adjoint) differentiation of expressions.
+
+use crate::functions::{default_registry, FunctionRegistry};
+use crate::operations as ops;
+use crate::operations::ConvertibleToExpr;
+use crate::primitives::{BinaryOpType, Expression, UnaryOpType};
+use crate::utils::{get_topological_order, ExpressionRawPointer};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// A term of the sum that makes up the adjoint of a node.
+struct Contribution {
+    negate: bool,
+    term: Rc<Expression>,
+}
+
+fn is_one(expr: &Expression) -> bool {
+    match expr {
+        Expression::Scalar(x) => x.as_f64() == 1.0,
+        _ => false,
+    }
+}
+
+/// Returns `adjoint * factor`, eliding multiplications by one.
+fn scale(adjoint: &Rc<Expression>, factor: Rc<Expression>) -> Rc<Expression> {
+    if is_one(adjoint) {
+        factor
+    } else {
+        ops::mul(adjoint, &factor)
+    }
+}
+
+fn accumulate(contributions: Vec<Contribution>) -> Rc<Expression> {
+    let mut iter = contributions.into_iter();
+    let first = iter.next().unwrap();
+    let init = if first.negate {
+        ops::neg(&first.term)
+    } else {
+        first.term
+    };
+    iter.fold(init, |acc, x| {
+            if x.negate {
+                ops::sub(&acc, &x.term)
+            } else {
+                ops::add(&acc, &x.term)
+            }
+        })
+}
+
+fn is_differentiable(op: BinaryOpType) -> bool {
+    matches!(op,
+             BinaryOpType::Sum
+             | BinaryOpType::Subtract
+             | BinaryOpType::Product
+             | BinaryOpType::Divide
+             | BinaryOpType::Modulo
+             | BinaryOpType::Exponent)
+}
+
+/// Returns the expressions for the gradient of `expr` w.r.t. the variables
+/// `wrt`, i.e. the `i`-th returned expression is the derivative of `expr`
+/// w.r.t. `wrt[i]`.
+///
+/// The gradient is built by accumulating adjoints in a single reverse
+/// topological sweep over the unique nodes of `expr`. Each adjoint is built
+/// only once and the returned expressions refer to the nodes of `expr` for the
+/// intermediate primal values, so that the result can be fed to common
+/// sub-expression elimination or code generation. Calls are differentiated
+/// with the derivative rules of [`default_registry`]. Subscripts and operations
+/// that are piecewise constant in their operands, such as comparisons, are
+/// treated as constants.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{get_gradient, parse};
+///
+/// let grad = get_gradient(&parse("x*y + sin(x)"), &["x", "y"]);
+/// assert_eq!(grad[0], parse("cos(x) + y"));
+/// assert_eq!(grad[1], parse("x"));
+/// ```
+pub fn get_gradient<T: ToString>(expr: &Rc<Expression>, wrt: &[T]) -> Vec<Rc<Expression>> {
+    get_gradient_with_registry(expr, wrt, default_registry())
+}
+
+/// Same as [`get_gradient`], but differentiates the calls with the derivative
+/// rules of the functions in `registry`.
+pub fn get_gradient_with_registry<T: ToString>(expr: &Rc<Expression>, wrt: &[T],
+                                               registry: &FunctionRegistry)
+                                               -> Vec<Rc<Expression>> {
+    let wrt: Vec<String> = wrt.iter().map(|x| x.to_string()).collect();
+    let order = get_topological_order(expr);
+
+    // {{{ find the nodes that depend on the variables in 'wrt'
+
+    let mut active: HashSet<ExpressionRawPointer> = HashSet::new();
+    for node in order.iter() {
+        let is_active = match &**node {
+            Expression::Scalar(_) | Expression::Subscript(_, _) => false,
+            Expression::Variable(name) => wrt.contains(name),
+            Expression::UnaryOp(op, x) => {
+                (*op == UnaryOpType::Minus) && active.contains(&ExpressionRawPointer(x.clone()))
+            }
+            Expression::BinaryOp(left, op, right) => {
+                is_differentiable(*op)
+                && (active.contains(&ExpressionRawPointer(left.clone()))
+                    || active.contains(&ExpressionRawPointer(right.clone())))
+            }
+            Expression::Call(_, params) => {
+                params.iter()
+                      .any(|x| active.contains(&ExpressionRawPointer(x.clone())))
+            }
+            Expression::If(_, then, else_) => {
+                active.contains(&ExpressionRawPointer(then.clone()))
+                || active.contains(&ExpressionRawPointer(else_.clone()))
+            }
+        };
+        if is_active {
+            active.insert(ExpressionRawPointer(node.clone()));
+        }
+    }
+
+    // }}}
+
+    // {{{ reverse sweep
+
+    let mut contributions: HashMap<ExpressionRawPointer, Vec<Contribution>> = HashMap::new();
+    let mut variable_contributions: HashMap<String, Vec<Contribution>> = HashMap::new();
+    contributions.insert(ExpressionRawPointer(expr.clone()),
+                         vec![Contribution { negate: false,
+                                             term: 1.to_expr() }]);
+
+    for node in order.iter().rev() {
+        let key = ExpressionRawPointer(node.clone());
+        if !active.contains(&key) {
+            continue;
+        }
+        let adjoint = match contributions.remove(&key) {
+            Some(x) => accumulate(x),
+            None => continue,
+        };
+
+        let mut push = |child: &Rc<Expression>, negate: bool, term: Rc<Expression>| {
+            let child_key = ExpressionRawPointer(child.clone());
+            if active.contains(&child_key) {
+                contributions.entry(child_key)
+                             .or_default()
+                             .push(Contribution { negate, term });
+            }
+        };
+
+        match &**node {
+            Expression::Variable(name) => {
+                variable_contributions.entry(name.clone())
+                                      .or_default()
+                                      .push(Contribution { negate: false,
+                                                           term: adjoint });
+            }
+            Expression::UnaryOp(_, x) => push(x, true, adjoint),
+            Expression::BinaryOp(left, op, right) => match op {
+                BinaryOpType::Sum => {
+                    push(left, false, adjoint.clone());
+                    push(right, false, adjoint);
+                }
+                BinaryOpType::Subtract => {
+                    push(left, false, adjoint.clone());
+                    push(right, true, adjoint);
+                }
+                BinaryOpType::Product => {
+                    push(left, false, scale(&adjoint, right.clone()));
+                    push(right, false, scale(&adjoint, left.clone()));
+                }
+                BinaryOpType::Divide => {
+                    push(left, false, ops::div(&adjoint, right));
+                    push(right, true, scale(&adjoint, ops::div(node, right)));
+                }
+                BinaryOpType::Modulo => {
+                    push(left, false, adjoint.clone());
+                    push(right, true, scale(&adjoint, ops::floor_div(left, right)));
+                }
+                BinaryOpType::Exponent => {
+                    let dleft = ops::mul(right, &ops::pow(left, &ops::sub(right, &1)));
+                    push(left, false, scale(&adjoint, dleft));
+                    let dright = ops::mul(node, &ops::call(crate::var("log"), [left.clone()]));
+                    push(right, false, scale(&adjoint, dright));
+                }
+                _ => unreachable!(),
+            },
+            Expression::Call(call, params) => {
+                let function = registry.expect_callee(call, params);
+                let partials = match function.symbolic_partials {
+                    Some(x) => x(params),
+                    None => panic!("No derivative rule known for '{}'.", function.name),
+                };
+                for (param, partial) in params.iter().zip(partials) {
+                    push(param, false, scale(&adjoint, partial));
+                }
+            }
+            Expression::If(cond, then, else_) => {
+                push(then,
+                     false,
+                     ops::ifthenelse(cond.clone(), adjoint.clone(), 0.to_expr()));
+                push(else_,
+                     false,
+                     ops::ifthenelse(cond.clone(), 0.to_expr(), adjoint));
+            }
+            Expression::Scalar(_) | Expression::Subscript(_, _) => unreachable!(),
+        }
+    }
+
+    // }}}
+
+    wrt.iter()
+       .map(|name| match variable_contributions.remove(name) {
+           Some(x) => accumulate(x),
+           None => 0.to_expr(),
+       })
+       .collect()
+}
+
+// vim: fdm=marker
@@ -0,0 +1,661 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::functions::{default_registry, FunctionRegistry, PrintTarget};
+use crate::mapper_impls::type_inference::{
+    get_common_dtype, get_integral_constant, get_integral_value,
+};
+use crate::mappers::fold::FoldMapper;
+use crate::mappers::CachedMapper;
+use crate::operations as ops;
+use crate::primitives::{BinaryOpType, DType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use hashbrown::HashMap;
+use std::rc::Rc;
+
+// {{{ signature
+
+/// Semantics of [`BinaryOpType::FloorDiv`] and [`BinaryOpType::Modulo`] in
+/// the code emitted by [`to_rust_fn`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DivModSemantics {
+    /// Quotients are rounded towards negative infinity and remainders take
+    /// the sign of the divisor, as in Python.
+    Python,
+    /// Quotients are rounded towards zero and remainders take the sign of the
+    /// dividend, as with Rust's `/` and `%` on integers.
+    Rust,
+}
+
+/// Type of an argument of the function emitted by [`to_rust_fn`].
+#[derive(Clone)]
+pub enum RustArgType {
+    /// A scalar passed by value.
+    Scalar(DType),
+    /// A slice `&[T]` of elements of type `DType` that is indexed via
+    /// [`Expression::Subscript`]. The element `a[i, j]` is read from
+    /// `a[i*strides[0] + j*strides[1]]`.
+    Slice(DType, Vec<Rc<Expression>>),
+}
+
+/// Signature of the function emitted by [`to_rust_fn`].
+#[derive(Clone)]
+pub struct RustSignature {
+    pub name: String,
+    /// `(name, type)` of the function's arguments, in order. Every variable
+    /// of the expression must be an argument.
+    pub args: Vec<(String, RustArgType)>,
+    /// Type of the returned value. If *None*, the type inferred for the
+    /// expression is returned.
+    pub return_dtype: Option<DType>,
+    pub div_mod_semantics: DivModSemantics,
+}
+
+impl RustSignature {
+    /// Returns the signature of a function `name` taking `args`, returning the
+    /// inferred type with Python's floor division and modulo semantics.
+    pub fn new<T: ToString>(name: T, args: Vec<(String, RustArgType)>) -> Self {
+        RustSignature { name: name.to_string(),
+                        args,
+                        return_dtype: None,
+                        div_mod_semantics: DivModSemantics::Python }
+    }
+}
+
+// }}}
+
+// {{{ typed code snippets
+
+// Precedence levels of Rust's operators. Operations with a higher level bind
+// tighter.
+const PREC_BLOCK: u8 = 0;
+const PREC_LOR: u8 = 1;
+const PREC_LAND: u8 = 2;
+const PREC_CMP: u8 = 3;
+const PREC_BOR: u8 = 4;
+const PREC_BXOR: u8 = 5;
+const PREC_BAND: u8 = 6;
+const PREC_SHIFT: u8 = 7;
+const PREC_ADD: u8 = 8;
+const PREC_MUL: u8 = 9;
+const PREC_CAST: u8 = 10;
+const PREC_UNARY: u8 = 11;
+const PREC_ATOM: u8 = 12;
+
+/// Type of a value in the emitted code.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum RustType {
+    Bool,
+    Num(DType),
+}
+
+#[derive(Clone)]
+struct Code {
+    text: String,
+    ty: RustType,
+    /// Precedence level of the outermost operation in `text`.
+    prec: u8,
+    /// Value of the code if it is a literal.
+    value: Option<LiteralT>,
+}
+
+impl Code {
+    fn new(text: String, ty: RustType, prec: u8) -> Self {
+        Code { text,
+               ty,
+               prec,
+               value: None }
+    }
+
+    /// Returns the code as an operand of an operation with precedence level
+    /// `outer_prec`.
+    fn operand(&self, outer_prec: u8) -> String {
+        if outer_prec > self.prec {
+            format!("({})", self.text)
+        } else {
+            self.text.clone()
+        }
+    }
+
+    /// Returns the code as the left operand of `<`, `<=` or `<<`, that would
+    /// be parsed as the start of generic arguments following a cast.
+    fn lt_operand(&self, outer_prec: u8) -> String {
+        if self.prec == PREC_CAST {
+            format!("({})", self.text)
+        } else {
+            self.operand(outer_prec)
+        }
+    }
+
+    fn dtype(&self) -> DType {
+        match self.ty {
+            RustType::Num(dtype) => dtype,
+            RustType::Bool => panic!("'{}' is a boolean, expected a number", self.text),
+        }
+    }
+
+    fn cast(&self, dtype: DType) -> Code {
+        if let Some(literal) = self.value.as_ref().and_then(|x| get_literal(x, dtype)) {
+            return literal;
+        }
+        let text = match self.ty {
+            RustType::Num(x) if x == dtype => return self.clone(),
+            RustType::Bool if !dtype.is_integral() => {
+                format!("{} as u8 as {}",
+                        self.operand(PREC_CAST),
+                        get_type_name(dtype))
+            }
+            _ => format!("{} as {}", self.operand(PREC_CAST), get_type_name(dtype)),
+        };
+        Code::new(text, RustType::Num(dtype), PREC_CAST)
+    }
+
+    fn to_num(&self) -> Code {
+        match self.ty {
+            RustType::Num(_) => self.clone(),
+            RustType::Bool => self.cast(DType::I64),
+        }
+    }
+
+    fn to_bool(&self) -> Code {
+        match self.ty {
+            RustType::Bool => self.clone(),
+            RustType::Num(dtype) => {
+                let zero = if dtype.is_integral() { "0" } else { "0.0" };
+                Code::new(format!("{} != {}", self.operand(PREC_CMP + 1), zero),
+                          RustType::Bool,
+                          PREC_CMP)
+            }
+        }
+    }
+}
+
+fn get_type_name(dtype: DType) -> &'static str {
+    match dtype {
+        DType::U8 => "u8",
+        DType::U16 => "u16",
+        DType::U32 => "u32",
+        DType::U64 => "u64",
+
+        DType::I8 => "i8",
+        DType::I16 => "i16",
+        DType::I32 => "i32",
+        DType::I64 => "i64",
+
+        DType::F32 => "f32",
+        DType::F64 => "f64",
+    }
+}
+
+fn is_signed(dtype: DType) -> bool {
+    !matches!(dtype, DType::U8 | DType::U16 | DType::U32 | DType::U64)
+}
+
+fn get_float_literal<T: std::fmt::Debug>(value: T, is_nan: bool, is_infinite: bool,
+                                         is_positive: bool, dtype: DType)
+                                         -> String {
+    let type_name = get_type_name(dtype);
+    if is_nan {
+        format!("{}::NAN", type_name)
+    } else if is_infinite && is_positive {
+        format!("{}::INFINITY", type_name)
+    } else if is_infinite {
+        format!("{}::NEG_INFINITY", type_name)
+    } else {
+        format!("{:?}_{}", value, type_name)
+    }
+}
+
+/// Returns the smallest and the largest values of the integral type `dtype`.
+fn get_integral_range(dtype: DType) -> (i128, i128) {
+    match dtype {
+        DType::U8 => (0, u8::MAX as i128),
+        DType::U16 => (0, u16::MAX as i128),
+        DType::U32 => (0, u32::MAX as i128),
+        DType::U64 => (0, u64::MAX as i128),
+
+        DType::I8 => (i8::MIN as i128, i8::MAX as i128),
+        DType::I16 => (i16::MIN as i128, i16::MAX as i128),
+        DType::I32 => (i32::MIN as i128, i32::MAX as i128),
+        DType::I64 => (i64::MIN as i128, i64::MAX as i128),
+
+        DType::F32 | DType::F64 => unreachable!(),
+    }
+}
+
+/// Returns the code for the literal `value` of type `dtype`. Returns *None*
+/// if `value` cannot be written as a literal of type `dtype` without loss,
+/// i.e. if `value` is a floating point number and `dtype` is integral, or if
+/// `value` is out of the range of `dtype`.
+fn get_literal(value: &LiteralT, dtype: DType) -> Option<Code> {
+    let text = match dtype {
+        DType::F32 => {
+            let x = match value {
+                LiteralT::F32(x) => *x,
+                _ => value.as_f64() as f32,
+            };
+            get_float_literal(x, x.is_nan(), x.is_infinite(), x > 0.0, dtype)
+        }
+        DType::F64 => {
+            let x = value.as_f64();
+            get_float_literal(x, x.is_nan(), x.is_infinite(), x > 0.0, dtype)
+        }
+        _ if value.is_integral() => {
+            let x = get_integral_value(value);
+            let (lo, hi) = get_integral_range(dtype);
+            if x < lo || x > hi {
+                return None;
+            }
+            format!("{}_{}", x, get_type_name(dtype))
+        }
+        _ => return None,
+    };
+
+    let prec = if text.starts_with('-') {
+        PREC_UNARY
+    } else {
+        PREC_ATOM
+    };
+    Some(Code { value: Some(*value),
+                ..Code::new(text, RustType::Num(dtype), prec) })
+}
+
+// }}}
+
+struct RustEmitter<'a> {
+    signature: &'a RustSignature,
+    registry: &'a FunctionRegistry,
+    cache: HashMap<ExpressionRawPointer, Code>,
+}
+
+impl CachedMapper<ExpressionRawPointer, Code> for RustEmitter<'_> {
+    fn query_cache(&self, key: &ExpressionRawPointer) -> Option<&Code> {
+        self.cache.get(key)
+    }
+    fn add_to_cache(&mut self, key: ExpressionRawPointer, value: Code) {
+        self.cache.insert(key, value);
+    }
+}
+
+impl RustEmitter<'_> {
+    fn get_arg_type(&self, name: &str) -> &RustArgType {
+        match self.signature.args.iter().find(|(arg, _)| arg == name) {
+            Some((_, arg_type)) => arg_type,
+            None => panic!("Variable '{}' not found in the signature.", name),
+        }
+    }
+
+    fn emit_floor_div(&self, left: &Code, right: &Code, dtype: DType) -> Code {
+        let ty = RustType::Num(dtype);
+        let quotient = format!("{} / {}",
+                               left.operand(PREC_MUL),
+                               right.operand(PREC_MUL + 1));
+        match self.signature.div_mod_semantics {
+            DivModSemantics::Python if !dtype.is_integral() => {
+                Code::new(format!("({}).floor()", quotient), ty, PREC_ATOM)
+            }
+            DivModSemantics::Python if is_signed(dtype) => {
+                Code::new(format!("{{ let (a, b) = ({}, {}); let q = a / b; \
+                                   if a % b != 0 && ((a % b < 0) != (b < 0)) {{ q - 1 }} \
+                                   else {{ q }} }}",
+                                  left.text, right.text),
+                          ty,
+                          PREC_BLOCK)
+            }
+            DivModSemantics::Rust if !dtype.is_integral() => {
+                Code::new(format!("({}).trunc()", quotient), ty, PREC_ATOM)
+            }
+            _ => Code::new(quotient, ty, PREC_MUL),
+        }
+    }
+
+    fn emit_modulo(&self, left: &Code, right: &Code, dtype: DType) -> Code {
+        let ty = RustType::Num(dtype);
+        let zero = if dtype.is_integral() { "0" } else { "0.0" };
+        match self.signature.div_mod_semantics {
+            DivModSemantics::Python if is_signed(dtype) => {
+                Code::new(format!("{{ let (a, b) = ({}, {}); let r = a % b; \
+                                   if r != {zero} && ((r < {zero}) != (b < {zero})) \
+                                   {{ r + b }} else {{ r }} }}",
+                                  left.text,
+                                  right.text,
+                                  zero = zero),
+                          ty,
+                          PREC_BLOCK)
+            }
+            _ => Code::new(format!("{} % {}",
+                                   left.operand(PREC_MUL),
+                                   right.operand(PREC_MUL + 1)),
+                           ty,
+                           PREC_MUL),
+        }
+    }
+
+    fn emit_exponent(&self, base: &Code, exponent: &Code, exponent_expr: &Expression) -> Code {
+        let (base_dtype, exp_dtype) = (base.dtype(), exponent.dtype());
+        let constant_exponent = get_integral_constant(exponent_expr);
+
+        let (text, dtype) = if base_dtype.is_integral() && exp_dtype.is_integral() {
+            let exponent = match constant_exponent {
+                // negative powers of integers are floating point numbers, as
+                // in evaluate
+                Some(n) if n < 0 => {
+                    return self.emit_exponent(&base.cast(DType::F64), exponent, exponent_expr)
+                }
+                Some(n) => n.min(u32::MAX as i128).to_string(),
+                None => exponent.cast(DType::U32).text,
+            };
+            (format!("{}.pow({})", base.operand(PREC_ATOM), exponent), base_dtype)
+        } else if exp_dtype.is_integral()
+                  && constant_exponent.map_or(true, |n| i32::try_from(n).is_ok())
+        {
+            let exponent = match constant_exponent {
+                Some(n) => n.to_string(),
+                None => exponent.cast(DType::I32).text,
+            };
+            (format!("{}.powi({})", base.operand(PREC_ATOM), exponent), base_dtype)
+        } else {
+            let dtype = get_common_dtype(base_dtype, exp_dtype);
+            (format!("{}.powf({})",
+                     base.cast(dtype).operand(PREC_ATOM),
+                     exponent.cast(dtype).text),
+             dtype)
+        };
+        Code::new(text, RustType::Num(dtype), PREC_ATOM)
+    }
+}
+
+impl FoldMapper for RustEmitter<'_> {
+    type Output = Code;
+
+    fn map_scalar(&mut self, value: &LiteralT) -> Self::Output {
+        get_literal(value, value.dtype()).unwrap()
+    }
+    fn map_variable(&mut self, name: String) -> Self::Output {
+        match self.get_arg_type(&name) {
+            RustArgType::Scalar(dtype) => Code::new(name, RustType::Num(*dtype), PREC_ATOM),
+            RustArgType::Slice(..) => panic!("Slice '{}' must be subscripted.", name),
+        }
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Self::Output {
+        let x = self.visit(x);
+        let (x, op_str) = match op {
+            UnaryOpType::LogicalNot => (x.to_bool(), "!"),
+            UnaryOpType::BitwiseNot => (x.to_num(), "!"),
+            UnaryOpType::Minus => (x.to_num(), "-"),
+        };
+        if op == UnaryOpType::BitwiseNot && !x.dtype().is_integral() {
+            panic!("BitwiseNot is not defined for the floating point operand '{}'.",
+                   x.text);
+        }
+        if op == UnaryOpType::Minus && !is_signed(x.dtype()) {
+            // rustc rejects negating an unsigned integer
+            return Code::new(format!("{}.wrapping_neg()", x.operand(PREC_ATOM)),
+                             x.ty,
+                             PREC_ATOM);
+        }
+        Code::new(format!("{}{}", op_str, x.operand(PREC_UNARY)),
+                  x.ty,
+                  PREC_UNARY)
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
+                     -> Self::Output {
+        let left_code = self.visit(left);
+        let right_code = self.visit(right);
+
+        match op {
+            BinaryOpType::LogicalAnd | BinaryOpType::LogicalOr => {
+                let (op_str, prec) = if op == BinaryOpType::LogicalAnd {
+                    ("&&", PREC_LAND)
+                } else {
+                    ("||", PREC_LOR)
+                };
+                return Code::new(format!("{} {} {}",
+                                         left_code.to_bool().operand(prec),
+                                         op_str,
+                                         right_code.to_bool().operand(prec + 1)),
+                                 RustType::Bool,
+                                 prec);
+            }
+            BinaryOpType::LeftShift | BinaryOpType::RightShift => {
+                let left_code = left_code.to_num();
+                if !left_code.dtype().is_integral() || !right_code.to_num().dtype().is_integral() {
+                    panic!("{} is not defined for the floating point operands '{}', '{}'.",
+                           op, left_code.text, right_code.text);
+                }
+                let op_str = if op == BinaryOpType::LeftShift {
+                    "<<"
+                } else {
+                    ">>"
+                };
+                return Code::new(format!("{} {} {}",
+                                         left_code.lt_operand(PREC_SHIFT),
+                                         op_str,
+                                         right_code.cast(DType::U32).operand(PREC_SHIFT + 1)),
+                                 left_code.ty,
+                                 PREC_SHIFT);
+            }
+            BinaryOpType::Exponent => {
+                return self.emit_exponent(&left_code.to_num(), &right_code.to_num(), right);
+            }
+            _ => {}
+        }
+
+        let (left_code, right_code) = (left_code.to_num(), right_code.to_num());
+        let dtype = match op {
+            BinaryOpType::Divide
+                if left_code.dtype() == DType::F32 && right_code.dtype() == DType::F32 =>
+            {
+                DType::F32
+            }
+            BinaryOpType::Divide => DType::F64,
+            _ => get_common_dtype(left_code.dtype(), right_code.dtype()),
+        };
+        let (left_code, right_code) = (left_code.cast(dtype), right_code.cast(dtype));
+        if matches!(op,
+                    BinaryOpType::BitwiseOr | BinaryOpType::BitwiseXor | BinaryOpType::BitwiseAnd)
+           && !dtype.is_integral()
+        {
+            panic!("{} is not defined for the floating point operands '{}', '{}'.",
+                   op, left_code.text, right_code.text);
+        }
+        if op == BinaryOpType::Subtract && !is_signed(dtype) {
+            // an unsigned subtraction panics on underflow in debug builds
+            return Code::new(format!("{}.wrapping_sub({})",
+                                     left_code.operand(PREC_ATOM),
+                                     right_code.text),
+                             RustType::Num(dtype),
+                             PREC_ATOM);
+        }
+
+        let (op_str, ty, prec) = match op {
+            BinaryOpType::Sum => ("+", RustType::Num(dtype), PREC_ADD),
+            BinaryOpType::Subtract => ("-", RustType::Num(dtype), PREC_ADD),
+            BinaryOpType::Product => ("*", RustType::Num(dtype), PREC_MUL),
+            BinaryOpType::Divide => ("/", RustType::Num(dtype), PREC_MUL),
+            BinaryOpType::FloorDiv => return self.emit_floor_div(&left_code, &right_code, dtype),
+            BinaryOpType::Modulo => return self.emit_modulo(&left_code, &right_code, dtype),
+
+            BinaryOpType::Equal => ("==", RustType::Bool, PREC_CMP),
+            BinaryOpType::NotEqual => ("!=", RustType::Bool, PREC_CMP),
+            BinaryOpType::Greater => (">", RustType::Bool, PREC_CMP),
+            BinaryOpType::GreaterEqual => (">=", RustType::Bool, PREC_CMP),
+            BinaryOpType::Less => ("<", RustType::Bool, PREC_CMP),
+            BinaryOpType::LessEqual => ("<=", RustType::Bool, PREC_CMP),
+
+            BinaryOpType::BitwiseOr => ("|", RustType::Num(dtype), PREC_BOR),
+            BinaryOpType::BitwiseXor => ("^", RustType::Num(dtype), PREC_BXOR),
+            BinaryOpType::BitwiseAnd => ("&", RustType::Num(dtype), PREC_BAND),
+
+            BinaryOpType::LogicalAnd
+            | BinaryOpType::LogicalOr
+            | BinaryOpType::LeftShift
+            | BinaryOpType::RightShift
+            | BinaryOpType::Exponent => unreachable!(),
+        };
+
+        // comparisons are non-associative in Rust
+        let left_str = match op {
+            BinaryOpType::Less | BinaryOpType::LessEqual => left_code.lt_operand(prec + 1),
+            _ if prec == PREC_CMP => left_code.operand(prec + 1),
+            _ => left_code.operand(prec),
+        };
+        Code::new(format!("{} {} {}", left_str, op_str, right_code.operand(prec + 1)),
+                  ty,
+                  prec)
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Self::Output {
+        let function = self.registry.expect_callee(call, params);
+        let args: Vec<Code> = params.iter().map(|x| self.visit(x).to_num()).collect();
+        let dtypes: Vec<DType> = args.iter().map(|x| x.dtype()).collect();
+        let dtype = (function.result_dtype)(&dtypes);
+
+        let text = match function.printed_names.get(&PrintTarget::Rust) {
+            Some(name) if name.starts_with('.') => {
+                let args: Vec<Code> = args.iter().map(|x| x.cast(dtype)).collect();
+                let rest: Vec<String> = args[1..].iter().map(|x| x.text.clone()).collect();
+                format!("{}{}({})",
+                        args[0].operand(PREC_ATOM),
+                        name,
+                        rest.join(", "))
+            }
+            _ => {
+                let args: Vec<String> = args.iter().map(|x| x.text.clone()).collect();
+                format!("{}({})",
+                        function.printed_name(PrintTarget::Rust),
+                        args.join(", "))
+            }
+        };
+        Code::new(text, RustType::Num(dtype), PREC_ATOM)
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT) -> Self::Output {
+        let name = match &**agg {
+            Expression::Variable(name) => name,
+            _ => panic!("Only slice arguments can be subscripted, got '{}'.", agg),
+        };
+        let (dtype, strides) = match self.get_arg_type(name) {
+            RustArgType::Slice(dtype, strides) => (*dtype, strides.clone()),
+            RustArgType::Scalar(_) => panic!("Scalar '{}' cannot be subscripted.", name),
+        };
+        if strides.len() != indices.len() {
+            panic!("'{}' has {} strides, but is indexed with {} indices.",
+                   name,
+                   strides.len(),
+                   indices.len());
+        }
+
+        let linear_index =
+            indices.iter()
+                   .zip(strides.iter())
+                   .map(|(idx, stride)| match &**stride {
+                       Expression::Scalar(x) if x.is_integral() && x.as_i64() == 1 => idx.clone(),
+                       _ => ops::mul(idx, stride),
+                   })
+                   .reduce(|acc, x| ops::add(&acc, &x))
+                   .expect("Subscripts must have at least one index.");
+        let index = self.visit(&linear_index).to_num();
+        Code::new(format!("{}[{} as usize]", name, index.operand(PREC_CAST)),
+                  RustType::Num(dtype),
+                  PREC_ATOM)
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
+              -> Self::Output {
+        let cond = self.visit(cond).to_bool();
+        let (then, else_) = (self.visit(then), self.visit(else_));
+        let (then, else_) = if then.ty == else_.ty {
+            (then, else_)
+        } else {
+            let dtype = get_common_dtype(then.to_num().dtype(), else_.to_num().dtype());
+            (then.cast(dtype), else_.cast(dtype))
+        };
+        Code::new(format!("if {} {{ {} }} else {{ {} }}",
+                          cond.text, then.text, else_.text),
+                  then.ty,
+                  PREC_BLOCK)
+    }
+}
+
+/// Returns the Rust source of a function with the signature `signature` that
+/// evaluates `expr`. Calls are emitted using the Rust names of the functions
+/// in the [default registry](crate::functions::default_registry), for ex.
+/// `sin(x)` is emitted as `x.sin()`.
+///
+/// Operands of an operation with different types are converted to `i64` if
+/// both are integers and to `f64` otherwise. Literals are emitted directly in
+/// the converted type. Divisions are always performed in floating point
+/// arithmetic. Negations and subtractions of unsigned integers wrap around.
+/// Integers raised to a negative literal power are computed as `f64`s, other
+/// integral exponents of integers must be non-negative.
+///
+/// # Panics
+///
+/// If a bitwise operation or a shift has a floating point operand.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, to_rust_fn, DType, RustArgType, RustSignature};
+///
+/// let signature = RustSignature::new("f", vec![("x".to_string(),
+///                                               RustArgType::Scalar(DType::F64))]);
+/// assert_eq!(to_rust_fn(&parse("2*sin(x)"), &signature),
+///            "pub fn f(x: f64) -> f64 {\n    2.0_f64 * x.sin()\n}\n");
+/// ```
+pub fn to_rust_fn(expr: &Rc<Expression>, signature: &RustSignature) -> String {
+    to_rust_fn_with_registry(expr, signature, default_registry())
+}
+
+/// Returns the Rust source of a function evaluating `expr` with its calls
+/// emitted as per the functions in `registry`. Also see [`to_rust_fn`].
+pub fn to_rust_fn_with_registry(expr: &Rc<Expression>, signature: &RustSignature,
+                                registry: &FunctionRegistry)
+                                -> String {
+    let mut mapper = RustEmitter { signature,
+                                   registry,
+                                   cache: HashMap::new() };
+    let body = mapper.visit(expr);
+    let body = match signature.return_dtype {
+        Some(dtype) => body.cast(dtype),
+        None => body,
+    };
+
+    let args: Vec<String> = signature.args
+                                     .iter()
+                                     .map(|(name, arg_type)| match arg_type {
+                                         RustArgType::Scalar(dtype) => {
+                                             format!("{}: {}", name, get_type_name(*dtype))
+                                         }
+                                         RustArgType::Slice(dtype, _) => {
+                                             format!("{}: &[{}]", name, get_type_name(*dtype))
+                                         }
+                                     })
+                                     .collect();
+    let return_type = match body.ty {
+        RustType::Bool => "bool",
+        RustType::Num(dtype) => get_type_name(dtype),
+    };
+
+    format!("pub fn {}({}) -> {} {{\n    {}\n}}\n",
+            signature.name,
+            args.join(", "),
+            return_type,
+            body.text)
+}
+
+// vim: fdm=marker
@@ -0,0 +1,284 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::primitives::{BinaryOpType, DType, Expression, UnaryOpType};
+use crate::utils::{get_children, get_topological_order, ExpressionRawPointer};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// The variant of an [`Expression`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum NodeKind {
+    Scalar,
+    Variable,
+    UnaryOp,
+    BinaryOp,
+    Call,
+    Subscript,
+    If,
+}
+
+impl NodeKind {
+    pub fn of(expr: &Expression) -> Self {
+        match expr {
+            Expression::Scalar(_) => NodeKind::Scalar,
+            Expression::Variable(_) => NodeKind::Variable,
+            Expression::UnaryOp(..) => NodeKind::UnaryOp,
+            Expression::BinaryOp(..) => NodeKind::BinaryOp,
+            Expression::Call(..) => NodeKind::Call,
+            Expression::Subscript(..) => NodeKind::Subscript,
+            Expression::If(..) => NodeKind::If,
+        }
+    }
+}
+
+/// A sub-expression that is an operand of more than one operation. See
+/// [`ExpressionStats::shared_subexpressions`].
+#[derive(Clone)]
+pub struct SharedSubexpression {
+    pub expr: Rc<Expression>,
+    /// Number of times the sub-expression is used as an operand.
+    pub fan_out: usize,
+    /// Number of nodes in the tree representation of the sub-expression.
+    pub num_tree_nodes: u128,
+}
+
+/// Statistics of an expression's DAG. See [`ExpressionStats::compute`].
+///
+/// Histograms count each node of the DAG once, irrespective of the number of
+/// times it is shared.
+#[derive(Clone)]
+pub struct ExpressionStats {
+    /// Number of unique nodes, i.e. nodes in the DAG.
+    pub num_dag_nodes: usize,
+    /// Number of nodes if no sub-expression were shared, i.e. nodes in the
+    /// tree representation. Saturates at `u128::MAX`.
+    pub num_tree_nodes: u128,
+    /// Number of edges on the longest path from the root to a leaf.
+    pub max_depth: usize,
+    /// Mean depth of the nodes in the tree representation.
+    pub average_depth: f64,
+    pub node_kinds: HashMap<NodeKind, usize>,
+    pub unary_ops: HashMap<UnaryOpType, usize>,
+    pub binary_ops: HashMap<BinaryOpType, usize>,
+    /// Number of scalar nodes of each data-type.
+    pub literal_dtypes: HashMap<DType, usize>,
+    /// Variables other than the call targets.
+    pub variables: BTreeSet<String>,
+    /// Names of the called functions. Callees that are not variables are
+    /// recorded via their [`Display`](std::fmt::Display) representation.
+    pub call_targets: BTreeSet<String>,
+    /// Non-leaf sub-expressions with a fan-out greater than one, sorted by
+    /// decreasing fan-out and then by decreasing size.
+    pub shared_subexpressions: Vec<SharedSubexpression>,
+}
+
+impl ExpressionStats {
+    /// Returns the statistics of `expr`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use symoxide::{parse, ExpressionStats};
+    ///
+    /// let x = parse("sin(x) + 1");
+    /// let expr = symoxide::operations::mul(&x, &x);
+    /// let stats = ExpressionStats::compute(&expr);
+    /// assert_eq!(stats.num_dag_nodes, 6);
+    /// assert_eq!(stats.num_tree_nodes, 11);
+    /// assert_eq!(stats.shared_subexpressions[0].fan_out, 2);
+    /// ```
+    pub fn compute(expr: &Rc<Expression>) -> Self {
+        // operands appear before the operations consuming them
+        let order = get_topological_order(expr);
+        let index: HashMap<ExpressionRawPointer, usize> =
+            order.iter()
+                 .enumerate()
+                 .map(|(i, node)| (ExpressionRawPointer(node.clone()), i))
+                 .collect();
+        let children: Vec<Vec<usize>> = order.iter()
+                                             .map(|node| {
+                                                 get_children(node).into_iter()
+                                       .map(|child| index[&ExpressionRawPointer(child)])
+                                       .collect()
+                                             })
+                                             .collect();
+
+        let mut stats = ExpressionStats { num_dag_nodes: order.len(),
+                                          num_tree_nodes: 0,
+                                          max_depth: 0,
+                                          average_depth: 0.0,
+                                          node_kinds: HashMap::new(),
+                                          unary_ops: HashMap::new(),
+                                          binary_ops: HashMap::new(),
+                                          literal_dtypes: HashMap::new(),
+                                          variables: BTreeSet::new(),
+                                          call_targets: BTreeSet::new(),
+                                          shared_subexpressions: vec![] };
+
+        // {{{ histograms
+
+        let mut fan_out = vec![0; order.len()];
+        let mut callee_uses = vec![0; order.len()];
+
+        for (i, node) in order.iter().enumerate() {
+            *stats.node_kinds.entry(NodeKind::of(node)).or_insert(0) += 1;
+            for &child in children[i].iter() {
+                fan_out[child] += 1;
+            }
+            match &**node {
+                Expression::Scalar(value) => {
+                    *stats.literal_dtypes.entry(value.dtype()).or_insert(0) += 1;
+                }
+                Expression::UnaryOp(op, _) => *stats.unary_ops.entry(*op).or_insert(0) += 1,
+                Expression::BinaryOp(_, op, _) => *stats.binary_ops.entry(*op).or_insert(0) += 1,
+                Expression::Call(call, _) => {
+                    callee_uses[children[i][0]] += 1;
+                    stats.call_targets.insert(call.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        for (i, node) in order.iter().enumerate() {
+            if let Expression::Variable(name) = &**node {
+                // a variable could be a callee as well as an operand
+                if callee_uses[i] == 0 || fan_out[i] > callee_uses[i] {
+                    stats.variables.insert(name.clone());
+                }
+            }
+        }
+
+        // }}}
+
+        // {{{ tree sizes and depths
+
+        let mut num_tree_nodes = vec![1u128; order.len()];
+        let mut height = vec![0usize; order.len()];
+        for i in 0..order.len() {
+            for &child in children[i].iter() {
+                num_tree_nodes[i] = num_tree_nodes[i].saturating_add(num_tree_nodes[child]);
+                height[i] = height[i].max(height[child] + 1);
+            }
+        }
+
+        // number of paths from the root to each node and the sum of their
+        // lengths
+        let mut npaths = vec![0f64; order.len()];
+        let mut depth_sum = vec![0f64; order.len()];
+        let root = order.len() - 1;
+        npaths[root] = 1.0;
+        for i in (0..order.len()).rev() {
+            for &child in children[i].iter() {
+                npaths[child] += npaths[i];
+                depth_sum[child] += depth_sum[i] + npaths[i];
+            }
+        }
+
+        stats.num_tree_nodes = num_tree_nodes[root];
+        stats.max_depth = height[root];
+        stats.average_depth = depth_sum.iter().sum::<f64>() / npaths.iter().sum::<f64>();
+
+        // }}}
+
+        stats.shared_subexpressions =
+            order.iter()
+                 .enumerate()
+                 .filter(|(i, node)| fan_out[*i] > 1 && !get_children(node).is_empty())
+                 .map(|(i, node)| SharedSubexpression { expr: node.clone(),
+                                                        fan_out: fan_out[i],
+                                                        num_tree_nodes: num_tree_nodes[i] })
+                 .collect();
+        stats.shared_subexpressions.sort_by(|a, b| {
+                                       b.fan_out
+                                        .cmp(&a.fan_out)
+                                        .then(b.num_tree_nodes.cmp(&a.num_tree_nodes))
+                                   });
+
+        stats
+    }
+
+    /// Returns the ratio of the number of tree nodes to the number of DAG
+    /// nodes. A ratio greater than 1 indicates that sub-expressions are
+    /// shared.
+    pub fn sharing_ratio(&self) -> f64 {
+        self.num_tree_nodes as f64 / self.num_dag_nodes as f64
+    }
+}
+
+fn write_histogram<K: fmt::Display>(f: &mut fmt::Formatter, title: &str,
+                                    histogram: Vec<(K, usize)>)
+                                    -> fmt::Result {
+    let mut histogram = histogram;
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.to_string().cmp(&b.0.to_string())));
+    let entries: Vec<String> = histogram.iter()
+                                        .map(|(k, count)| format!("{}: {}", k, count))
+                                        .collect();
+    writeln!(f, "{}: {{{}}}", title, entries.join(", "))
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl fmt::Display for ExpressionStats {
+    /// Prints a human-readable report of the statistics with the first 5
+    /// entries of [`ExpressionStats::shared_subexpressions`], i.e. the most
+    /// shared sub-expressions.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "DAG nodes: {}", self.num_dag_nodes)?;
+        writeln!(f,
+                 "Tree nodes: {} (sharing ratio: {:.2})",
+                 self.num_tree_nodes,
+                 self.sharing_ratio())?;
+        writeln!(f,
+                 "Depth: {} (average: {:.2})",
+                 self.max_depth, self.average_depth)?;
+        write_histogram(f,
+                        "Node kinds",
+                        self.node_kinds.iter().map(|(k, v)| (*k, *v)).collect())?;
+        write_histogram(f,
+                        "Unary ops",
+                        self.unary_ops.iter().map(|(k, v)| (*k, *v)).collect())?;
+        write_histogram(f,
+                        "Binary ops",
+                        self.binary_ops.iter().map(|(k, v)| (*k, *v)).collect())?;
+        write_histogram(f,
+                        "Literal types",
+                        self.literal_dtypes
+                            .iter()
+                            .map(|(k, v)| (format!("{:?}", k), *v))
+                            .collect())?;
+        let variables: Vec<&str> = self.variables.iter().map(|x| x.as_str()).collect();
+        writeln!(f, "Variables: {}", variables.join(", "))?;
+        let call_targets: Vec<&str> = self.call_targets.iter().map(|x| x.as_str()).collect();
+        write!(f, "Call targets: {}", call_targets.join(", "))?;
+        for shared in self.shared_subexpressions.iter().take(5) {
+            write!(f,
+                   "\nShared (fan-out: {}, tree nodes: {}): {}",
+                   shared.fan_out, shared.num_tree_nodes, shared.expr)?;
+        }
+        Ok(())
+    }
+}
+
+// vim: fdm=marker
@@ -7,27 +7,27 @@ use hashbrown::HashMap;
 use std::fmt;
 use std::rc::Rc;
 
-const PREC_IF: u8 = 0;
-const PREC_LOR: u8 = 1;
-const PREC_LAND: u8 = 2;
-const PREC_LNOT: u8 = 3;
-const PREC_CMP: u8 = 4;
-const PREC_BOR: u8 = 5;
-const PREC_BXOR: u8 = 6;
-const PREC_BAND: u8 = 7;
-const PREC_SHIFT: u8 = 8;
-const PREC_ADD: u8 = 9;
-const PREC_PROD: u8 = 10;
-const PREC_UNARY: u8 = 11;
-const PREC_EXP: u8 = 12;
-const PREC_ATOM: u8 = 13;
+pub(crate) const PREC_IF: u8 = 0;
+pub(crate) const PREC_LOR: u8 = 1;
+pub(crate) const PREC_LAND: u8 = 2;
+pub(crate) const PREC_LNOT: u8 = 3;
+pub(crate) const PREC_CMP: u8 = 4;
+pub(crate) const PREC_BOR: u8 = 5;
+pub(crate) const PREC_BXOR: u8 = 6;
+pub(crate) const PREC_BAND: u8 = 7;
+pub(crate) const PREC_SHIFT: u8 = 8;
+pub(crate) const PREC_ADD: u8 = 9;
+pub(crate) const PREC_PROD: u8 = 10;
+pub(crate) const PREC_UNARY: u8 = 11;
+pub(crate) const PREC_EXP: u8 = 12;
+pub(crate) const PREC_ATOM: u8 = 13;
 
 #[derive(CachedMapper)]
 pub struct Stringifier {
     cache: HashMap<(ExpressionRawPointer, u8), String>,
 }
 
-fn guard_with_paren(my_str: String, my_prec: u8, outer_prec: &u8) -> String {
+pub(crate) fn guard_with_paren(my_str: String, my_prec: u8, outer_prec: &u8) -> String {
     if *outer_prec > my_prec {
         format!("({})", my_str)
     } else {
@@ -35,6 +35,36 @@ fn guard_with_paren(my_str: String, my_prec: u8, outer_prec: &u8) -> String {
     }
 }
 
+/// Returns the precedence level of the binary operation `op`. Operations
+/// with a higher level bind tighter.
+pub(crate) fn get_binary_op_precedence(op: BinaryOpType) -> u8 {
+    match op {
+        BinaryOpType::Sum | BinaryOpType::Subtract => PREC_ADD,
+        BinaryOpType::Product
+        | BinaryOpType::Divide
+        | BinaryOpType::FloorDiv
+        | BinaryOpType::Modulo => PREC_PROD,
+
+        BinaryOpType::Equal
+        | BinaryOpType::NotEqual
+        | BinaryOpType::Greater
+        | BinaryOpType::GreaterEqual
+        | BinaryOpType::Less
+        | BinaryOpType::LessEqual => PREC_CMP,
+
+        BinaryOpType::BitwiseOr => PREC_BOR,
+        BinaryOpType::BitwiseXor => PREC_BXOR,
+        BinaryOpType::BitwiseAnd => PREC_BAND,
+
+        BinaryOpType::LogicalAnd => PREC_LAND,
+        BinaryOpType::LogicalOr => PREC_LOR,
+
+        BinaryOpType::LeftShift | BinaryOpType::RightShift => PREC_SHIFT,
+
+        BinaryOpType::Exponent => PREC_EXP,
+    }
+}
+
 impl FoldMapperWithContext for Stringifier {
     type Context = u8;
     type Output = String;
@@ -93,31 +123,7 @@ impl FoldMapperWithContext for Stringifier {
             BinaryOpType::Exponent => "**",
         };
 
-        let my_prec = match op {
-            BinaryOpType::Sum | BinaryOpType::Subtract => PREC_ADD,
-            BinaryOpType::Product
-            | BinaryOpType::Divide
-            | BinaryOpType::FloorDiv
-            | BinaryOpType::Modulo => PREC_PROD,
-
-            BinaryOpType::Equal
-            | BinaryOpType::NotEqual
-            | BinaryOpType::Greater
-            | BinaryOpType::GreaterEqual
-            | BinaryOpType::Less
-            | BinaryOpType::LessEqual => PREC_CMP,
-
-            BinaryOpType::BitwiseOr => PREC_BOR,
-            BinaryOpType::BitwiseXor => PREC_BXOR,
-            BinaryOpType::BitwiseAnd => PREC_BAND,
-
-            BinaryOpType::LogicalAnd => PREC_LAND,
-            BinaryOpType::LogicalOr => PREC_LOR,
-
-            BinaryOpType::LeftShift | BinaryOpType::RightShift => PREC_SHIFT,
-
-            BinaryOpType::Exponent => PREC_EXP,
-        };
+        let my_prec = get_binary_op_precedence(op);
 
         let my_str = match op {
             // right-to-left associative
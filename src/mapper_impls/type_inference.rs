@@ -0,0 +1,194 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Inference of the data-type of an expression. See [`infer_dtype`].
+
+use crate::functions::{default_registry, FunctionRegistry};
+use crate::mappers::fold::FoldMapper;
+use crate::mappers::CachedMapper;
+use crate::primitives::{BinaryOpType, DType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use crate::CachedMapper;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// {{{ helpers
+
+/// Returns the type to which operands of types `a` and `b` are cast before
+/// an arithmetic operation.
+pub(crate) fn get_common_dtype(a: DType, b: DType) -> DType {
+    if a == b {
+        a
+    } else if a.is_integral() && b.is_integral() {
+        DType::I64
+    } else {
+        DType::F64
+    }
+}
+
+/// Returns the value of the integral literal `value`. Unlike
+/// [`LiteralT::as_i64`], `u64`s above `i64::MAX` do not wrap around.
+pub(crate) fn get_integral_value(value: &LiteralT) -> i128 {
+    match value {
+        LiteralT::U64(x) => *x as i128,
+        _ => value.as_i64() as i128,
+    }
+}
+
+/// Returns the value of `expr` if it is an integral literal or the negation
+/// of one.
+pub(crate) fn get_integral_constant(expr: &Expression) -> Option<i128> {
+    match expr {
+        Expression::Scalar(x) if x.is_integral() => Some(get_integral_value(x)),
+        Expression::UnaryOp(UnaryOpType::Minus, x) => match &**x {
+            Expression::Scalar(x) if x.is_integral() => Some(-get_integral_value(x)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// }}}
+
+// {{{ DTypeInferrer
+
+#[derive(CachedMapper)]
+struct DTypeInferrer<'a> {
+    dtypes: &'a HashMap<String, DType>,
+    registry: &'a FunctionRegistry,
+    cache: HashMap<ExpressionRawPointer, DType>,
+}
+
+impl DTypeInferrer<'_> {
+    fn get_variable_dtype(&self, name: &str) -> DType {
+        match self.dtypes.get(name) {
+            Some(x) => *x,
+            None => panic!("Data-type of the variable '{}' not known.", name),
+        }
+    }
+}
+
+impl FoldMapper for DTypeInferrer<'_> {
+    type Output = DType;
+
+    fn map_scalar(&mut self, value: &LiteralT) -> Self::Output {
+        value.dtype()
+    }
+    fn map_variable(&mut self, name: String) -> Self::Output {
+        self.get_variable_dtype(&name)
+    }
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>) -> Self::Output {
+        let dtype = self.visit(x);
+        match op {
+            UnaryOpType::LogicalNot => DType::I64,
+            UnaryOpType::BitwiseNot | UnaryOpType::Minus => dtype,
+        }
+    }
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>)
+                     -> Self::Output {
+        let (left_dtype, right_dtype) = (self.visit(left), self.visit(right));
+        match op {
+            BinaryOpType::Equal
+            | BinaryOpType::NotEqual
+            | BinaryOpType::Greater
+            | BinaryOpType::GreaterEqual
+            | BinaryOpType::Less
+            | BinaryOpType::LessEqual
+            | BinaryOpType::LogicalAnd
+            | BinaryOpType::LogicalOr => DType::I64,
+
+            BinaryOpType::Divide if left_dtype == DType::F32 && right_dtype == DType::F32 => {
+                DType::F32
+            }
+            BinaryOpType::Divide => DType::F64,
+            BinaryOpType::LeftShift | BinaryOpType::RightShift => left_dtype,
+            BinaryOpType::Exponent if left_dtype.is_integral() && right_dtype.is_integral() => {
+                // negative powers of integers are floating point numbers
+                match get_integral_constant(right) {
+                    Some(n) if n < 0 => DType::F64,
+                    _ => left_dtype,
+                }
+            }
+            BinaryOpType::Exponent if right_dtype.is_integral() => left_dtype,
+            _ => get_common_dtype(left_dtype, right_dtype),
+        }
+    }
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT) -> Self::Output {
+        let function = self.registry.expect_callee(call, params);
+        let dtypes: Vec<DType> = params.iter().map(|x| self.visit(x)).collect();
+        (function.result_dtype)(&dtypes)
+    }
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT) -> Self::Output {
+        for idx in indices {
+            self.visit(idx);
+        }
+        match &**agg {
+            Expression::Variable(name) => self.get_variable_dtype(name),
+            _ => self.visit(agg),
+        }
+    }
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>)
+              -> Self::Output {
+        self.visit(cond);
+        let (then, else_) = (self.visit(then), self.visit(else_));
+        get_common_dtype(then, else_)
+    }
+}
+
+// }}}
+
+/// Returns the data-type of the value of `expr` when its variables are of the
+/// data-types in `dtypes`. The data-type of a subscripted variable in
+/// `dtypes` is the data-type of its elements.
+///
+/// The data-types follow the code emitted by
+/// [`to_rust_fn`](crate::to_rust_fn): operands of different types are
+/// converted to `i64` if both are integers and to `f64` otherwise, divisions
+/// are performed in floating point arithmetic, and comparisons, logical
+/// operations evaluate to `i64`s. The data-types of calls are as per the
+/// functions' [`result_dtype`](crate::functions::FunctionInfo::result_dtype)
+/// in the [default registry](crate::functions::default_registry).
+///
+/// # Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use symoxide::{infer_dtype, parse, DType};
+///
+/// let dtypes = HashMap::from([("i".to_string(), DType::I32), ("x".to_string(), DType::F32)]);
+/// assert_eq!(infer_dtype(&parse("i + 1"), &dtypes), DType::I32);
+/// assert_eq!(infer_dtype(&parse("i * x"), &dtypes), DType::F64);
+/// assert_eq!(infer_dtype(&parse("x < sin(i)"), &dtypes), DType::I64);
+/// ```
+pub fn infer_dtype(expr: &Rc<Expression>, dtypes: &HashMap<String, DType>) -> DType {
+    infer_dtype_with_registry(expr, dtypes, default_registry())
+}
+
+/// Returns the data-type of the value of `expr` with the data-types of its
+/// calls as per the functions in `registry`. Also see [`infer_dtype`].
+pub fn infer_dtype_with_registry(expr: &Rc<Expression>, dtypes: &HashMap<String, DType>,
+                                 registry: &FunctionRegistry)
+                                 -> DType {
+    let mut mapper = DTypeInferrer { dtypes,
+                                     registry,
+                                     cache: HashMap::new() };
+    mapper.visit(expr)
+}
+
+// vim: fdm=marker
@@ -31,15 +31,7 @@ pub trait UnachedCombineMapper: Sized {
     fn combine(&self, values: &[Self::Output]) -> Self::Output;
 
     fn visit(&self, expr: &Expression) -> Self::Output {
-        match expr {
-            Expression::Scalar(s) => self.map_scalar(&s),
-            Expression::Variable(name) => self.map_variable(name.to_string()),
-            Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x),
-            Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r),
-            Expression::Call(call, params) => self.map_call(&call, &params),
-            Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices),
-            Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_),
-        }
+        dispatch!(self, expr)
     }
 
     fn map_scalar(&self, value: &LiteralT) -> Self::Output;
@@ -80,15 +72,7 @@ pub trait CombineMapperWithContext: Sized {
     fn combine(&self, values: &[Self::Output]) -> Self::Output;
 
     fn visit(&self, expr: &Expression, context: &Self::Context) -> Self::Output {
-        match expr {
-            Expression::Scalar(s) => self.map_scalar(&s, context),
-            Expression::Variable(name) => self.map_variable(name.to_string(), context),
-            Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x, context),
-            Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r, context),
-            Expression::Call(call, params) => self.map_call(&call, &params, context),
-            Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices, context),
-            Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_, context),
-        }
+        dispatch!(self, expr, context)
     }
 
     fn map_variable(&self, name: String, context: &Self::Context) -> Self::Output;
@@ -142,15 +126,7 @@ pub trait CombineMapper: Sized + CachedMapper<ExpressionRawPointer, Self::Output
         match self.query_cache(&cache_key) {
             Some(x) => x.clone(),
             None => {
-                let result = match &*expr.clone() {
-                    Expression::Scalar(s) => self.map_scalar(&s),
-                    Expression::Variable(name) => self.map_variable(name.to_string()),
-                    Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), x),
-                    Expression::BinaryOp(l, op, r) => self.map_binary_op(l, op.clone(), r),
-                    Expression::Call(call, params) => self.map_call(call, &params),
-                    Expression::Subscript(agg, indices) => self.map_subscript(agg, &indices),
-                    Expression::If(cond, then, else_) => self.map_if(cond, then, else_),
-                };
+                let result = dispatch!(self, &*expr.clone());
 
                 self.add_to_cache(cache_key, result.clone());
                 result
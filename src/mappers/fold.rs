@@ -13,15 +13,7 @@ pub trait FoldMapper: CachedMapper<ExpressionRawPointer, Self::Output> {
         match self.query_cache(&cache_key) {
             Some(x) => x.clone(),
             None => {
-                let result = match &*(expr.clone()) {
-                    Expression::Scalar(s) => self.map_scalar(&s),
-                    Expression::Variable(name) => self.map_variable(name.to_string()),
-                    Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), x),
-                    Expression::BinaryOp(l, op, r) => self.map_binary_op(l, op.clone(), r),
-                    Expression::Call(call, params) => self.map_call(call, &params),
-                    Expression::Subscript(agg, indices) => self.map_subscript(agg, indices),
-                    Expression::If(cond, then, else_) => self.map_if(cond, then, else_),
-                };
+                let result = dispatch!(self, &*(expr.clone()));
                 self.add_to_cache(cache_key, result.clone());
                 result
             }
@@ -47,15 +39,7 @@ pub trait UncachedFoldMapper {
     type Output;
 
     fn visit(&self, expr: &Expression) -> Self::Output {
-        match expr {
-            Expression::Scalar(s) => self.map_scalar(&s),
-            Expression::Variable(name) => self.map_variable(name.to_string()),
-            Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), x),
-            Expression::BinaryOp(l, op, r) => self.map_binary_op(l, op.clone(), r),
-            Expression::Call(call, params) => self.map_call(call, &params),
-            Expression::Subscript(agg, indices) => self.map_subscript(agg, indices),
-            Expression::If(cond, then, else_) => self.map_if(cond, then, else_),
-        }
+        dispatch!(self, expr)
     }
 
     fn map_scalar(&self, value: &LiteralT) -> Self::Output;
@@ -77,15 +61,7 @@ pub trait UncachedFoldMapperWithContext {
     type Output;
 
     fn visit(&self, expr: &Expression, context: &Self::Context) -> Self::Output {
-        match expr {
-            Expression::Scalar(s) => self.map_scalar(&s, context),
-            Expression::Variable(name) => self.map_variable(name.to_string(), context),
-            Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x, context),
-            Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r, context),
-            Expression::Call(call, params) => self.map_call(&call, &params, context),
-            Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices, context),
-            Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_, context),
-        }
+        dispatch!(self, expr, context)
     }
 
     fn map_scalar(&self, value: &LiteralT, context: &Self::Context) -> Self::Output;
@@ -120,19 +96,7 @@ pub trait FoldMapperWithContext: CachedMapper<Self::CacheKey, Self::Output> {
         match self.query_cache(&cache_key) {
             Some(x) => x.clone(),
             None => {
-                let result = match &*(expr.clone()) {
-                    Expression::Scalar(s) => self.map_scalar(&s, context),
-                    Expression::Variable(name) => self.map_variable(name.to_string(), context),
-                    Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x, context),
-                    Expression::BinaryOp(l, op, r) => {
-                        self.map_binary_op(&l, op.clone(), &r, context)
-                    }
-                    Expression::Call(call, params) => self.map_call(&call, &params, context),
-                    Expression::Subscript(agg, indices) => {
-                        self.map_subscript(&agg, &indices, context)
-                    }
-                    Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_, context),
-                };
+                let result = dispatch!(self, &*(expr.clone()), context);
                 self.add_to_cache(cache_key, result.clone());
                 result
             }
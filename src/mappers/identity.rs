@@ -31,15 +31,7 @@ pub trait IdentityMapper: CachedMapper<ExpressionRawPointer, Rc<Expression>> {
         match self.query_cache(&cache_key) {
             Some(x) => x.clone(),
             None => {
-                let result = match &*expr {
-                    Expression::Scalar(s) => self.map_scalar(&s),
-                    Expression::Variable(name) => self.map_variable(name.to_string()),
-                    Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x),
-                    Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r),
-                    Expression::Call(call, params) => self.map_call(&call, &params),
-                    Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices),
-                    Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_),
-                };
+                let result = dispatch!(self, &*expr);
                 self.add_to_cache(cache_key, result.clone());
                 result
             }
@@ -89,15 +81,7 @@ pub trait IdentityMapper: CachedMapper<ExpressionRawPointer, Rc<Expression>> {
 
 pub trait UncachedIdentityMapper {
     fn visit(&self, expr: &Expression) -> Rc<Expression> {
-        match expr {
-            Expression::Scalar(s) => self.map_scalar(&s),
-            Expression::Variable(name) => self.map_variable(name.to_string()),
-            Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x),
-            Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r),
-            Expression::Call(call, params) => self.map_call(&call, &params),
-            Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices),
-            Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_),
-        }
+        dispatch!(self, expr)
     }
 
     fn map_scalar(&self, value: &LiteralT) -> Rc<Expression> {
@@ -141,15 +125,7 @@ pub trait IdentityMapperWithContext {
     type Context;
 
     fn visit(&self, expr: &Expression, context: &Self::Context) -> Rc<Expression> {
-        match expr {
-            Expression::Scalar(s) => self.map_scalar(&s, context),
-            Expression::Variable(name) => self.map_variable(name.to_string(), context),
-            Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x, context),
-            Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r, context),
-            Expression::Call(call, params) => self.map_call(&call, &params, context),
-            Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices, context),
-            Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_, context),
-        }
+        dispatch!(self, expr, context)
     }
 
     fn map_scalar(&self, value: &LiteralT, _context: &Self::Context) -> Rc<Expression> {
@@ -209,15 +185,7 @@ pub trait IdentityMapperWithCustomCacheKey: CachedMapper<Self::CacheKey, Rc<Expr
         match self.query_cache(&cache_key) {
             Some(x) => x.clone(),
             None => {
-                let result = match &*expr {
-                    Expression::Scalar(s) => self.map_scalar(&s),
-                    Expression::Variable(name) => self.map_variable(name.to_string()),
-                    Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x),
-                    Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r),
-                    Expression::Call(call, params) => self.map_call(&call, &params),
-                    Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices),
-                    Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_),
-                };
+                let result = dispatch!(self, &*expr);
                 self.add_to_cache(cache_key, result.clone());
                 result
             }
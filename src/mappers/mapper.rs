@@ -0,0 +1,246 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A single mapper trait, configurable in its context and caching behavior.
+//!
+//! The specialized traits in [`crate::mappers`] each fix one combination of
+//! receiver mutability, context and cache key. [`Mapper`] instead takes the
+//! context by `&mut` and leaves the memoization strategy to a
+//! [`CachePolicy`] chosen when constructing its [`MapperCache`].
+
+use crate::mapper_impls::hasher::HashCacher;
+use crate::mappers::fold::FoldMapper;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+use crate::utils::ExpressionRawPointer;
+use hashbrown::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// {{{ CachePolicy
+
+/// Strategy by which a [`Mapper`] memoizes the results of its visits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Every visit recomputes its result.
+    None,
+    /// Results are keyed by the address of the visited node.
+    ByPointer,
+    /// Results are keyed by the address of the visited node along with the
+    /// key returned by [`Mapper::get_context_key`].
+    ByPointerAndContext,
+    /// Results are keyed by the structure of the visited node, so that
+    /// structurally equal sub-expressions at different addresses are mapped
+    /// only once.
+    Structural,
+}
+
+// }}}
+
+// {{{ MapperCache
+
+struct StructuralKey {
+    expr: Rc<Expression>,
+    hashval: u64,
+}
+
+impl Hash for StructuralKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hashval.hash(state)
+    }
+}
+
+impl PartialEq for StructuralKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.hashval == other.hashval && self.expr == other.expr
+    }
+}
+
+impl Eq for StructuralKey {}
+
+#[derive(PartialEq, Eq, Hash)]
+enum CacheKey<K> {
+    Pointer(ExpressionRawPointer),
+    PointerAndContext(ExpressionRawPointer, K),
+    Structural(StructuralKey),
+}
+
+/// Storage for the results memoized by a [`Mapper`]. `K` is the mapper's
+/// context key type and `V` its output type.
+pub struct MapperCache<K, V> {
+    policy: CachePolicy,
+    hasher: HashCacher,
+    results: HashMap<CacheKey<K>, V>,
+}
+
+impl<K: Hash + Eq, V> MapperCache<K, V> {
+    pub fn new(policy: CachePolicy) -> Self {
+        MapperCache { policy,
+                      hasher: HashCacher::new(),
+                      results: HashMap::new() }
+    }
+
+    pub fn policy(&self) -> CachePolicy {
+        self.policy
+    }
+
+    /// Returns the number of memoized results.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Forgets all memoized results, retaining the cache policy.
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    fn get_structural_key(&mut self, expr: &Rc<Expression>) -> StructuralKey {
+        StructuralKey { expr: expr.clone(),
+                        hashval: self.hasher.visit(expr) }
+    }
+}
+
+// }}}
+
+// {{{ Mapper
+
+/// A mapper over [`Expression`] DAGs that threads a mutable context through
+/// its visits and memoizes its results as per the [`CachePolicy`] of its
+/// [`MapperCache`].
+///
+/// # Example
+/// ```rust
+/// use std::rc::Rc;
+/// use symoxide::mappers::mapper::{CachePolicy, Mapper, MapperCache};
+/// use symoxide::{parse, BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+///
+/// // Records the names of the variables in the order they are first seen.
+/// struct VariableCollector {
+///     cache: MapperCache<(), ()>,
+/// }
+///
+/// impl Mapper for VariableCollector {
+///     type Output = ();
+///     type Context = Vec<String>;
+///     type ContextKey = ();
+///
+///     fn cache(&mut self) -> &mut MapperCache<(), ()> {
+///         &mut self.cache
+///     }
+///     fn get_context_key(&self, _context: &Vec<String>) {}
+///
+///     fn map_scalar(&mut self, _value: &LiteralT, _names: &mut Vec<String>) {}
+///     fn map_variable(&mut self, name: String, names: &mut Vec<String>) {
+///         names.push(name);
+///     }
+///     fn map_unary_op(&mut self, _op: UnaryOpType, x: &Rc<Expression>,
+///                     names: &mut Vec<String>) {
+///         self.visit(x, names)
+///     }
+///     fn map_binary_op(&mut self, left: &Rc<Expression>, _op: BinaryOpType,
+///                      right: &Rc<Expression>, names: &mut Vec<String>) {
+///         self.visit(left, names);
+///         self.visit(right, names);
+///     }
+///     fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT,
+///                 names: &mut Vec<String>) {
+///         self.visit(call, names);
+///         params.iter().for_each(|param| self.visit(param, names));
+///     }
+///     fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT,
+///                      names: &mut Vec<String>) {
+///         self.visit(agg, names);
+///         indices.iter().for_each(|idx| self.visit(idx, names));
+///     }
+///     fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>,
+///               else_: &Rc<Expression>, names: &mut Vec<String>) {
+///         self.visit(cond, names);
+///         self.visit(then, names);
+///         self.visit(else_, names);
+///     }
+/// }
+///
+/// let mut collector = VariableCollector { cache: MapperCache::new(CachePolicy::Structural) };
+/// let mut names = Vec::new();
+/// collector.visit(&parse("x*y + x - z"), &mut names);
+/// assert_eq!(names, vec!["x", "y", "z"]);
+/// ```
+pub trait Mapper: Sized {
+    type Output: Clone;
+    type Context;
+    type ContextKey: Hash + Eq;
+
+    fn cache(&mut self) -> &mut MapperCache<Self::ContextKey, Self::Output>;
+
+    /// Returns the part of *context* that the mapped results depend on. Only
+    /// consulted for [`CachePolicy::ByPointerAndContext`].
+    fn get_context_key(&self, context: &Self::Context) -> Self::ContextKey;
+
+    fn visit(&mut self, expr: &Rc<Expression>, context: &mut Self::Context) -> Self::Output {
+        let cache_key = match self.cache().policy() {
+            CachePolicy::None => None,
+            CachePolicy::ByPointer => Some(CacheKey::Pointer(ExpressionRawPointer(expr.clone()))),
+            CachePolicy::ByPointerAndContext => {
+                let context_key = self.get_context_key(context);
+                Some(CacheKey::PointerAndContext(ExpressionRawPointer(expr.clone()), context_key))
+            }
+            CachePolicy::Structural => {
+                Some(CacheKey::Structural(self.cache().get_structural_key(expr)))
+            }
+        };
+
+        if let Some(key) = &cache_key {
+            if let Some(x) = self.cache().results.get(key) {
+                return x.clone();
+            }
+        }
+
+        let result = dispatch!(self, &**expr, context);
+
+        if let Some(key) = cache_key {
+            self.cache().results.insert(key, result.clone());
+        }
+        result
+    }
+
+    fn map_scalar(&mut self, value: &LiteralT, context: &mut Self::Context) -> Self::Output;
+    fn map_variable(&mut self, name: String, context: &mut Self::Context) -> Self::Output;
+    fn map_unary_op(&mut self, op: UnaryOpType, x: &Rc<Expression>, context: &mut Self::Context)
+                    -> Self::Output;
+    fn map_binary_op(&mut self, left: &Rc<Expression>, op: BinaryOpType, right: &Rc<Expression>,
+                     context: &mut Self::Context)
+                     -> Self::Output;
+    fn map_call(&mut self, call: &Rc<Expression>, params: &SmallVecExprT,
+                context: &mut Self::Context)
+                -> Self::Output;
+    fn map_subscript(&mut self, agg: &Rc<Expression>, indices: &SmallVecExprT,
+                     context: &mut Self::Context)
+                     -> Self::Output;
+    fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>, else_: &Rc<Expression>,
+              context: &mut Self::Context)
+              -> Self::Output;
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -1,6 +1,37 @@
+/// Dispatches `$expr` (a `&Expression`) to the `map_*` method of `$self`
+/// corresponding to its node kind, forwarding the trailing arguments to each
+/// of them. Every mapper trait routes through this macro, so that introducing a
+/// new node kind only requires editing this match.
+macro_rules! dispatch {
+    ($self:ident, $expr:expr $(, $arg:expr)*) => {
+        match $expr {
+            $crate::primitives::Expression::Scalar(s) => $self.map_scalar(s $(, $arg)*),
+            $crate::primitives::Expression::Variable(name) => {
+                $self.map_variable(name.to_string() $(, $arg)*)
+            }
+            $crate::primitives::Expression::UnaryOp(op, x) => {
+                $self.map_unary_op(*op, x $(, $arg)*)
+            }
+            $crate::primitives::Expression::BinaryOp(l, op, r) => {
+                $self.map_binary_op(l, *op, r $(, $arg)*)
+            }
+            $crate::primitives::Expression::Call(call, params) => {
+                $self.map_call(call, params $(, $arg)*)
+            }
+            $crate::primitives::Expression::Subscript(agg, indices) => {
+                $self.map_subscript(agg, indices $(, $arg)*)
+            }
+            $crate::primitives::Expression::If(cond, then, else_) => {
+                $self.map_if(cond, then, else_ $(, $arg)*)
+            }
+        }
+    };
+}
+
 pub mod combine;
 pub mod fold;
 pub mod identity;
+pub mod mapper;
 pub mod walk;
 
 pub trait CachedMapper<KT, VT> {
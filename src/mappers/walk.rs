@@ -34,15 +34,7 @@ pub trait UncachedWalkMapper {
 
     fn visit(&self, expr: &Expression) {
         if self.should_walk(expr) {
-            match expr {
-                Expression::Scalar(s) => self.map_scalar(&s),
-                Expression::Variable(name) => self.map_variable(name.to_string()),
-                Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x),
-                Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r),
-                Expression::Call(call, params) => self.map_call(&call, &params),
-                Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices),
-                Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_),
-            };
+            dispatch!(self, expr);
             self.post_walk(expr);
         }
     }
@@ -96,15 +88,7 @@ pub trait WalkMapperWithContext {
 
     fn visit(&self, expr: &Expression, context: &Self::Context) {
         if self.should_walk(expr, context) {
-            match expr {
-                Expression::Scalar(s) => self.map_scalar(&s, context),
-                Expression::Variable(name) => self.map_variable(name.to_string(), context),
-                Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), &x, context),
-                Expression::BinaryOp(l, op, r) => self.map_binary_op(&l, op.clone(), &r, context),
-                Expression::Call(call, params) => self.map_call(&call, &params, context),
-                Expression::Subscript(agg, indices) => self.map_subscript(&agg, &indices, context),
-                Expression::If(cond, then, else_) => self.map_if(&cond, &then, &else_, context),
-            };
+            dispatch!(self, expr, context);
             self.post_walk(expr, context);
         }
     }
@@ -163,15 +147,7 @@ pub trait WalkMapper: CachedMapper<ExpressionRawPointer, bool> {
             Some(true) => {}
             None => {
                 if self.should_walk(&expr) {
-                    match &*expr.clone() {
-                        Expression::Scalar(s) => self.map_scalar(&s),
-                        Expression::Variable(name) => self.map_variable(name.to_string()),
-                        Expression::UnaryOp(op, x) => self.map_unary_op(op.clone(), x),
-                        Expression::BinaryOp(l, op, r) => self.map_binary_op(l, op.clone(), r),
-                        Expression::Call(call, params) => self.map_call(call, &params),
-                        Expression::Subscript(agg, indices) => self.map_subscript(agg, &indices),
-                        Expression::If(cond, then, else_) => self.map_if(cond, then, else_),
-                    };
+                    dispatch!(self, &*expr.clone());
                     self.post_walk(&expr);
                 };
                 self.add_to_cache(cache_key, true);
@@ -18,15 +18,15 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use crate::mapper_impls::equality::are_structurally_equal;
-use crate::primitives::Expression;
+use crate::mapper_impls::equality::are_nodes_structurally_equal;
+use crate::primitives::{Expression, UnaryOpType};
 use crate::{define_binary_op, impl_scalar_to_expr};
 use std::iter::IntoIterator;
 use std::rc::Rc;
 
 impl PartialEq for Expression {
     fn eq(&self, other: &Self) -> bool {
-        are_structurally_equal(self, other)
+        are_nodes_structurally_equal(self, other)
     }
 }
 
@@ -48,6 +48,7 @@ impl_scalar_to_expr!(f64);
 // }}}
 
 define_binary_op!(add, Sum);
+define_binary_op!(sub, Subtract);
 define_binary_op!(mul, Product);
 define_binary_op!(div, Divide);
 define_binary_op!(floor_div, FloorDiv);
@@ -57,9 +58,30 @@ define_binary_op!(less_equal, LessEqual);
 define_binary_op!(greater, Greater);
 define_binary_op!(greater_equal, GreaterEqual);
 define_binary_op!(equal, Equal);
-define_binary_op!(not_equal, GreaterEqual);
+define_binary_op!(not_equal, NotEqual);
+define_binary_op!(bitwise_or, BitwiseOr);
+define_binary_op!(bitwise_xor, BitwiseXor);
+define_binary_op!(bitwise_and, BitwiseAnd);
+define_binary_op!(logical_and, LogicalAnd);
+define_binary_op!(logical_or, LogicalOr);
 define_binary_op!(left_shift, LeftShift);
 define_binary_op!(right_shift, RightShift);
+define_binary_op!(pow, Exponent);
+
+/// Returns the [`Expression::UnaryOp`] negating `x`.
+pub fn neg(x: &dyn ConvertibleToExpr) -> Rc<Expression> {
+    Rc::new(Expression::UnaryOp(UnaryOpType::Minus, x.to_expr()))
+}
+
+/// Returns the [`Expression::UnaryOp`] for the bitwise complement of `x`.
+pub fn bitwise_not(x: &dyn ConvertibleToExpr) -> Rc<Expression> {
+    Rc::new(Expression::UnaryOp(UnaryOpType::BitwiseNot, x.to_expr()))
+}
+
+/// Returns the [`Expression::UnaryOp`] for the logical negation of `x`.
+pub fn logical_not(x: &dyn ConvertibleToExpr) -> Rc<Expression> {
+    Rc::new(Expression::UnaryOp(UnaryOpType::LogicalNot, x.to_expr()))
+}
 
 /// Returns an [`Expression::Subscript`] with the expression `agg` indexed into
 /// via with the indices `indices`.
@@ -69,6 +91,14 @@ pub fn index<T: IntoIterator<Item = Rc<Expression>>>(agg: Rc<Expression>, indice
     Rc::new(Expression::Subscript(agg.clone(), vec_indices))
 }
 
+/// Returns an [`Expression::Call`] invoking `call` with the arguments
+/// `params`.
+pub fn call<T: IntoIterator<Item = Rc<Expression>>>(call: Rc<Expression>, params: T)
+                                                    -> Rc<Expression> {
+    let vec_params = params.into_iter().collect();
+    Rc::new(Expression::Call(call, vec_params))
+}
+
 /// Returns an [`Expression::If`] expression.
 pub fn ifthenelse(cond: Rc<Expression>, then: Rc<Expression>, else_: Rc<Expression>)
                   -> Rc<Expression> {
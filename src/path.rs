@@ -0,0 +1,352 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Addressing sub-expressions by their position in an expression.
+//!
+//! An [`ExprPath`] is the sequence of child indices followed from the root of
+//! an expression to one of its sub-expressions. The children of a node are
+//! numbered as its operands: `[x]` for a unary operation, `[left, right]` for
+//! a binary operation, `[function, *args]` for a call, `[aggregate,
+//! *indices]` for a subscript and `[cond, then, else]` for an if-then-else.
+//! Paths address positions in the expression *tree*, so a node shared by
+//! several parents is reachable via several paths.
+
+use crate::primitives::Expression;
+use crate::utils::{get_children, with_children, ExpressionRawPointer};
+use hashbrown::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+// {{{ ExprPath
+
+/// A sequence of child indices leading from the root of an expression to one
+/// of its sub-expressions. Displayed as `/i/j/...`, with `/` denoting the root.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, ExprPath};
+///
+/// let expr = parse("f(x, y + 2) * z");
+/// // left operand of the product -> second argument of f -> right operand
+/// let path = ExprPath::from(vec![0, 2, 1]);
+/// assert_eq!(path.to_string(), "/0/2/1");
+/// assert_eq!(expr.get(&path).unwrap().to_string(), "2");
+/// assert_eq!(expr.replace_at(&path, parse("3")), parse("f(x, y + 3) * z"));
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct ExprPath(Vec<usize>);
+
+impl ExprPath {
+    /// Returns the path to the root of an expression.
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.0
+    }
+
+    /// Returns the number of edges between the root and the node at `self`.
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the path to the *index*-th child of the node at `self`.
+    pub fn child(&self, index: usize) -> Self {
+        let mut indices = self.0.clone();
+        indices.push(index);
+        Self(indices)
+    }
+
+    /// Returns the path to the parent of the node at `self`, or *None* if
+    /// `self` is the root.
+    pub fn parent(&self) -> Option<Self> {
+        self.0.split_last().map(|(_, init)| Self(init.to_vec()))
+    }
+
+    /// Returns *true* if the node at `other` is the node at `self` or one of
+    /// its descendants.
+    pub fn is_prefix_of(&self, other: &ExprPath) -> bool {
+        other.0.starts_with(&self.0)
+    }
+}
+
+impl From<Vec<usize>> for ExprPath {
+    fn from(indices: Vec<usize>) -> Self {
+        Self(indices)
+    }
+}
+
+impl FromIterator<usize> for ExprPath {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl fmt::Display for ExprPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        self.0.iter().try_for_each(|index| write!(f, "/{}", index))
+    }
+}
+
+// }}}
+
+// {{{ get / replace_at
+
+impl Expression {
+    /// Returns the sub-expression of `self` at *path*, or *None* if *path*
+    /// does not exist in `self`.
+    pub fn get(self: &Rc<Self>, path: &ExprPath) -> Option<Rc<Expression>> {
+        path.0.iter().try_fold(self.clone(), |expr, &index| {
+                         get_children(&expr).get(index).cloned()
+                     })
+    }
+
+    /// Returns `self` with the sub-expression at *path* replaced by *new*.
+    /// Only the nodes along *path* are rebuilt, all other nodes are shared
+    /// with `self`.
+    ///
+    /// # Panics
+    ///
+    /// If *path* does not exist in `self`.
+    pub fn replace_at(self: &Rc<Self>, path: &ExprPath, new: Rc<Expression>) -> Rc<Expression> {
+        let mut zipper = ExprZipper::new(self.clone());
+        for &index in path.indices() {
+            if !zipper.down(index) {
+                panic!("Path '{}' does not exist in '{}'.", path, self);
+            }
+        }
+        zipper.replace(new);
+        zipper.into_root()
+    }
+}
+
+// }}}
+
+// {{{ find_paths / find_nodes
+
+/// Returns the paths of the sub-expressions of *expr* satisfying *predicate*,
+/// in pre-order. A node shared by several parents is tested once per path
+/// leading to it, so the number of paths may grow exponentially with the
+/// depth of a DAG with shared sub-expressions. See [`find_nodes`] to visit
+/// every node once.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{find_paths, parse, Expression};
+///
+/// let expr = parse("f(x) + g(x, y)");
+/// let paths = find_paths(&expr, |e| matches!(e, Expression::Variable(name) if name == "x"));
+/// let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+/// assert_eq!(paths, vec!["/0/1", "/1/1"]);
+/// ```
+pub fn find_paths<F>(expr: &Rc<Expression>, mut predicate: F) -> Vec<ExprPath>
+    where F: FnMut(&Expression) -> bool
+{
+    fn rec<F: FnMut(&Expression) -> bool>(expr: &Expression, path: &mut Vec<usize>,
+                                          predicate: &mut F, paths: &mut Vec<ExprPath>) {
+        if predicate(expr) {
+            paths.push(ExprPath(path.clone()));
+        }
+        for (index, child) in get_children(expr).iter().enumerate() {
+            path.push(index);
+            rec(child, path, predicate, paths);
+            path.pop();
+        }
+    }
+
+    let mut paths = vec![];
+    rec(expr, &mut vec![], &mut predicate, &mut paths);
+    paths
+}
+
+/// Returns the distinct sub-expressions of *expr* satisfying *predicate*, each
+/// with the first path leading to it in pre-order. Unlike [`find_paths`],
+/// every node shared by several parents is tested and descended into only
+/// once, so the cost is linear in the number of nodes of the DAG.
+///
+/// # Example
+/// ```rust
+/// use std::rc::Rc;
+/// use symoxide::{find_nodes, parse, BinaryOpType, Expression};
+///
+/// let x_plus_1 = parse("x + 1");
+/// let expr = Rc::new(Expression::BinaryOp(x_plus_1.clone(), BinaryOpType::Product, x_plus_1));
+/// let nodes = find_nodes(&expr, |e| matches!(e, Expression::Variable(_)));
+/// assert_eq!(nodes.len(), 1);
+/// assert_eq!(nodes[0].0.to_string(), "/0/0");
+/// ```
+pub fn find_nodes<F>(expr: &Rc<Expression>, mut predicate: F) -> Vec<(ExprPath, Rc<Expression>)>
+    where F: FnMut(&Expression) -> bool
+{
+    fn rec<F: FnMut(&Expression) -> bool>(expr: &Rc<Expression>, path: &mut Vec<usize>,
+                                          predicate: &mut F,
+                                          visited: &mut HashSet<ExpressionRawPointer>,
+                                          nodes: &mut Vec<(ExprPath, Rc<Expression>)>) {
+        if !visited.insert(ExpressionRawPointer(expr.clone())) {
+            return;
+        }
+        if predicate(expr) {
+            nodes.push((ExprPath(path.clone()), expr.clone()));
+        }
+        for (index, child) in get_children(expr).iter().enumerate() {
+            path.push(index);
+            rec(child, path, predicate, visited, nodes);
+            path.pop();
+        }
+    }
+
+    let mut nodes = vec![];
+    rec(expr,
+        &mut vec![],
+        &mut predicate,
+        &mut HashSet::new(),
+        &mut nodes);
+    nodes
+}
+
+// }}}
+
+// {{{ ExprZipper
+
+/// A cursor into an expression that can move between a node, its parent and
+/// its siblings, and replace the node it points to. Moving up rebuilds the
+/// parent only if its child was replaced, so untouched parts of the
+/// expression remain shared with the original one.
+///
+/// # Example
+/// ```rust
+/// use symoxide::{parse, ExprZipper};
+///
+/// let mut zipper = ExprZipper::new(parse("a[i, j] + b"));
+/// assert!(zipper.down(0) && zipper.down(2));
+/// assert_eq!(zipper.path().to_string(), "/0/2");
+/// zipper.replace(parse("j + 1"));
+/// assert!(zipper.left());
+/// assert_eq!(zipper.focus().to_string(), "i");
+/// assert_eq!(zipper.into_root(), parse("a[i, j + 1] + b"));
+/// ```
+pub struct ExprZipper {
+    focus: Rc<Expression>,
+    /// The ancestors of *focus*, each with the index of the child leading
+    /// towards *focus*. The parent of *focus* is the last entry.
+    ancestors: Vec<(Rc<Expression>, usize)>,
+}
+
+impl ExprZipper {
+    /// Returns a zipper focused on *root*.
+    pub fn new(root: Rc<Expression>) -> Self {
+        Self { focus: root,
+               ancestors: vec![] }
+    }
+
+    pub fn focus(&self) -> &Rc<Expression> {
+        &self.focus
+    }
+
+    /// Returns the path from the root to the focused node.
+    pub fn path(&self) -> ExprPath {
+        self.ancestors.iter().map(|(_, index)| *index).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.ancestors.is_empty()
+    }
+
+    /// Replaces the focused node with *new*.
+    pub fn replace(&mut self, new: Rc<Expression>) {
+        self.focus = new;
+    }
+
+    /// Moves the focus to the *index*-th child of the focused node. Returns
+    /// *false*, leaving the zipper unchanged, if no such child exists.
+    pub fn down(&mut self, index: usize) -> bool {
+        match get_children(&self.focus).get(index) {
+            Some(child) => {
+                let parent = std::mem::replace(&mut self.focus, child.clone());
+                self.ancestors.push((parent, index));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the focus to the parent of the focused node. Returns *false* if
+    /// the zipper is focused on the root.
+    pub fn up(&mut self) -> bool {
+        match self.ancestors.pop() {
+            Some((parent, index)) => {
+                let mut children = get_children(&parent);
+                children[index] = self.focus.clone();
+                self.focus = with_children(&parent, children);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn move_to_sibling(&mut self, index: Option<usize>) -> bool {
+        match index {
+            Some(index) if !self.is_root() => {
+                let (parent, _) = self.ancestors.last().unwrap();
+                if index >= get_children(parent).len() {
+                    return false;
+                }
+                self.up();
+                self.down(index)
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves the focus to the previous sibling of the focused node. Returns
+    /// *false* if there is none.
+    pub fn left(&mut self) -> bool {
+        let index = self.ancestors
+                        .last()
+                        .and_then(|(_, index)| index.checked_sub(1));
+        self.move_to_sibling(index)
+    }
+
+    /// Moves the focus to the next sibling of the focused node. Returns
+    /// *false* if there is none.
+    pub fn right(&mut self) -> bool {
+        let index = self.ancestors.last().map(|(_, index)| index + 1);
+        self.move_to_sibling(index)
+    }
+
+    /// Returns the root of the expression, including all replacements made
+    /// through the zipper.
+    pub fn into_root(mut self) -> Rc<Expression> {
+        while self.up() {}
+        self.focus
+    }
+}
+
+// }}}
+
+// vim: fdm=marker
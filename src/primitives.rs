@@ -41,6 +41,102 @@ pub enum LiteralT {
     F64(f64),
 }
 
+/// Data-types of the values an expression can evaluate to. See [`LiteralT`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DType {
+    U8,
+    U16,
+    U32,
+    U64,
+
+    I8,
+    I16,
+    I32,
+    I64,
+
+    F32,
+    F64,
+}
+
+impl DType {
+    /// Returns *true* only if the data-type is a signed or unsigned integer
+    /// type.
+    pub fn is_integral(&self) -> bool {
+        !matches!(self, DType::F32 | DType::F64)
+    }
+}
+
+impl LiteralT {
+    pub fn dtype(&self) -> DType {
+        match self {
+            LiteralT::U8(_) => DType::U8,
+            LiteralT::U16(_) => DType::U16,
+            LiteralT::U32(_) => DType::U32,
+            LiteralT::U64(_) => DType::U64,
+
+            LiteralT::I8(_) => DType::I8,
+            LiteralT::I16(_) => DType::I16,
+            LiteralT::I32(_) => DType::I32,
+            LiteralT::I64(_) => DType::I64,
+
+            LiteralT::F32(_) => DType::F32,
+            LiteralT::F64(_) => DType::F64,
+        }
+    }
+
+    /// Returns *true* only if the literal is of a signed or unsigned integer
+    /// type.
+    pub fn is_integral(&self) -> bool {
+        !matches!(self, LiteralT::F32(_) | LiteralT::F64(_))
+    }
+
+    /// Returns the literal's value casted to an `f64`.
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            LiteralT::U8(x) => x as f64,
+            LiteralT::U16(x) => x as f64,
+            LiteralT::U32(x) => x as f64,
+            LiteralT::U64(x) => x as f64,
+
+            LiteralT::I8(x) => x as f64,
+            LiteralT::I16(x) => x as f64,
+            LiteralT::I32(x) => x as f64,
+            LiteralT::I64(x) => x as f64,
+
+            LiteralT::F32(x) => x as f64,
+            LiteralT::F64(x) => x,
+        }
+    }
+
+    /// Returns the literal's value casted to an `i64`. Floating point values
+    /// are truncated towards zero.
+    pub fn as_i64(&self) -> i64 {
+        match *self {
+            LiteralT::U8(x) => x as i64,
+            LiteralT::U16(x) => x as i64,
+            LiteralT::U32(x) => x as i64,
+            LiteralT::U64(x) => x as i64,
+
+            LiteralT::I8(x) => x as i64,
+            LiteralT::I16(x) => x as i64,
+            LiteralT::I32(x) => x as i64,
+            LiteralT::I64(x) => x,
+
+            LiteralT::F32(x) => x as i64,
+            LiteralT::F64(x) => x as i64,
+        }
+    }
+
+    /// Returns *true* if the literal is non-zero.
+    pub fn is_truthy(&self) -> bool {
+        if self.is_integral() {
+            self.as_i64() != 0
+        } else {
+            self.as_f64() != 0.0
+        }
+    }
+}
+
 /// The core expression type. The different arms of thie enum describe the
 /// expression.
 #[derive(Clone)]
@@ -73,7 +169,7 @@ pub enum Expression {
 /// un-defined, for example Python and C disagree on their `Modulo` semantics
 /// for negative denominators. And so, it is upto the downstream user to lower
 /// this expression as they seem fit to.
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum BinaryOpType {
     Sum,
     Subtract,
@@ -103,7 +199,7 @@ pub enum BinaryOpType {
 }
 
 /// Unary Operation types.
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum UnaryOpType {
     LogicalNot,
     BitwiseNot,
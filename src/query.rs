@@ -0,0 +1,449 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Selecting sub-expressions with composable [`Selector`]s.
+//!
+//! A selector is a predicate on a node *in its position* within an
+//! expression: besides the node itself it may constrain its operands (via
+//! [`Selector::with_child`], [`Selector::with_descendant`], ...) and its
+//! ancestors (via [`Selector::child_of`] and [`Selector::descendant_of`]).
+//! [`select`] returns every node matched by a selector along with its
+//! [`ExprPath`], and [`replace_matches`] rewrites them.
+//!
+//! # Example
+//! ```rust
+//! use symoxide::query::{select, Selector};
+//! use symoxide::parse;
+//!
+//! // calls to hankel_1 whose first argument is a negative literal
+//! let selector = Selector::call("hankel_1").with_arg(0, Selector::literal(|x| x.as_f64() < 0.0));
+//! let expr = parse("hankel_1(-2, x) + hankel_1(2, x) * j_0(-1, x)");
+//! let matches = select(&expr, &selector);
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].path.to_string(), "/0");
+//! assert_eq!(matches[0].expr.to_string(), "hankel_1(-2, x)");
+//! ```
+
+use crate::mapper_impls::stats::NodeKind;
+use crate::path::ExprPath;
+use crate::primitives::{BinaryOpType, Expression, LiteralT, UnaryOpType};
+use crate::utils::{get_children, with_children, ExpressionRawPointer};
+use hashbrown::{HashMap, HashSet};
+use std::ops::Not;
+use std::rc::Rc;
+
+// {{{ Selector
+
+/// Results of the ancestor-independent parts of selectors, shared by all the
+/// tests made during a single query. Keyed by the address of the selector's
+/// predicate and the node.
+#[derive(Default)]
+struct QueryMemo {
+    has_descendant: HashMap<(usize, ExpressionRawPointer), bool>,
+}
+
+type PredicateT = dyn Fn(&Rc<Expression>, &[Rc<Expression>], &mut QueryMemo) -> bool;
+
+/// A predicate on a node and its ancestors. Cheap to clone. See the
+/// [module-level documentation](self).
+#[derive(Clone)]
+pub struct Selector {
+    /// Called with the node and its ancestors, ordered from the root to the
+    /// node's parent.
+    predicate: Rc<PredicateT>,
+    /// *false* if *predicate* ignores the ancestors, in which case its result
+    /// only depends on the node.
+    uses_ancestors: bool,
+}
+
+impl Selector {
+    fn new<F>(predicate: F) -> Self
+        where F: Fn(&Rc<Expression>, &[Rc<Expression>], &mut QueryMemo) -> bool + 'static
+    {
+        Self { predicate: Rc::new(predicate),
+               uses_ancestors: false }
+    }
+
+    fn using_ancestors(self, uses_ancestors: bool) -> Self {
+        Self { uses_ancestors: self.uses_ancestors || uses_ancestors,
+               ..self }
+    }
+
+    fn matches_memoized(&self, expr: &Rc<Expression>, ancestors: &[Rc<Expression>],
+                        memo: &mut QueryMemo)
+                        -> bool {
+        (self.predicate)(expr, ancestors, memo)
+    }
+
+    /// Returns *true* if the node *expr* whose ancestors are *ancestors*
+    /// (ordered from the root to the parent) is matched by `self`.
+    pub fn matches_in(&self, expr: &Rc<Expression>, ancestors: &[Rc<Expression>]) -> bool {
+        self.matches_memoized(expr, ancestors, &mut QueryMemo::default())
+    }
+
+    /// Returns *true* if `self` matches *expr* as the root of an expression.
+    pub fn matches(&self, expr: &Rc<Expression>) -> bool {
+        self.matches_in(expr, &[])
+    }
+
+    // {{{ node selectors
+
+    /// Matches every node.
+    pub fn any() -> Self {
+        Self::new(|_, _, _| true)
+    }
+
+    /// Matches the nodes satisfying *predicate*.
+    pub fn predicate<F: Fn(&Expression) -> bool + 'static>(predicate: F) -> Self {
+        Self::new(move |expr, _, _| predicate(expr))
+    }
+    /// Matches the nodes of kind *kind*.
+    pub fn kind(kind: NodeKind) -> Self {
+        Self::predicate(move |expr| NodeKind::of(expr) == kind)
+    }
+
+    /// Matches the variable named *name*.
+    pub fn variable(name: &str) -> Self {
+        let name = name.to_string();
+        Self::predicate(move |expr| matches!(expr, Expression::Variable(x) if *x == name))
+    }
+
+    /// Matches the literals satisfying *predicate*.
+    pub fn literal<F: Fn(&LiteralT) -> bool + 'static>(predicate: F) -> Self {
+        Self::predicate(move |expr| matches!(expr, Expression::Scalar(x) if predicate(x)))
+    }
+
+    /// Matches the unary operations of type *op*.
+    pub fn unary_op(op: UnaryOpType) -> Self {
+        Self::predicate(move |expr| matches!(expr, Expression::UnaryOp(x, _) if *x == op))
+    }
+
+    /// Matches the binary operations of type *op*.
+    pub fn binary_op(op: BinaryOpType) -> Self {
+        Self::predicate(move |expr| matches!(expr, Expression::BinaryOp(_, x, _) if *x == op))
+    }
+
+    /// Matches the calls to the function named *name*.
+    pub fn call(name: &str) -> Self {
+        let name = name.to_string();
+        Self::predicate(move |expr| match expr {
+            Expression::Call(function, _) => {
+                matches!(&**function, Expression::Variable(x) if *x == name)
+            }
+            _ => false,
+        })
+    }
+
+    /// Matches the subscripts of the array named *name*.
+    pub fn subscript(name: &str) -> Self {
+        let name = name.to_string();
+        Self::predicate(move |expr| match expr {
+            Expression::Subscript(agg, _) => {
+                matches!(&**agg, Expression::Variable(x) if *x == name)
+            }
+            _ => false,
+        })
+    }
+
+    // }}}
+
+    // {{{ combinators
+
+    /// Matches the nodes matched by both `self` and *other*.
+    pub fn and(self, other: Selector) -> Self {
+        let uses_ancestors = self.uses_ancestors || other.uses_ancestors;
+        Self::new(move |expr, ancestors, memo| {
+            self.matches_memoized(expr, ancestors, memo)
+            && other.matches_memoized(expr, ancestors, memo)
+        }).using_ancestors(uses_ancestors)
+    }
+
+    /// Matches the nodes matched by `self` or *other*.
+    pub fn or(self, other: Selector) -> Self {
+        let uses_ancestors = self.uses_ancestors || other.uses_ancestors;
+        Self::new(move |expr, ancestors, memo| {
+            self.matches_memoized(expr, ancestors, memo)
+            || other.matches_memoized(expr, ancestors, memo)
+        }).using_ancestors(uses_ancestors)
+    }
+
+    // }}}
+
+    // {{{ axes
+
+    /// Matches the nodes matched by `self` whose *index*-th child, numbered
+    /// as in [`ExprPath`], is matched by *child*.
+    pub fn with_child(self, index: usize, child: Selector) -> Self {
+        let uses_ancestors = child.uses_ancestors;
+        self.and(Self::new(move |expr, ancestors, memo| {
+                     get_children(expr).get(index).is_some_and(|x| {
+                                                      child.matches_memoized(x,
+                                                                             &push(ancestors, expr),
+                                                                             memo)
+                                                  })
+                 }).using_ancestors(uses_ancestors))
+    }
+
+    /// Matches the calls matched by `self` whose *index*-th argument is
+    /// matched by *arg*.
+    pub fn with_arg(self, index: usize, arg: Selector) -> Self {
+        self.and(Selector::kind(NodeKind::Call))
+            .with_child(index + 1, arg)
+    }
+
+    /// Matches the nodes matched by `self` with at least one child matched
+    /// by *child*.
+    pub fn with_any_child(self, child: Selector) -> Self {
+        let uses_ancestors = child.uses_ancestors;
+        self.and(Self::new(move |expr, ancestors, memo| {
+                     let ancestors = push(ancestors, expr);
+                     get_children(expr).iter()
+                                       .any(|x| child.matches_memoized(x, &ancestors, memo))
+                 }).using_ancestors(uses_ancestors))
+    }
+
+    /// Matches the nodes matched by `self` with at least one proper
+    /// descendant matched by *descendant*.
+    ///
+    /// Unless *descendant* constrains the ancestors of the nodes it matches,
+    /// the descendants of a node are only searched once per query, however
+    /// many parents share it.
+    pub fn with_descendant(self, descendant: Selector) -> Self {
+        /// Searches every path below *expr*, as *descendant* may depend on
+        /// the ancestors of the nodes it tests.
+        fn rec_in_context(expr: &Rc<Expression>, ancestors: &mut Vec<Rc<Expression>>,
+                          descendant: &Selector, memo: &mut QueryMemo)
+                          -> bool {
+            ancestors.push(expr.clone());
+            let found = get_children(expr).iter().any(|x| {
+                                                     descendant.matches_memoized(x, ancestors, memo)
+                                                     || rec_in_context(x, ancestors, descendant,
+                                                                       memo)
+                                                 });
+            ancestors.pop();
+            found
+        }
+
+        /// Searches every node below *expr* once.
+        fn rec(expr: &Rc<Expression>, descendant: &Selector, memo: &mut QueryMemo) -> bool {
+            let key = (Rc::as_ptr(&descendant.predicate) as *const () as usize,
+                       ExpressionRawPointer(expr.clone()));
+            if let Some(found) = memo.has_descendant.get(&key) {
+                return *found;
+            }
+            let found = get_children(expr).iter().any(|x| {
+                                                     descendant.matches_memoized(x, &[], memo)
+                                                     || rec(x, descendant, memo)
+                                                 });
+            memo.has_descendant.insert(key, found);
+            found
+        }
+
+        let uses_ancestors = descendant.uses_ancestors;
+        self.and(Self::new(move |expr, ancestors, memo| {
+                     if descendant.uses_ancestors {
+                         rec_in_context(expr, &mut ancestors.to_vec(), &descendant, memo)
+                     } else {
+                         rec(expr, &descendant, memo)
+                     }
+                 }).using_ancestors(uses_ancestors))
+    }
+
+    /// Matches the nodes matched by `self` whose parent is matched by
+    /// *parent*.
+    pub fn child_of(self, parent: Selector) -> Self {
+        self.and(Self::new(move |_, ancestors, memo| match ancestors.split_last() {
+                     Some((x, rest)) => parent.matches_memoized(x, rest, memo),
+                     None => false,
+                 }).using_ancestors(true))
+    }
+
+    /// Matches the nodes matched by `self` with at least one proper ancestor
+    /// matched by *ancestor*.
+    pub fn descendant_of(self, ancestor: Selector) -> Self {
+        self.and(Self::new(move |_, ancestors, memo| {
+                     (0..ancestors.len()).any(|i| {
+                                             ancestor.matches_memoized(&ancestors[i],
+                                                                       &ancestors[..i],
+                                                                       memo)
+                                         })
+                 }).using_ancestors(true))
+    }
+
+    // }}}
+}
+
+impl Not for Selector {
+    type Output = Selector;
+
+    /// Matches the nodes not matched by `self`.
+    fn not(self) -> Self::Output {
+        let uses_ancestors = self.uses_ancestors;
+        Self::new(move |expr, ancestors, memo| !self.matches_memoized(expr, ancestors, memo))
+            .using_ancestors(uses_ancestors)
+    }
+}
+
+fn push(ancestors: &[Rc<Expression>], expr: &Rc<Expression>) -> Vec<Rc<Expression>> {
+    ancestors.iter()
+             .chain(std::iter::once(expr))
+             .cloned()
+             .collect()
+}
+
+// }}}
+
+// {{{ select / replace_matches
+
+/// A node matched by a [`Selector`].
+#[derive(Clone)]
+pub struct QueryMatch {
+    pub path: ExprPath,
+    pub expr: Rc<Expression>,
+}
+
+/// Identifies a node along with, if *selector* depends on them, its ancestors.
+/// Visiting a node twice in the same context gives the same results.
+fn get_visit_key(expr: &Rc<Expression>, ancestors: &[Rc<Expression>], selector: &Selector)
+                 -> (ExpressionRawPointer, Vec<ExpressionRawPointer>) {
+    let context = match selector.uses_ancestors {
+        true => ancestors.iter()
+                         .map(|x| ExpressionRawPointer(x.clone()))
+                         .collect(),
+        false => vec![],
+    };
+    (ExpressionRawPointer(expr.clone()), context)
+}
+
+/// Returns the distinct nodes of *expr* matched by *selector* in pre-order,
+/// each with the first path at which it is matched.
+///
+/// A node shared by several parents is tested once, unless *selector*
+/// constrains ancestors (via [`Selector::child_of`] or
+/// [`Selector::descendant_of`]), in which case it is tested once per distinct
+/// sequence of ancestors leading to it.
+pub fn select(expr: &Rc<Expression>, selector: &Selector) -> Vec<QueryMatch> {
+    struct Walk<'a> {
+        selector: &'a Selector,
+        memo: QueryMemo,
+        visited: HashSet<(ExpressionRawPointer, Vec<ExpressionRawPointer>)>,
+        matched: HashSet<ExpressionRawPointer>,
+        matches: Vec<QueryMatch>,
+    }
+
+    fn rec(expr: &Rc<Expression>, ancestors: &mut Vec<Rc<Expression>>, path: &mut Vec<usize>,
+           walk: &mut Walk) {
+        if !walk.visited
+                .insert(get_visit_key(expr, ancestors, walk.selector))
+        {
+            return;
+        }
+        if walk.selector
+               .matches_memoized(expr, ancestors, &mut walk.memo)
+           && walk.matched.insert(ExpressionRawPointer(expr.clone()))
+        {
+            walk.matches
+                .push(QueryMatch { path: ExprPath::from(path.clone()),
+                                   expr: expr.clone() });
+        }
+        ancestors.push(expr.clone());
+        for (index, child) in get_children(expr).iter().enumerate() {
+            path.push(index);
+            rec(child, ancestors, path, walk);
+            path.pop();
+        }
+        ancestors.pop();
+    }
+
+    let mut walk = Walk { selector,
+                          memo: QueryMemo::default(),
+                          visited: HashSet::new(),
+                          matched: HashSet::new(),
+                          matches: vec![] };
+    rec(expr, &mut vec![], &mut vec![], &mut walk);
+    walk.matches
+}
+
+/// Returns *expr* with every outermost node matched by *selector* replaced by
+/// `f(node)`. Matches nested within other matches are not visited.
+///
+/// *f* is called once per distinct matched node, and the rewritten expression
+/// shares its unchanged sub-expressions with *expr*. Nodes shared by several
+/// parents are visited as in [`select`].
+///
+/// # Example
+/// ```rust
+/// use symoxide::query::{replace_matches, Selector};
+/// use symoxide::{parse, BinaryOpType};
+///
+/// let expr = parse("(x + 1) * (y + (z + 2))");
+/// let sums = Selector::binary_op(BinaryOpType::Sum);
+/// assert_eq!(replace_matches(&expr, &sums, |_| parse("0")), parse("0 * 0"));
+/// ```
+pub fn replace_matches<F>(expr: &Rc<Expression>, selector: &Selector, mut f: F) -> Rc<Expression>
+    where F: FnMut(&Rc<Expression>) -> Rc<Expression>
+{
+    struct Rewrite<'a, F> {
+        selector: &'a Selector,
+        f: F,
+        memo: QueryMemo,
+        cache: HashMap<(ExpressionRawPointer, Vec<ExpressionRawPointer>), Rc<Expression>>,
+        replacements: HashMap<ExpressionRawPointer, Rc<Expression>>,
+    }
+
+    fn rec<F>(expr: &Rc<Expression>, ancestors: &mut Vec<Rc<Expression>>, rewrite: &mut Rewrite<F>)
+              -> Rc<Expression>
+        where F: FnMut(&Rc<Expression>) -> Rc<Expression>
+    {
+        let key = get_visit_key(expr, ancestors, rewrite.selector);
+        if let Some(result) = rewrite.cache.get(&key) {
+            return result.clone();
+        }
+        let result = if rewrite.selector
+                               .matches_memoized(expr, ancestors, &mut rewrite.memo)
+        {
+            let f = &mut rewrite.f;
+            rewrite.replacements
+                   .entry(ExpressionRawPointer(expr.clone()))
+                   .or_insert_with(|| f(expr))
+                   .clone()
+        } else {
+            ancestors.push(expr.clone());
+            let children = get_children(expr).iter()
+                                             .map(|child| rec(child, ancestors, rewrite))
+                                             .collect();
+            ancestors.pop();
+            with_children(expr, children)
+        };
+        rewrite.cache.insert(key, result.clone());
+        result
+    }
+
+    let mut rewrite = Rewrite { selector,
+                                f: &mut f,
+                                memo: QueryMemo::default(),
+                                cache: HashMap::new(),
+                                replacements: HashMap::new() };
+    rec(expr, &mut vec![], &mut rewrite)
+}
+
+// }}}
+
+// vim: fdm=marker
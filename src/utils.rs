@@ -24,7 +24,9 @@
 // IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::primitives::Expression;
+use crate::primitives::{Expression, SmallVecExprT};
+use hashbrown::HashSet;
+use smallvec::smallvec;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
@@ -59,3 +61,68 @@ impl Hash for ExpressionRawPointer {
 }
 
 // }}}
+
+/// Returns the operands of `expr`, i.e. `[x]` for a unary operation, `[left,
+/// right]` for a binary operation, `[call, *params]` for a call, `[agg,
+/// *indices]` for a subscript and `[cond, then, else]` for an if-then-else
+/// expression.
+pub(crate) fn get_children(expr: &Expression) -> SmallVecExprT {
+    match expr {
+        Expression::Scalar(_) | Expression::Variable(_) => smallvec![],
+        Expression::UnaryOp(_, x) => smallvec![x.clone()],
+        Expression::BinaryOp(left, _, right) => smallvec![left.clone(), right.clone()],
+        Expression::Call(call, params) => std::iter::once(call.clone()).chain(params.iter()
+                                                                                    .cloned())
+                                                                       .collect(),
+        Expression::Subscript(agg, indices) => std::iter::once(agg.clone()).chain(indices.iter()
+                                                                                         .cloned())
+                                                                           .collect(),
+        Expression::If(cond, then, else_) => smallvec![cond.clone(), then.clone(), else_.clone()],
+    }
+}
+
+/// Returns `expr` with its operands replaced by `children`, ordered as in
+/// [`get_children`]. Returns `expr` itself if every child is pointer-equal to
+/// the operand it replaces.
+pub(crate) fn with_children(expr: &Rc<Expression>, children: SmallVecExprT) -> Rc<Expression> {
+    if get_children(expr).iter()
+                         .zip(children.iter())
+                         .all(|(old, new)| Rc::ptr_eq(old, new))
+    {
+        return expr.clone();
+    }
+
+    let new_expr = match &**expr {
+        Expression::Scalar(_) | Expression::Variable(_) => return expr.clone(),
+        Expression::UnaryOp(op, _) => Expression::UnaryOp(*op, children[0].clone()),
+        Expression::BinaryOp(_, op, _) => {
+            Expression::BinaryOp(children[0].clone(), *op, children[1].clone())
+        }
+        Expression::Call(..) => Expression::Call(children[0].clone(), children[1..].into()),
+        Expression::Subscript(..) => {
+            Expression::Subscript(children[0].clone(), children[1..].into())
+        }
+        Expression::If(..) => Expression::If(children[0].clone(),
+                                             children[1].clone(),
+                                             children[2].clone()),
+    };
+    Rc::new(new_expr)
+}
+
+/// Returns the nodes of the DAG `expr` such that every node appears after all
+/// its operands. Each node appears exactly once.
+pub(crate) fn get_topological_order(expr: &Rc<Expression>) -> Vec<Rc<Expression>> {
+    fn rec(expr: &Rc<Expression>, visited: &mut HashSet<ExpressionRawPointer>,
+           order: &mut Vec<Rc<Expression>>) {
+        if visited.insert(ExpressionRawPointer(expr.clone())) {
+            for child in get_children(expr) {
+                rec(&child, visited, order);
+            }
+            order.push(expr.clone());
+        }
+    }
+
+    let mut order = vec![];
+    rec(expr, &mut HashSet::new(), &mut order);
+    order
+}
@@ -0,0 +1,582 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Implementation of `expr!`: a compile-time parser for the grammar of
+//! symoxide's `parse_py_flavor.lalrpop` that emits calls to the builders in
+//! `symoxide::operations`.
+
+use proc_macro2::{Delimiter, Ident, Literal, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned};
+use syn::{Error, Lit, Result};
+
+// {{{ lexing
+
+#[derive(Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Int(i32),
+    Float(f64),
+    /// An operator or a delimiter.
+    Op(&'static str),
+    /// `#name`
+    Interpolation(Ident),
+}
+
+#[derive(Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+    /// *true* if the token is immediately followed by the next one, i.e.
+    /// without any whitespace in between.
+    is_glued: bool,
+}
+
+const OPERATORS: [&str; 25] = ["**", "//", "==", "!=", ">=", "<=", "<<", ">>", "+", "-", "*", "/",
+                               "%", "<", ">", "|", "^", "&", "~", "(", ")", "[", "]", ",", "#"];
+
+const KEYWORDS: [&str; 5] = ["if", "else", "and", "or", "not"];
+
+fn get_operator(text: &str) -> Option<&'static str> {
+    OPERATORS.iter().find(|op| **op == text).copied()
+}
+
+fn lex_literal(literal: &Literal) -> Result<TokenKind> {
+    let span = literal.span();
+    match Lit::new(literal.clone()) {
+        Lit::Int(x) if x.suffix().is_empty() => {
+            x.base10_parse::<i32>()
+             .map(TokenKind::Int)
+             .map_err(|_| Error::new(span, "integer literal does not fit in an i32"))
+        }
+        Lit::Float(x) if x.suffix().is_empty() => x.base10_parse::<f64>().map(TokenKind::Float),
+        Lit::Int(_) | Lit::Float(_) => {
+            Err(Error::new(span, "literals with a type suffix are not supported"))
+        }
+        _ => Err(Error::new(span, "expected an integer or a floating point literal")),
+    }
+}
+
+/// Lexes the Rust tokens *input*. `//` starts a comment in Rust, so floor
+/// divisions can only be spelled in the string literal form of `expr!`.
+fn lex_token_stream(input: TokenStream, tokens: &mut Vec<Token>) -> Result<()> {
+    let mut trees = input.into_iter().peekable();
+    while let Some(tree) = trees.next() {
+        match tree {
+            TokenTree::Ident(ident) => {
+                tokens.push(Token { kind: TokenKind::Ident(ident.to_string()),
+                                    span: ident.span(),
+                                    is_glued: false });
+            }
+            TokenTree::Literal(literal) => {
+                tokens.push(Token { kind: lex_literal(&literal)?,
+                                    span: literal.span(),
+                                    is_glued: false });
+            }
+            TokenTree::Punct(punct) => {
+                let span = punct.span();
+                if punct.as_char() == '#' {
+                    match trees.next() {
+                        Some(TokenTree::Ident(ident)) => {
+                            tokens.push(Token { kind: TokenKind::Interpolation(ident),
+                                                span,
+                                                is_glued: false });
+                            continue;
+                        }
+                        _ => return Err(Error::new(span, "expected an identifier after '#'")),
+                    }
+                }
+                let mut text = punct.as_char().to_string();
+                if punct.spacing() == proc_macro2::Spacing::Joint {
+                    if let Some(TokenTree::Punct(next)) = trees.peek() {
+                        let joined = format!("{}{}", text, next.as_char());
+                        if get_operator(&joined).is_some() {
+                            text = joined;
+                            trees.next();
+                        }
+                    }
+                }
+                let op = get_operator(&text).ok_or_else(|| {
+                                                Error::new(span, format!("unexpected '{}'", text))
+                                            })?;
+                // whitespace is not part of the token stream, a sign is glued
+                // to the literal that starts where the sign ends
+                let is_glued = match trees.peek() {
+                    Some(TokenTree::Literal(literal)) => span.end() == literal.span().start(),
+                    _ => false,
+                };
+                tokens.push(Token { kind: TokenKind::Op(op),
+                                    span,
+                                    is_glued });
+            }
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => {
+                        lex_token_stream(group.stream(), tokens)?;
+                        continue;
+                    }
+                    Delimiter::Brace => {
+                        return Err(Error::new(group.span(), "braces are not supported"))
+                    }
+                };
+                tokens.push(Token { kind: TokenKind::Op(open),
+                                    span: group.span_open(),
+                                    is_glued: false });
+                lex_token_stream(group.stream(), tokens)?;
+                tokens.push(Token { kind: TokenKind::Op(close),
+                                    span: group.span_close(),
+                                    is_glued: false });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lexes the contents of the string literal form of `expr!`. All tokens are
+/// attributed the span of the string literal.
+fn lex_str(text: &str, span: Span) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut i = 0;
+    let error = |msg: String| Error::new(span, msg);
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let kind = if c.is_whitespace() {
+            if let Some(last) = tokens.last_mut() {
+                last.is_glued = false;
+            }
+            i += 1;
+            continue;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            TokenKind::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() {
+            let mut is_float = false;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == '.' {
+                is_float = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                is_float = true;
+                i += 1;
+                if i < chars.len() && chars[i] == '-' {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            if is_float {
+                TokenKind::Float(literal.parse()
+                                        .map_err(|_| {
+                                            error(format!("invalid float literal '{}'", literal))
+                                        })?)
+            } else {
+                TokenKind::Int(literal.parse().map_err(|_| {
+                                                   error(format!("integer literal '{}' does not \
+                                                                 fit in an i32",
+                                                                 literal))
+                                               })?)
+            }
+        } else if c == '#' {
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start + 1..i].iter().collect();
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(error("expected an identifier after '#'".to_string()));
+            }
+            TokenKind::Interpolation(Ident::new(&name, span))
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            match get_operator(&two).or_else(|| get_operator(&c.to_string())) {
+                Some(op) => {
+                    i += op.len();
+                    TokenKind::Op(op)
+                }
+                None => return Err(error(format!("unexpected character '{}'", c))),
+            }
+        };
+        tokens.push(Token { kind,
+                            span,
+                            is_glued: true });
+    }
+    Ok(tokens)
+}
+
+// }}}
+
+// {{{ parsing
+
+/// A recursive descent parser with one function per precedence level of the
+/// grammar. Every function returns the tokens of a Rust expression of type
+/// `Rc<symoxide::Expression>`.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Span reported for errors at the end of the input.
+    end_span: Span,
+}
+
+fn builder(name: &str, span: Span) -> TokenStream {
+    let name = Ident::new(name, span);
+    quote_spanned! {span=> ::symoxide::operations::#name}
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn span(&self) -> Span {
+        self.peek().map_or(self.end_span, |token| token.span)
+    }
+
+    fn peek_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Op(x), .. }) if *x == op)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Ident(x), .. }) if x == keyword)
+    }
+
+    /// Consumes the next token if it is one of the operators *ops*.
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<(&'static str, Span)> {
+        match self.peek() {
+            Some(Token { kind: TokenKind::Op(x),
+                         span,
+                         .. })
+                if ops.contains(x) =>
+            {
+                let result = (*x, *span);
+                self.pos += 1;
+                Some(result)
+            }
+            _ => None,
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> Option<Span> {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            Some(self.tokens[self.pos - 1].span)
+        } else {
+            None
+        }
+    }
+
+    fn expect_op(&mut self, op: &'static str) -> Result<()> {
+        match self.eat_op(&[op]) {
+            Some(_) => Ok(()),
+            None => Err(self.error(&format!("expected '{}'", op))),
+        }
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        match self.peek() {
+            Some(_) => Error::new(self.span(), msg),
+            None => Error::new(self.end_span,
+                               format!("{}, found the end of the expression", msg)),
+        }
+    }
+
+    fn parse(&mut self) -> Result<TokenStream> {
+        let result = self.parse_if()?;
+        match self.peek() {
+            Some(_) => Err(self.error("unexpected token")),
+            None => Ok(result),
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<TokenStream> {
+        let then = self.parse_or()?;
+        match self.eat_keyword("if") {
+            Some(span) => {
+                let cond = self.parse_or()?;
+                if self.eat_keyword("else").is_none() {
+                    return Err(self.error("expected 'else'"));
+                }
+                let else_ = self.parse_if()?;
+                let ifthenelse = builder("ifthenelse", span);
+                Ok(quote! {#ifthenelse(#cond, #then, #else_)})
+            }
+            None => Ok(then),
+        }
+    }
+
+    /// Parses a left-associative chain of binary operations with the
+    /// operands parsed by *parse_operand*.
+    fn parse_binary_ops(&mut self, ops: &[(&'static str, &str)],
+                        parse_operand: fn(&mut Self) -> Result<TokenStream>)
+                        -> Result<TokenStream> {
+        let mut result = parse_operand(self)?;
+        loop {
+            let matched = ops.iter().find_map(|(op, name)| {
+                                        let is_match = if KEYWORDS.contains(op) {
+                                            self.peek_keyword(op)
+                                        } else {
+                                            self.peek_op(op)
+                                        };
+                                        is_match.then_some(*name)
+                                    });
+            match matched {
+                Some(name) => {
+                    let span = self.span();
+                    self.pos += 1;
+                    let right = parse_operand(self)?;
+                    let function = builder(name, span);
+                    result = quote! {#function(&(#result), &(#right))};
+                }
+                None => return Ok(result),
+            }
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("or", "logical_or")], Self::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("and", "logical_and")], Self::parse_not)
+    }
+
+    fn parse_not(&mut self) -> Result<TokenStream> {
+        match self.eat_keyword("not") {
+            Some(span) => {
+                let x = self.parse_not()?;
+                let function = builder("logical_not", span);
+                Ok(quote! {#function(&(#x))})
+            }
+            None => self.parse_comparison(),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("==", "equal"),
+                                ("!=", "not_equal"),
+                                (">=", "greater_equal"),
+                                ("<=", "less_equal"),
+                                (">", "greater"),
+                                ("<", "less")],
+                              Self::parse_bitwise_or)
+    }
+
+    fn parse_bitwise_or(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("|", "bitwise_or")], Self::parse_bitwise_xor)
+    }
+
+    fn parse_bitwise_xor(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("^", "bitwise_xor")], Self::parse_bitwise_and)
+    }
+
+    fn parse_bitwise_and(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("&", "bitwise_and")], Self::parse_shift)
+    }
+
+    fn parse_shift(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("<<", "left_shift"), (">>", "right_shift")],
+                              Self::parse_sum)
+    }
+
+    fn parse_sum(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("+", "add"), ("-", "sub")], Self::parse_product)
+    }
+
+    fn parse_product(&mut self) -> Result<TokenStream> {
+        self.parse_binary_ops(&[("*", "mul"),
+                                ("//", "floor_div"),
+                                ("/", "div"),
+                                ("%", "modulo")],
+                              Self::parse_unary)
+    }
+
+    fn is_negative_literal(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.pos + 1)) {
+            (Some(sign), Some(literal)) => {
+                sign.kind == TokenKind::Op("-")
+                && sign.is_glued
+                && matches!(literal.kind, TokenKind::Int(_) | TokenKind::Float(_))
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<TokenStream> {
+        if self.is_negative_literal() {
+            // parsed as a literal, as in the lexer of the runtime parser
+            return self.parse_power();
+        }
+        match self.eat_op(&["-", "~", "+"]) {
+            Some((op, span)) => {
+                let x = self.parse_unary()?;
+                match op {
+                    "-" => {
+                        let function = builder("neg", span);
+                        Ok(quote! {#function(&(#x))})
+                    }
+                    "~" => {
+                        let function = builder("bitwise_not", span);
+                        Ok(quote! {#function(&(#x))})
+                    }
+                    _ => Ok(x),
+                }
+            }
+            None => self.parse_power(),
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<TokenStream> {
+        let base = self.parse_postfix()?;
+        match self.eat_op(&["**"]) {
+            Some((_, span)) => {
+                let exponent = self.parse_unary()?;
+                let function = builder("pow", span);
+                Ok(quote! {#function(&(#base), &(#exponent))})
+            }
+            None => Ok(base),
+        }
+    }
+
+    /// Parses the comma separated expressions up to *close*.
+    fn parse_list(&mut self, close: &'static str) -> Result<Vec<TokenStream>> {
+        let mut exprs = vec![];
+        while !self.peek_op(close) {
+            exprs.push(self.parse_if()?);
+            if self.eat_op(&[","]).is_none() {
+                break;
+            }
+        }
+        self.expect_op(close)?;
+        Ok(exprs)
+    }
+
+    fn parse_postfix(&mut self) -> Result<TokenStream> {
+        let mut result = self.parse_atom()?;
+        while let Some((open, span)) = self.eat_op(&["(", "["]) {
+            let (close, name) = if open == "(" {
+                (")", "call")
+            } else {
+                ("]", "index")
+            };
+            let exprs = self.parse_list(close)?;
+            let function = builder(name, span);
+            result = quote! {
+                #function(#result,
+                          ::std::vec![#(#exprs),*]
+                              as ::std::vec::Vec<::std::rc::Rc<::symoxide::Expression>>)
+            };
+        }
+        Ok(result)
+    }
+
+    fn parse_atom(&mut self) -> Result<TokenStream> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.error("expected an expression")),
+        };
+        let span = token.span;
+        match token.kind {
+            TokenKind::Ident(name) if !KEYWORDS.contains(&name.as_str()) => {
+                self.pos += 1;
+                Ok(quote_spanned! {span=> ::symoxide::var(#name)})
+            }
+            TokenKind::Int(_) | TokenKind::Float(_) => {
+                self.pos += 1;
+                Ok(scalar(&token.kind, false, span))
+            }
+            TokenKind::Op("-") if self.is_negative_literal() => {
+                self.pos += 2;
+                Ok(scalar(&self.tokens[self.pos - 1].kind, true, span))
+            }
+            TokenKind::Op("(") => {
+                self.pos += 1;
+                let result = self.parse_if()?;
+                self.expect_op(")")?;
+                Ok(result)
+            }
+            TokenKind::Interpolation(ident) => {
+                self.pos += 1;
+                Ok(quote_spanned! {ident.span()=>
+                    ::symoxide::operations::ConvertibleToExpr::to_expr(&#ident)
+                })
+            }
+            _ => Err(self.error("expected an expression")),
+        }
+    }
+}
+
+fn scalar(kind: &TokenKind, negate: bool, span: Span) -> TokenStream {
+    let sign = if negate { -1 } else { 1 };
+    let value = match *kind {
+        TokenKind::Int(x) => {
+            let x = Literal::i32_suffixed(sign * x);
+            quote_spanned! {span=> ::symoxide::LiteralT::I32(#x)}
+        }
+        TokenKind::Float(x) => {
+            let x = Literal::f64_suffixed(sign as f64 * x);
+            quote_spanned! {span=> ::symoxide::LiteralT::F64(#x)}
+        }
+        _ => unreachable!(),
+    };
+    quote_spanned! {span=> ::std::rc::Rc::new(::symoxide::Expression::Scalar(#value))}
+}
+
+// }}}
+
+/// Returns the expansion of `expr!(input)`.
+pub(crate) fn expand(input: TokenStream) -> Result<TokenStream> {
+    let mut trees = input.clone().into_iter();
+    let tokens = match (trees.next(), trees.next()) {
+        (Some(TokenTree::Literal(literal)), None) if literal.to_string().starts_with('"') => {
+            match Lit::new(literal) {
+                Lit::Str(x) => lex_str(&x.value(), x.span())?,
+                x => return Err(Error::new(x.span(), "expected a string literal")),
+            }
+        }
+        _ => {
+            let mut tokens = vec![];
+            lex_token_stream(input, &mut tokens)?;
+            tokens
+        }
+    };
+    let end_span = tokens.last()
+                         .map_or_else(Span::call_site, |token| token.span);
+    if tokens.is_empty() {
+        return Err(Error::new(end_span, "expected an expression"));
+    }
+    Parser { tokens,
+             pos: 0,
+             end_span }.parse()
+}
+
+// vim: fdm=marker
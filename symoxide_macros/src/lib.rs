@@ -0,0 +1,139 @@
+use lazy_static::lazy_static;
+use proc_macro;
+use proc_macro::TokenStream;
+use quote::quote;
+use regex::Regex;
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{parse, parse_macro_input, parse_str, Expr, LitFloat, LitInt, LitStr, Result};
+
+mod expr;
+mod mapper;
+
+lazy_static! {
+    static ref RE_SENTENCE: Regex =
+        Regex::new(r"^(([a-zA-Z_][_0-9a-zA-Z]*)\s+)*([a-zA-Z_][_0-9a-zA-Z]*)\s*$").unwrap();
+    static ref RE_WORD: Regex = Regex::new(r"(?P<id>[a-zA-Z_][_0-9a-zA-Z]*)").unwrap();
+}
+
+fn get_words(s: &str) -> Vec<&str> {
+    if !RE_SENTENCE.is_match(s) {
+        panic!("Provided sentence does not correspond to a collection of words");
+    }
+
+    RE_WORD.captures_iter(s)
+           .map(|capture| capture.name("id").unwrap().as_str())
+           .collect()
+}
+
+fn parse_variables_string_stream(s: String) -> TokenStream {
+    let words = get_words(&s[..]);
+    let mut word_list: Punctuated<Expr, Comma> = Punctuated::new();
+    for word in words {
+        let str_to_parse = format!("symoxide::var(\"{}\")", word);
+        // TODO: Avoid this call to `parse_str`
+        word_list.push(parse_str(&str_to_parse[..]).unwrap());
+    }
+    let gen = quote! {(#word_list,)};
+    gen.into()
+}
+
+/// Converts a space-delimited string to a tuple of `symoxide::var` calls on
+/// individual words.
+///
+/// # Examples
+///
+/// ```rust
+/// use symoxide as s;
+/// let (foo, bar) = s::variables!("foo bar");
+/// assert_eq!(foo, s::var("foo"));
+/// assert_eq!(bar, s::var("bar"));
+/// ```
+#[proc_macro]
+pub fn variables(token_stream: TokenStream) -> TokenStream {
+    let item: Result<LitStr> = parse(token_stream);
+    match item {
+        Ok(x) => parse_variables_string_stream(x.value()),
+        Err(_) => {
+            panic!("split! expects a string literal.")
+        }
+    }
+}
+
+/// Converts an [`i32`] or [`f64`] literal into an instance of
+/// `symoxide::Expression::Scalar`.
+#[proc_macro]
+pub fn scalar(token_stream: TokenStream) -> TokenStream {
+    let item: Result<LitInt> = parse(token_stream.clone());
+    match item {
+        Ok(x) => {
+            let gen = quote! { std::rc::Rc::new(symoxide::Expression::Scalar(symoxide::LiteralT::I32(#x))) };
+            gen.into()
+        }
+        Err(_) => {
+            let item: Result<LitFloat> = parse(token_stream);
+            match item {
+                Ok(x) => {
+                    let gen = quote! { std::rc::Rc::new(symoxide::Expression::Scalar(symoxide::LiteralT::F64(#x))) };
+                    gen.into()
+                }
+                Err(_) => panic!("split! expects a int/float literal."),
+            }
+        }
+    }
+}
+
+/// Builds a `Rc<symoxide::Expression>` from an expression written in the
+/// grammar of `symoxide::parse`. The expression is parsed at compile time and
+/// expands to calls to the builders in `symoxide::operations`; syntax errors
+/// are reported as compile errors.
+///
+/// `#name` interpolates a Rust value `name` that implements
+/// `symoxide::operations::ConvertibleToExpr`, e.g. a
+/// `Rc<symoxide::Expression>`.
+///
+/// As in `symoxide::parse`, a `-` directly followed by a number is part of the
+/// literal, i.e. `expr!(-2**2)` is `(-2)**2` while `expr!(- 2**2)` is
+/// `-(2**2)`.
+///
+/// Since `//` starts a comment in Rust, floor divisions are only available when
+/// the expression is passed as a string literal, as in `expr!("i // 2")`.
+#[proc_macro]
+pub fn expr(token_stream: TokenStream) -> TokenStream {
+    match expr::expand(token_stream.into()) {
+        Ok(x) => x.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Implements the trait `symoxide::mappers::CachedMapper` for a struct.
+/// Requires that struct contains a field of type [`std::collections::HashMap`]
+/// (or a map with the same `get` and `insert` methods), whose key and value
+/// types are the key and value types of the cache. The field is named `cache`
+/// unless set via a `#[mapper(cache = <field>)]` attribute.
+#[proc_macro_derive(CachedMapper, attributes(mapper))]
+pub fn derive_cached_mapper(input: TokenStream) -> TokenStream {
+    match mapper::derive_cached_mapper(parse_macro_input!(input)) {
+        Ok(x) => x.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Completes an `impl` of `symoxide::mappers::fold::FoldMapper` or
+/// `symoxide::mappers::fold::UncachedFoldMapper` whose output is
+/// `Rc<symoxide::Expression>`: the `map_*` methods that the impl does not
+/// define rebuild the visited node from its recursively mapped children, and
+/// `type Output` defaults to `Rc<symoxide::Expression>`.
+///
+/// The kind of mapper is inferred from the name of the implemented trait; if
+/// the trait is imported under another name pass it as
+/// `#[identity_fold_mapper(cached)]` or `#[identity_fold_mapper(uncached)]`.
+#[proc_macro_attribute]
+pub fn identity_fold_mapper(args: TokenStream, input: TokenStream) -> TokenStream {
+    match mapper::expand_identity_fold_mapper(args.into(), parse_macro_input!(input)) {
+        Ok(x) => x.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+// vim: fdm=marker
@@ -0,0 +1,182 @@
+// Copyright (c) 2022 Kaushik Kulkarni
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Implementations of the macros generating mapper boilerplate:
+//! `#[derive(CachedMapper)]` and `#[identity_fold_mapper]`.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::ParseStream;
+use syn::spanned::Spanned;
+use syn::{
+    parse_quote, Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, ImplItem,
+    ItemImpl, PathArguments, Result, Token, Type,
+};
+
+// {{{ derive(CachedMapper)
+
+/// Returns the name of the cache field as set by a `#[mapper(cache = name)]`
+/// attribute in *attrs*, defaults to `cache`.
+fn get_cache_field_name(attrs: &[Attribute]) -> Result<Ident> {
+    let mut name = Ident::new("cache", Span::call_site());
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("mapper")) {
+        let (key, value) = attr.parse_args_with(|input: ParseStream| {
+                                   let key: Ident = input.parse()?;
+                                   input.parse::<Token![=]>()?;
+                                   let value: Ident = input.parse()?;
+                                   Ok((key, value))
+                               })?;
+        if key != "cache" {
+            return Err(Error::new(key.span(), "unknown mapper option, expected 'cache'"));
+        }
+        name = value;
+    }
+    Ok(name)
+}
+
+/// Returns the key and value types of the map type *ty*, i.e. `K` and `V` for
+/// `HashMap<K, V>`.
+fn get_key_value_types(ty: &Type) -> Result<(Type, Type)> {
+    let error = || Error::new(ty.span(), "expected the cache to be a HashMap<K, V>");
+    let segment = match ty {
+        Type::Path(ty_path) => ty_path.path.segments.last().ok_or_else(error)?,
+        _ => return Err(error()),
+    };
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => &args.args,
+        _ => return Err(error()),
+    };
+    let types: Vec<&Type> = args.iter()
+                                .filter_map(|arg| match arg {
+                                    GenericArgument::Type(ty) => Some(ty),
+                                    _ => None,
+                                })
+                                .collect();
+    match types[..] {
+        [key, value] => Ok((key.clone(), value.clone())),
+        _ => Err(error()),
+    }
+}
+
+pub(crate) fn derive_cached_mapper(input: DeriveInput) -> Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(x) => match &x.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(input.ident.span(),
+                                      "derive CachedMapper expects a struct with named fields"))
+            }
+        },
+        _ => return Err(Error::new(input.ident.span(), "derive CachedMapper expects a struct")),
+    };
+    let cache = get_cache_field_name(&input.attrs)?;
+    let field = fields.iter()
+                      .find(|field| field.ident.as_ref() == Some(&cache))
+                      .ok_or_else(|| {
+                          Error::new(cache.span(),
+                                     format!("derive CachedMapper requires a '{}' HashMap field",
+                                             cache))
+                      })?;
+    let (kt, vt) = get_key_value_types(&field.ty)?;
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics CachedMapper<#kt, #vt> for #ident #ty_generics #where_clause {
+            fn query_cache(&self, key: &#kt) -> Option<&#vt> {
+                self.#cache.get(key)
+            }
+            fn add_to_cache(&mut self, key: #kt, val: #vt) {
+                self.#cache.insert(key, val);
+            }
+        }
+    })
+}
+
+// }}}
+
+// {{{ identity_fold_mapper
+
+const MAP_METHODS: [&str; 7] = ["map_scalar",
+                                "map_variable",
+                                "map_unary_op",
+                                "map_binary_op",
+                                "map_call",
+                                "map_subscript",
+                                "map_if"];
+
+pub(crate) fn expand_identity_fold_mapper(args: TokenStream, mut item: ItemImpl)
+                                          -> Result<TokenStream> {
+    let trait_path = match &item.trait_ {
+        Some((_, path, _)) => path,
+        None => {
+            return Err(Error::new(item.self_ty.span(),
+                                  "#[identity_fold_mapper] expects an impl of a fold mapper \
+                                   trait"))
+        }
+    };
+    let flavor: Ident = if args.is_empty() {
+        match trait_path.segments.last() {
+            Some(x) if x.ident == "FoldMapper" => Ident::new("cached", Span::call_site()),
+            Some(x) if x.ident == "UncachedFoldMapper" => Ident::new("uncached", Span::call_site()),
+            _ => {
+                return Err(Error::new(trait_path.span(),
+                                      "cannot infer the kind of fold mapper, pass 'cached' or \
+                                       'uncached'"))
+            }
+        }
+    } else {
+        let flavor: Ident = syn::parse2(args)?;
+        if flavor != "cached" && flavor != "uncached" {
+            return Err(Error::new(flavor.span(), "expected 'cached' or 'uncached'"));
+        }
+        flavor
+    };
+
+    let has_output = item.items.iter().any(|x| {
+                                          matches!(x, ImplItem::Type(ty)
+                                                           if ty.ident == "Output")
+                                      });
+    let missing: Vec<Ident> = MAP_METHODS.iter()
+                                         .filter(|name| {
+                                             !item.items.iter().any(|x| {
+                                             matches!(x, ImplItem::Method(method)
+                                                              if method.sig.ident == name)
+                                         })
+                                         })
+                                         .map(|name| Ident::new(name, Span::call_site()))
+                                         .collect();
+
+    if !has_output {
+        item.items.push(parse_quote! {
+                            type Output = ::std::rc::Rc<::symoxide::Expression>;
+                        });
+    }
+    if !missing.is_empty() {
+        item.items.push(parse_quote! {
+                            ::symoxide::define_identity_fold_methods!(#flavor; #(#missing),*);
+                        });
+    }
+    Ok(quote! {#item})
+}
+
+// }}}
+
+// vim: fdm=marker
@@ -85,7 +85,7 @@ fn test_get_hasher() {
                                     [ops::left_shift(&bar, &two),
                                      ops::add(&baz, &two),
                                      ops::greater(&two_dup, &quux)]));
-    let hasher = sym::get_hasher(expr.clone());
+    let mut hasher = sym::get_hasher(expr.clone());
 
     // {{{ necessary test that 2 different object instances hash to the same value
 
@@ -130,3 +130,1271 @@ fn test_stringify_parse_are_inverses() {
     // assert_parse_roundtrip("f((x,),z)");
     // assert_parse_roundtrip("f(x,(y,z),z)");
 }
+
+#[test]
+fn test_evaluate() {
+    use std::collections::HashMap;
+    use sym::LiteralT;
+
+    let env = HashMap::from([("i".to_string(), LiteralT::I32(-7)),
+                             ("x".to_string(), LiteralT::F64(0.5))]);
+    assert_eq!(sym::evaluate(&parse("i // 2"), &env), LiteralT::I64(-4));
+    assert_eq!(sym::evaluate(&parse("i % 3"), &env), LiteralT::I64(2));
+    assert_eq!(sym::evaluate(&parse("i / 2"), &env), LiteralT::F64(-3.5));
+    assert_eq!(sym::evaluate(&parse("2*x + i"), &env), LiteralT::F64(-6.0));
+    assert_eq!(sym::evaluate(&parse("1 if i < 0 else 1 // 0"), &env),
+               LiteralT::I64(1));
+    // literals and variables are promoted like the results of operations
+    assert_eq!(sym::evaluate(&parse("1"), &env),
+               sym::evaluate(&parse("1 + 0"), &env));
+    assert_eq!(sym::evaluate(&parse("i"), &env), LiteralT::I64(-7));
+    assert_eq!(sym::evaluate(&parse("i > 0 and 1 // 0"), &env),
+               LiteralT::I64(0));
+    assert_eq!(sym::evaluate(&parse("sqrt(4*x + 2)"), &env),
+               LiteralT::F64(2.0));
+}
+
+#[test]
+fn test_compile() {
+    use std::collections::HashMap;
+    use sym::LiteralT;
+
+    let expr = parse("(x*y + sin(x*y)) / (1 + x*y) if i % 2 == 0 else (i > 0 or 1 // 0) - x*y");
+    let program = sym::compile(&expr);
+    assert_eq!(program.input_names(), ["i", "x", "y"]);
+
+    let xs: Vec<LiteralT> = (0..8).map(|k| LiteralT::F64(0.25 * k as f64)).collect();
+    let ys: Vec<LiteralT> = (0..8).map(|k| LiteralT::F64(1.5 - 0.5 * k as f64))
+                                  .collect();
+    let is: Vec<LiteralT> = (0..8).map(LiteralT::I32).collect();
+    let mut out = vec![LiteralT::I64(0); 8];
+    let mut registers = program.new_registers();
+    program.eval_batch(&[&is, &xs, &ys], &mut out, &mut registers);
+
+    for k in 0..8 {
+        let env = HashMap::from([("i".to_string(), is[k]),
+                                 ("x".to_string(), xs[k]),
+                                 ("y".to_string(), ys[k])]);
+        let expected = sym::evaluate(&expr, &env);
+        assert_eq!(program.eval(&[is[k], xs[k], ys[k]], &mut registers),
+                   expected);
+        assert_eq!(out[k], expected);
+    }
+
+    // programs hold no mutable state and can be shared across threads
+    fn assert_sync<T: Sync>(_: &T) {}
+    assert_sync(&program);
+    let program = sym::compile(&parse("1"));
+    assert_eq!(program.eval(&[], &mut program.new_registers()),
+               LiteralT::I64(1));
+
+    // calls to pure functions with the same arguments are computed once
+    let count_calls = |program: &sym::Program| {
+        program.instructions()
+               .iter()
+               .filter(|insn| matches!(insn, sym::mapper_impls::compiler::Instruction::Call { .. }))
+               .count()
+    };
+    let expr = parse("sin(x) * sin(x) + (sin(x) if y > 0 else sin(y))");
+    assert_eq!(count_calls(&sym::compile(&expr)), 2);
+    let mut registry = sym::functions::FunctionRegistry::default();
+    let mut rand = sym::functions::FunctionInfo::new("rand", 1, |_| LiteralT::F64(0.5));
+    rand.is_pure = false;
+    registry.register(rand);
+    let program = sym::mapper_impls::compiler::compile_with_registry(&parse("rand(x) + rand(x)"),
+                                                                     &["x"],
+                                                                     &registry);
+    assert_eq!(count_calls(&program), 2);
+}
+
+#[test]
+fn test_infer_dtype() {
+    use std::collections::HashMap;
+    use sym::DType;
+
+    let dtypes = HashMap::from([("i".to_string(), DType::I32),
+                                ("n".to_string(), DType::U64),
+                                ("x".to_string(), DType::F32),
+                                ("a".to_string(), DType::F32)]);
+    assert_eq!(sym::infer_dtype(&parse("i * n"), &dtypes), DType::I64);
+    assert_eq!(sym::infer_dtype(&parse("a[i] / x"), &dtypes), DType::F32);
+    assert_eq!(sym::infer_dtype(&parse("i / 2"), &dtypes), DType::F64);
+    assert_eq!(sym::infer_dtype(&parse("i ** 2"), &dtypes), DType::I32);
+    assert_eq!(sym::infer_dtype(&parse("i ** -2"), &dtypes), DType::F64);
+    assert_eq!(sym::infer_dtype(&parse("x ** 2"), &dtypes), DType::F32);
+    assert_eq!(sym::infer_dtype(&parse("n << 2 if x > 0 else n"), &dtypes),
+               DType::U64);
+    assert_eq!(sym::infer_dtype(&parse("sqrt(i) + x"), &dtypes), DType::F64);
+}
+
+#[test]
+fn test_evaluate_with_gradient() {
+    use std::collections::HashMap;
+    use sym::LiteralT;
+
+    let (x, y) = (0.7, 1.3);
+    let env = HashMap::from([("x".to_string(), LiteralT::F64(x)),
+                             ("y".to_string(), LiteralT::F64(y)),
+                             ("n".to_string(), LiteralT::I32(3))]);
+    let expr = parse("sin(x)*(y**n) + exp(x/y) if x > 0 else 0");
+    let (value, grad) = sym::evaluate_with_gradient(&expr, &env, &["x", "y"]);
+
+    assert_eq!(value, sym::evaluate(&expr, &env));
+    let expected = [x.cos() * y.powi(3) + (x / y).exp() / y,
+                    3.0 * x.sin() * y.powi(2) - (x / y).exp() * x / (y * y)];
+    for (computed, expected) in grad.iter().zip(expected.iter()) {
+        assert!((computed - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_get_gradient() {
+    use std::collections::HashMap;
+    use sym::LiteralT;
+
+    let env = HashMap::from([("x".to_string(), LiteralT::F64(0.7)),
+                             ("y".to_string(), LiteralT::F64(1.3)),
+                             ("z".to_string(), LiteralT::F64(-2.1))]);
+    let expr = parse(concat!("(sin(x*y) + x*y) / (z % y) - exp(-(x*y)) * (z**2) ",
+                             "+ (atan2(z, x) if z > 0 else sqrt(x)**y)"));
+    let wrt = ["x", "y", "z"];
+    let grad_exprs = sym::get_gradient(&expr, &wrt);
+    let (_, expected) = sym::evaluate_with_gradient(&expr, &env, &wrt);
+
+    for (grad_expr, expected) in grad_exprs.iter().zip(expected.iter()) {
+        let computed = sym::evaluate(grad_expr, &env).as_f64();
+        assert!((computed - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_function_registry() {
+    use sym::functions::{CallError, FunctionRegistry, PrintTarget};
+    use sym::DType;
+
+    let registry = FunctionRegistry::default();
+    assert!(registry.check_calls(&parse("sin(x) + atan2(y, sqrt(x))"))
+                    .is_ok());
+    assert_eq!(registry.check_calls(&parse("hankel_1(0, sin(x, y)) + f[0](x)")),
+               Err(vec![CallError::UnknownFunction("hankel_1".to_string()),
+                        CallError::WrongArity { name: "sin".to_string(),
+                                                expected: 1,
+                                                got: 2 },
+                        CallError::UnnamedCallee("f[0]".to_string())]));
+
+    let abs = registry.get("abs").unwrap();
+    assert!(abs.is_pure);
+    assert_eq!((abs.result_dtype)(&[DType::I32]), DType::I64);
+    assert_eq!(registry.get("sqrt")
+                       .unwrap()
+                       .printed_name(PrintTarget::NumPy),
+               "np.sqrt");
+}
+
+#[test]
+fn test_operation_builders() {
+    let (a, b) = sym::variables!("a b");
+    assert_eq!(parse("a != b"), ops::not_equal(&a, &b));
+    assert_eq!(parse("(a | b) ^ (a & b)"),
+               ops::bitwise_xor(&ops::bitwise_or(&a, &b), &ops::bitwise_and(&a, &b)));
+    assert_eq!(parse("not a and (b or ~a)"),
+               ops::logical_and(&ops::logical_not(&a),
+                                &ops::logical_or(&b, &ops::bitwise_not(&a))));
+    assert_eq!(parse("-a ** 2 - b"),
+               ops::sub(&ops::neg(&ops::pow(&a, &2)), &b));
+}
+
+#[test]
+fn test_to_dot() {
+    use sym::{DotOptions, RankDir};
+
+    let (a, b) = sym::variables!("a b");
+    let shared = ops::add(&a, &b);
+    let expr = ops::mul(&shared, &ops::sub(&shared, &2));
+
+    let dot_code = sym::to_dot(&expr, &DotOptions::default());
+    assert!(dot_code.contains("rankdir=TB;"));
+    assert!(!dot_code.contains("subgraph"));
+    assert_eq!(dot_code.matches("label=\"+\"").count(), 1);
+
+    let options = DotOptions { rankdir: RankDir::LeftToRight,
+                               cluster_shared: true,
+                               color_by_kind: true,
+                               show_literal_types: true,
+                               highlight: vec![shared.clone()],
+                               max_depth: None };
+    let dot_code = sym::to_dot(&expr, &options);
+    assert!(dot_code.contains("rankdir=LR;"));
+    assert!(dot_code.contains("subgraph cluster_0"));
+    assert!(!dot_code.contains("subgraph cluster_1"));
+    assert!(dot_code.contains("label=\"2 (I32)\""));
+    assert!(dot_code.contains("fillcolor=lightblue"));
+    assert_eq!(dot_code.matches("penwidth=3").count(), 1);
+
+    let options = DotOptions { max_depth: Some(1),
+                               ..DotOptions::default() };
+    let dot_code = sym::to_dot(&expr, &options);
+    assert_eq!(dot_code.matches("label=\"...\"").count(), 3);
+    assert!(!dot_code.contains("label=\"a\""));
+
+    let path =
+        std::env::temp_dir().join(format!("symoxide_test_to_dot_{}.dot", std::process::id()));
+    sym::write_dot(&expr, &options, &path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), dot_code);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_to_latex() {
+    use sym::{LatexOptions, MulSymbol};
+
+    let options = LatexOptions::default();
+    assert_eq!(sym::to_latex(&parse("-(a + b)*(c**(n - 1))"), &options),
+               "-\\left(a + b\\right) {c}^{n - 1}");
+    assert_eq!(sym::to_latex(&parse("sqrt(x[i, j+1]) + hankel(0, x)"), &options),
+               "\\sqrt{{x}_{i, j + 1}} + \\operatorname{hankel}\\left(0, x\\right)");
+    assert_eq!(sym::to_latex(&parse("(a // b) ** 2 + 2*3"), &options),
+               "{\\left(\\left\\lfloor \\frac{a}{b} \\right\\rfloor\\right)}^{2} + 2 \\cdot 3");
+    assert_eq!(sym::to_latex(&parse("x if x >= 0 else -x"), &options),
+               "\\begin{cases} x & \\text{if } x \\geq 0 \\\\ -x & \\text{otherwise} \
+                \\end{cases}");
+    assert_eq!(sym::to_latex(&parse("a*(-b) + a*(-2)"), &options),
+               "a \\left(-b\\right) + a \\left(-2\\right)");
+    assert_eq!(sym::to_latex(&parse("x_12 + alpha_12 * Omega_1 + y_i_j"), &options),
+               "x_{12} + \\alpha_{12} \\Omega_1 + y_{i\\_j}");
+
+    let options = LatexOptions { mul_symbol: MulSymbol::Times,
+                                 greek_letters: false };
+    assert_eq!(sym::to_latex(&parse("alpha_1 * beta"), &options),
+               "alpha_1 \\times beta");
+}
+
+#[test]
+fn test_to_rust_fn() {
+    use sym::{DType, DivModSemantics, LiteralT, RustArgType, RustSignature};
+
+    let mut signature =
+        RustSignature::new("kernel",
+                           vec![("i".to_string(), RustArgType::Scalar(DType::I32)),
+                                ("x".to_string(), RustArgType::Scalar(DType::F64)),
+                                ("a".to_string(),
+                                 RustArgType::Slice(DType::F32, vec![sym::var("n"), parse("1")])),
+                                ("n".to_string(), RustArgType::Scalar(DType::I32))]);
+    assert_eq!(sym::to_rust_fn(&parse("a[i, 2] * (x**2) if i > 0 else exp(x) % 2"),
+                               &signature),
+               "pub fn kernel(i: i32, x: f64, a: &[f32], n: i32) -> f64 {\n    if i > 0_i32 { \
+                a[(i * n + 2_i32) as usize] as f64 * x.powi(2) } else { { let (a, b) = \
+                (x.exp(), 2.0_f64); let r = a % b; if r != 0.0 && ((r < 0.0) != (b < 0.0)) { \
+                r + b } else { r } } }\n}\n");
+
+    signature.div_mod_semantics = DivModSemantics::Rust;
+    signature.return_dtype = Some(DType::I64);
+    assert_eq!(sym::to_rust_fn(&parse("i // 2 + i % n"), &signature),
+               "pub fn kernel(i: i32, x: f64, a: &[f32], n: i32) -> i64 {\n    \
+                (i / 2_i32 + i % n) as i64\n}\n");
+
+    let unsigned_signature =
+        RustSignature::new("unsigned_kernel",
+                           vec![("u".to_string(), RustArgType::Scalar(DType::U32)),
+                                ("v".to_string(), RustArgType::Scalar(DType::U32))]);
+    let unsigned_code = sym::to_rust_fn(&parse("-u * (u - v)"), &unsigned_signature);
+    assert_eq!(unsigned_code,
+               "pub fn unsigned_kernel(u: u32, v: u32) -> u32 {\n    \
+                u.wrapping_neg() * u.wrapping_sub(v)\n}\n");
+
+    // negative integral powers are floats, literals keep their own type
+    let big = std::rc::Rc::new(sym::Expression::Scalar(LiteralT::U64(u64::MAX)));
+    let literals_signature =
+        RustSignature::new("literals_kernel",
+                           vec![("i".to_string(), RustArgType::Scalar(DType::I32)),
+                                ("w".to_string(), RustArgType::Scalar(DType::U64))]);
+    let expr = ops::mul(&parse("i ** -2 + i ** 2"),
+                        &ops::add(&ops::add(&sym::var("w"), &big),
+                                  &ops::sub(&sym::var("i"), &big)));
+    let literals_code = sym::to_rust_fn(&expr, &literals_signature);
+    assert_eq!(literals_code,
+               "pub fn literals_kernel(i: i32, w: u64) -> f64 {\n    ((i as f64).powi(-2) \
+                + i.pow(2) as f64) * ((w + 18446744073709551615_u64) as i64 + (i as i64 \
+                - 18446744073709551615_u64 as i64)) as f64\n}\n");
+
+    // the emitted functions must be accepted by rustc
+    let dir = std::env::temp_dir().join(format!("symoxide_test_to_rust_fn_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("kernels.rs");
+    std::fs::write(&source,
+                   [sym::to_rust_fn(&parse("a[i, 2] * (x**2) if i > 0 else exp(x) % 2"),
+                                    &signature),
+                    unsigned_code,
+                    literals_code].concat()).unwrap();
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = std::process::Command::new(rustc).args(["--crate-type",
+                                                         "lib",
+                                                         "--edition",
+                                                         "2021",
+                                                         "--out-dir"])
+                                                  .arg(&dir)
+                                                  .arg(&source)
+                                                  .output()
+                                                  .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+#[should_panic(expected = "BitwiseNot is not defined for the floating point operand 'x'.")]
+fn test_to_rust_fn_float_bitwise_not() {
+    use sym::{DType, RustArgType, RustSignature};
+
+    let signature = RustSignature::new("f",
+                                       vec![("x".to_string(), RustArgType::Scalar(DType::F64))]);
+    sym::to_rust_fn(&parse("~x"), &signature);
+}
+
+#[test]
+fn test_to_python() {
+    use sym::PythonDialect;
+
+    let expr = parse("(x < y) < 3 and not z[i, j] if x > 0 else -x ** 2 + (-x) ** 2.0");
+    assert_eq!(sym::to_python(&expr, PythonDialect::NumPy),
+               "np.where(x > 0, np.logical_and((x < y) < 3, np.logical_not(z[i, j])), -x ** 2 \
+                + (-x) ** 2.0)");
+    assert_eq!(sym::to_python(&expr, PythonDialect::StrictPython),
+               "int(bool(_sym_lt(_sym_lt(x, y), 3) and int(not z[i, j]))) if _sym_gt(x, 0) else \
+                _sym_add(_sym_neg(_sym_pow(x, 2)), _sym_pow(_sym_neg(x), 2.0))");
+    assert_eq!(sym::to_python(&parse("exp(x) // 2 + hankel(0, x) % 3"),
+                              PythonDialect::NumPy),
+               "np.floor_divide(np.exp(x), 2) + hankel(0, x) % 3");
+}
+
+#[test]
+fn test_to_python_strict_semantics() {
+    use std::collections::HashMap;
+    use std::io::Write;
+    use sym::{LiteralT, PythonDialect};
+
+    // edge cases where Python's operators and math functions differ from evaluate
+    let exprs = ["i * j + 1",
+                 "-(i * j * 2)",
+                 "i ** 3",
+                 "j ** -2",
+                 "i << 70",
+                 "i >> -1",
+                 "(i + 7) // -2",
+                 "(i + 7) % -2",
+                 "x / (y - y)",
+                 "(x - x) / (y - y)",
+                 "-x / (y - y)",
+                 "1 // y",
+                 "x % (y - y)",
+                 "x // (-y)",
+                 "(-x) ** 0.5",
+                 "(y - y) ** -1",
+                 "(-10.0) ** 309",
+                 "log(y - y)",
+                 "log(-x)",
+                 "sqrt(-x)",
+                 "exp(1000 * x)",
+                 "sinh(-1000 * x)",
+                 "floor(x / (y - y))",
+                 "floor(x) + ceil(-x)",
+                 "abs(i * j * 2) + abs(-x)",
+                 "(i > x) + (i == j) + (x != x) + (i <= i * 2.0)",
+                 "atan2(x, y) if x > 1 and not i < 0 else tan(x)",
+                 "y / 3.0 + 0.5 * i"];
+    let (i, j, x, y) = (1i64 << 62, 4i64, 2.5f64, 0.1f64);
+    let env = HashMap::from([("i".to_string(), LiteralT::I64(i)),
+                             ("j".to_string(), LiteralT::I64(j)),
+                             ("x".to_string(), LiteralT::F64(x)),
+                             ("y".to_string(), LiteralT::F64(y))]);
+
+    let mut script = format!("{}\ni, j, x, y = {}, {}, {:?}, {:?}\n",
+                             sym::STRICT_PYTHON_PRELUDE,
+                             i,
+                             j,
+                             x,
+                             y);
+    for expr in exprs {
+        script.push_str(&format!("print(repr({}))\n",
+                                 sym::to_python(&parse(expr), PythonDialect::StrictPython)));
+    }
+    let python = std::env::var("PYTHON").unwrap_or_else(|_| "python3".to_string());
+    let mut child = match std::process::Command::new(python).stdin(std::process::Stdio::piped())
+                                                            .stdout(std::process::Stdio::piped())
+                                                            .stderr(std::process::Stdio::piped())
+                                                            .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("Python not found, skipping the comparison with evaluate.");
+            return;
+        }
+        Err(e) => panic!("{}", e),
+    };
+    child.stdin
+         .take()
+         .unwrap()
+         .write_all(script.as_bytes())
+         .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for (expr, line) in exprs.iter().zip(stdout.lines()) {
+        let expected = sym::evaluate(&parse(expr), &env);
+        let value = if expected.is_integral() {
+            LiteralT::I64(line.parse().unwrap())
+        } else {
+            LiteralT::F64(line.parse().unwrap())
+        };
+        let are_equal = match (value, expected) {
+            (LiteralT::F64(a), LiteralT::F64(b)) => a == b || (a.is_nan() && b.is_nan()),
+            _ => value == expected,
+        };
+        assert!(are_equal,
+                "'{}': Python gave {}, evaluate gave {}",
+                expr, line, expected);
+    }
+    assert_eq!(stdout.lines().count(), exprs.len());
+}
+
+#[test]
+fn test_pretty_print() {
+    use sym::PrettyOptions;
+
+    let expr = parse("f(alpha_1 + alpha_2 * beta, gamma[i, j + 1]) - delta ** 2");
+    assert_eq!(sym::pretty_print(&expr, &PrettyOptions::default()),
+               "f(alpha_1 + alpha_2 * beta, gamma[i, j + 1]) - delta ** 2");
+
+    let options = PrettyOptions { width: 22,
+                                  indent: 2,
+                                  let_bindings: false };
+    let pretty = sym::pretty_print(&expr, &options);
+    assert_eq!(pretty,
+               "f(\n  alpha_1\n    + alpha_2 * beta,\n  gamma[i, j + 1]\n)\n  - delta ** 2");
+    assert!(pretty.lines().all(|line| line.len() <= 22));
+
+    let x = sym::parse("sin(x) + 1");
+    let expr = ops::mul(&x, &ops::add(&x, &sym::var("_t0")));
+    let options = PrettyOptions { let_bindings: true,
+                                  ..PrettyOptions::default() };
+    assert_eq!(sym::pretty_print(&expr, &options),
+               "let _t1 = sin(x) + 1;\n_t1 * (_t1 + _t0)");
+}
+
+#[test]
+fn test_expression_stats() {
+    use sym::{BinaryOpType, DType, ExpressionStats, NodeKind};
+
+    let stats = ExpressionStats::compute(&sym::deduplicate_nodes(&parse("a + sin(a)")));
+    assert_eq!(stats.num_dag_nodes, 4);
+    assert_eq!(stats.num_tree_nodes, 5);
+    assert_eq!(stats.max_depth, 2);
+    assert!((stats.average_depth - 1.2).abs() < 1e-12);
+    assert_eq!(stats.node_kinds[&NodeKind::Variable], 2);
+    assert_eq!(stats.binary_ops[&BinaryOpType::Sum], 1);
+    assert_eq!(stats.variables, ["a".to_string()].into());
+    assert_eq!(stats.call_targets, ["sin".to_string()].into());
+    assert!(stats.shared_subexpressions.is_empty());
+
+    let y = parse("y[i] + 2.5");
+    let expr = ops::add(&ops::mul(&y, &y),
+                        &ops::ifthenelse(parse("y[i] > 0"), y.clone(), parse("0")));
+    let stats = ExpressionStats::compute(&expr);
+    assert_eq!(stats.literal_dtypes[&DType::F64], 1);
+    assert_eq!(stats.shared_subexpressions.len(), 1);
+    assert_eq!(stats.shared_subexpressions[0].fan_out, 3);
+    assert_eq!(stats.shared_subexpressions[0].num_tree_nodes, 5);
+    assert!(stats.to_string()
+                 .contains("Shared (fan-out: 3, tree nodes: 5): y[i] + 2.5"));
+}
+
+#[test]
+fn test_unified_mapper() {
+    use std::rc::Rc;
+    use sym::mappers::mapper::{CachePolicy, Mapper, MapperCache};
+    use sym::{BinaryOpType, Expression, LiteralT, SmallVecExprT, UnaryOpType};
+
+    // Computes the number of leaves while counting the nodes it maps.
+    struct LeafCounter {
+        cache: MapperCache<(), u64>,
+    }
+
+    impl Mapper for LeafCounter {
+        type Output = u64;
+        type Context = usize;
+        type ContextKey = ();
+
+        fn cache(&mut self) -> &mut MapperCache<(), u64> {
+            &mut self.cache
+        }
+        fn get_context_key(&self, _context: &usize) {}
+
+        fn map_scalar(&mut self, _value: &LiteralT, nmapped: &mut usize) -> u64 {
+            *nmapped += 1;
+            1
+        }
+        fn map_variable(&mut self, _name: String, nmapped: &mut usize) -> u64 {
+            *nmapped += 1;
+            1
+        }
+        fn map_unary_op(&mut self, _op: UnaryOpType, x: &Rc<Expression>, nmapped: &mut usize)
+                        -> u64 {
+            *nmapped += 1;
+            self.visit(x, nmapped)
+        }
+        fn map_binary_op(&mut self, left: &Rc<Expression>, _op: BinaryOpType,
+                         right: &Rc<Expression>, nmapped: &mut usize)
+                         -> u64 {
+            *nmapped += 1;
+            self.visit(left, nmapped) + self.visit(right, nmapped)
+        }
+        fn map_call(&mut self, _call: &Rc<Expression>, params: &SmallVecExprT,
+                    nmapped: &mut usize)
+                    -> u64 {
+            *nmapped += 1;
+            params.iter().map(|param| self.visit(param, nmapped)).sum()
+        }
+        fn map_subscript(&mut self, _agg: &Rc<Expression>, indices: &SmallVecExprT,
+                         nmapped: &mut usize)
+                         -> u64 {
+            *nmapped += 1;
+            indices.iter().map(|idx| self.visit(idx, nmapped)).sum()
+        }
+        fn map_if(&mut self, cond: &Rc<Expression>, then: &Rc<Expression>,
+                  else_: &Rc<Expression>, nmapped: &mut usize)
+                  -> u64 {
+            *nmapped += 1;
+            self.visit(cond, nmapped) + self.visit(then, nmapped) + self.visit(else_, nmapped)
+        }
+    }
+
+    let x_plus_y = parse("x + y");
+    let expr = ops::add(&ops::mul(&x_plus_y, &x_plus_y), &parse("x + y"));
+
+    for (policy, expected_nmapped) in [(CachePolicy::None, 11),
+                                       (CachePolicy::ByPointer, 8),
+                                       (CachePolicy::ByPointerAndContext, 8),
+                                       (CachePolicy::Structural, 5)]
+    {
+        let mut mapper = LeafCounter { cache: MapperCache::new(policy) };
+        let mut nmapped = 0;
+        assert_eq!(mapper.visit(&expr, &mut nmapped), 6);
+        assert_eq!(nmapped, expected_nmapped);
+    }
+}
+
+#[test]
+fn test_define_identity_fold_methods() {
+    use hashbrown::HashMap;
+    use std::rc::Rc;
+    use sym::mappers::fold::FoldMapper;
+    use sym::mappers::CachedMapper;
+    use sym::{define_identity_fold_methods, Expression, ExpressionRawPointer, LiteralT};
+
+    #[derive(sym::CachedMapper)]
+    struct ScalarDoubler {
+        cache: HashMap<ExpressionRawPointer, Rc<Expression>>,
+    }
+
+    impl FoldMapper for ScalarDoubler {
+        type Output = Rc<Expression>;
+
+        fn map_scalar(&mut self, value: &LiteralT) -> Rc<Expression> {
+            Rc::new(Expression::Scalar(LiteralT::I32(2 * value.as_i64() as i32)))
+        }
+
+        define_identity_fold_methods!(cached; map_variable, map_unary_op, map_binary_op, map_call,
+                                      map_subscript, map_if);
+    }
+
+    let mut mapper = ScalarDoubler { cache: HashMap::new() };
+    assert_eq!(mapper.visit(&parse("f(3, -x[1]) if y < 2 else 4")),
+               parse("f(6, -x[2]) if y < 4 else 8"));
+}
+
+#[test]
+fn test_identity_fold_mapper() {
+    use hashbrown::HashMap;
+    use std::rc::Rc;
+    use sym::mappers::fold::FoldMapper;
+    use sym::mappers::CachedMapper;
+    use sym::{identity_fold_mapper, Expression, ExpressionRawPointer};
+
+    #[derive(sym::CachedMapper)]
+    #[mapper(cache = memo)]
+    struct Renamer<'a> {
+        names: &'a std::collections::HashMap<&'static str, &'static str>,
+        memo: HashMap<ExpressionRawPointer, Rc<Expression>>,
+        nvisited: usize,
+    }
+
+    #[identity_fold_mapper]
+    impl FoldMapper for Renamer<'_> {
+        fn map_variable(&mut self, name: String) -> Rc<Expression> {
+            self.nvisited += 1;
+            sym::var(self.names.get(name.as_str()).copied().unwrap_or(&name))
+        }
+    }
+
+    let names = std::collections::HashMap::from([("x", "u"), ("y", "v")]);
+    let mut mapper = Renamer { names: &names,
+                               memo: HashMap::new(),
+                               nvisited: 0 };
+    let x = sym::var("x");
+    let expr = parse("f(2, a[x] if y < 2 else -z)");
+    assert_eq!(mapper.visit(&ops::add(&expr, &ops::mul(&x, &x))),
+               parse("f(2, a[u] if v < 2 else -z) + u*u"));
+    // the shared x is mapped once
+    assert_eq!(mapper.nvisited, 6);
+    assert_eq!(mapper.memo.len(), 15);
+}
+
+#[test]
+fn test_fingerprint() {
+    use sym::{fingerprint, Fingerprinter, LiteralT};
+
+    // Pinned digests: a change here breaks persisted fingerprints and calls for
+    // a bump of FINGERPRINT_VERSION.
+    assert_eq!(fingerprint(&parse("x + 1")).to_hex(),
+               "6a3f1b49e3c285fa17691932a89a74ebd5709a229cb226d400d602437b7b2a6d");
+    assert_eq!(fingerprint(&parse("a[i] if b else -c")).to_hex(),
+               "720782301a22f9162e6346fc45cc12b8e405feca72f9f06f92f33ea1e9d980ff");
+
+    let one_i64 = std::rc::Rc::new(sym::Expression::Scalar(LiteralT::I64(1)));
+    assert_ne!(fingerprint(&parse("x + 1")),
+               fingerprint(&ops::add(&sym::var("x"), &one_i64)));
+    assert_ne!(fingerprint(&parse("f(x, y)")),
+               fingerprint(&parse("f(x)[y]")));
+    assert_ne!(fingerprint(&scalar!(0.0)),
+               fingerprint(&std::rc::Rc::new(sym::Expression::Scalar(LiteralT::F64(-0.0)))));
+
+    let x_plus_y = parse("x + y");
+    let dag = ops::mul(&x_plus_y, &x_plus_y);
+    let mut fingerprinter = Fingerprinter::new();
+    assert_eq!(fingerprinter.fingerprint(&dag),
+               fingerprint(&parse("(x + y) * (x + y)")));
+    assert_eq!(fingerprinter.fingerprint(&x_plus_y),
+               fingerprint(&parse("x + y")));
+}
+
+#[test]
+fn test_shared_hasher_and_deduplicator() {
+    use std::rc::Rc;
+    use sym::{Deduplicator, HashCacher};
+
+    let x_plus_y = parse("x + y");
+    let mut hasher = sym::get_hasher(x_plus_y.clone());
+    // expressions built after the hasher are hashed on demand
+    let expr = ops::mul(&x_plus_y, &parse("x + y"));
+    let (left, right) = match &*expr {
+        sym::Expression::BinaryOp(left, _, right) => (left.clone(), right.clone()),
+        _ => unreachable!(),
+    };
+    assert_eq!(hasher.get(left), hasher.get(right));
+    assert_ne!(hasher.get(expr.clone()), hasher.get(x_plus_y.clone()));
+
+    let mut deduplicator = Deduplicator::with_hasher(HashCacher::new());
+    let roots = [parse("sin(x + y) * 2"),
+                 parse("2 * (x + y)"),
+                 parse("x + y")];
+    let deduped: Vec<_> = roots.iter()
+                               .map(|root| deduplicator.deduplicate(root))
+                               .collect();
+    for (root, deduped_root) in roots.iter().zip(deduped.iter()) {
+        assert_eq!(root, deduped_root);
+    }
+    match (&*deduped[1], &*deduped[0]) {
+        (sym::Expression::BinaryOp(two, _, x_plus_y),
+         sym::Expression::BinaryOp(_, _, other_two)) => {
+            assert!(Rc::ptr_eq(two, other_two));
+            assert!(Rc::ptr_eq(x_plus_y, &deduped[2]));
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_batch_apis() {
+    use std::collections::HashSet;
+    use std::rc::Rc;
+
+    let shared = parse("a*b + c");
+    let roots = [ops::mul(&shared, &sym::var("x")),
+                 ops::add(&shared, &shared),
+                 parse("a*b + c")];
+
+    let deps = sym::get_dependencies_many(&roots);
+    assert_eq!(deps[0],
+               HashSet::from(["a", "b", "c", "x"].map(String::from)));
+    assert_eq!(deps[1], HashSet::from(["a", "b", "c"].map(String::from)));
+    assert_eq!(deps[2], deps[1]);
+
+    assert_eq!(sym::get_num_nodes_many(&roots), vec![7, 6, 5]);
+    // a doubling chain shares every sub-expression between the roots
+    let mut chain = vec![sym::var("x")];
+    for _ in 0..200 {
+        let last = chain.last().unwrap().clone();
+        chain.push(ops::add(&last, &last));
+    }
+    assert_eq!(sym::get_num_nodes_many(&chain),
+               (1..=201).collect::<Vec<u32>>());
+    // the memory is linear in the number of nodes, a set of reachable nodes
+    // per node would take gigabytes for this tree of 2^18 - 1 nodes
+    let mut level: Vec<_> = (0..1 << 17).map(|i| sym::var(format!("x{}", i))).collect();
+    let mut subtrees = vec![];
+    while level.len() > 1 {
+        level = level.chunks(2)
+                     .map(|pair| ops::add(&pair[0], &pair[1]))
+                     .collect();
+        subtrees.push(level[0].clone());
+    }
+    assert_eq!(sym::get_num_nodes_many(&subtrees),
+               (2..=18).map(|height| (1 << height) - 1)
+                       .collect::<Vec<u32>>());
+
+    let deduped = sym::deduplicate_many(&roots);
+    assert_eq!(deduped.len(), 3);
+    assert!(roots.iter()
+                 .zip(deduped.iter())
+                 .all(|(root, deduped_root)| root == deduped_root));
+    match &*deduped[1] {
+        sym::Expression::BinaryOp(left, _, right) => {
+            assert!(Rc::ptr_eq(left, right));
+            assert!(Rc::ptr_eq(left, &deduped[2]));
+        }
+        _ => unreachable!(),
+    }
+
+    let pairs = [(roots[0].clone(), roots[1].clone()),
+                 (shared.clone(), roots[2].clone()),
+                 (roots[1].clone(), ops::add(&roots[2], &shared))];
+    assert_eq!(sym::are_structurally_equal_many(&pairs),
+               vec![false, true, true]);
+}
+
+#[test]
+fn test_analysis_context() {
+    use std::rc::Rc;
+    use sym::AnalysisContext;
+
+    let unique = parse("f(x, y[i]) + 1");
+    assert!(Rc::ptr_eq(&sym::deduplicate_nodes(&unique), &unique));
+
+    let with_dups = parse("(a + b) * (a + b) - c");
+    let deduped = sym::deduplicate_nodes(&with_dups);
+    match (&*with_dups, &*deduped) {
+        (sym::Expression::BinaryOp(_, _, c), sym::Expression::BinaryOp(_, _, deduped_c)) => {
+            assert!(Rc::ptr_eq(c, deduped_c));
+        }
+        _ => unreachable!(),
+    }
+
+    let mut ctx = AnalysisContext::new();
+    let expr1 = ctx.deduplicate(&with_dups);
+    let expr2 = ctx.deduplicate(&parse("(a + b) * (a + b) - c"));
+    assert!(Rc::ptr_eq(&expr1, &expr2));
+    assert!(Rc::ptr_eq(&ctx.deduplicate(&expr1), &expr1));
+    assert_eq!(ctx.get_num_nodes(&expr1), 6);
+    assert_eq!(ctx.get_num_nodes(&with_dups), 9);
+    // sub-expressions of the counted expressions are already counted
+    match &*expr1 {
+        sym::Expression::BinaryOp(product, _, _) => assert_eq!(ctx.get_num_nodes(product), 4),
+        _ => unreachable!(),
+    }
+    assert!(sym::are_structurally_equal(&expr1, &with_dups));
+    assert_eq!(ctx.get_dependencies(&expr2),
+               sym::get_dependencies(&with_dups));
+    assert_eq!(ctx.fingerprint(&expr1), sym::fingerprint(&with_dups));
+}
+
+#[test]
+fn test_dependency_info() {
+    use sym::mapper_impls::dependency::SubscriptedAggregate;
+    use sym::DependencyInfo;
+
+    let info = DependencyInfo::compute(&parse("a[i, j] * f(a[k], b[i + n]) + g(x) - n"));
+    assert_eq!(info.functions, vec!["f", "g"]);
+    assert_eq!(info.aggregates,
+               vec![SubscriptedAggregate { name: "a".to_string(),
+                                           num_indices: vec![2, 1] },
+                    SubscriptedAggregate { name: "b".to_string(),
+                                           num_indices: vec![1] }]);
+    assert_eq!(info.scalars, vec!["i", "j", "k", "n", "x"]);
+    assert_eq!(info.index_variables, vec!["i", "j", "k", "n"]);
+    assert!(info.occurrences.is_none());
+
+    let x_plus_y = parse("x + y[x]");
+    let expr = ops::mul(&ops::add(&x_plus_y, &x_plus_y), &sym::var("y"));
+    let info = DependencyInfo::compute_with_counts(&expr);
+    assert_eq!(info.scalars, vec!["x", "y"]);
+    assert_eq!(info.index_variables, vec!["x"]);
+    let occurrences = info.occurrences.unwrap();
+    assert_eq!(occurrences["x"], 4);
+    assert_eq!(occurrences["y"], 3);
+}
+
+#[test]
+fn test_array_accesses() {
+    use std::collections::HashMap;
+    use sym::mapper_impls::array_access::{get_index_range, IndexRange, Interval};
+
+    let expr = parse("a[i, j+1] * b[(i*10 + j) % 4302160 // 10] + a[i - 1, 2*j] + c[f(i)]");
+    let accesses = sym::collect_array_accesses(&expr);
+    assert_eq!(accesses.iter()
+                       .map(|access| access.to_string())
+                       .collect::<Vec<_>>(),
+               vec!["a[i, j + 1]",
+                    "b[(i * 10 + j) % 4302160 // 10]",
+                    "a[i - 1, 2 * j]",
+                    "c[f(i)]"]);
+
+    let domain = HashMap::from([("i".to_string(), Interval::new(0, 9)),
+                                ("j".to_string(), Interval::new(0, 4))]);
+    let footprints = sym::get_footprints(&expr, &domain);
+    assert_eq!(footprints.len(), 3);
+    let a_ranges: Vec<_> = footprints[0].ranges
+                                        .iter()
+                                        .map(|range| range.unwrap())
+                                        .collect();
+    assert_eq!(a_ranges[0].interval, Interval::new(-1, 9));
+    assert_eq!(a_ranges[1].interval, Interval::new(0, 8));
+    assert!(a_ranges.iter().all(|range| range.is_exact));
+    let b_range = footprints[1].ranges[0].unwrap();
+    assert_eq!(b_range.interval, Interval::new(0, 9));
+    assert!(!b_range.is_exact);
+    assert_eq!(footprints[2].ranges, vec![None]);
+
+    assert_eq!(get_index_range(&parse("-(3*i) + 2*(j - i)"), &domain).unwrap()
+                                                                     .interval,
+               Interval::new(-45, 8));
+    assert!(get_index_range(&parse("i + n"), &domain).is_none());
+
+    // shared sub-expressions are analysed once
+    let (mut affine, mut modular) = (sym::var("i"), sym::var("i"));
+    for _ in 0..40 {
+        affine = ops::add(&affine, &affine);
+        let remainder = ops::modulo(&modular, &7);
+        modular = ops::add(&remainder, &remainder);
+    }
+    assert_eq!(get_index_range(&affine, &domain).unwrap().interval,
+               Interval::new(0, 9 << 40));
+    assert_eq!(get_index_range(&modular, &domain),
+               Some(IndexRange { interval: Interval::new(0, 12),
+                                 is_exact: false }));
+
+    let shapes = HashMap::from([("a".to_string(), vec![10, 9]), ("b".to_string(), vec![10])]);
+    let oob = sym::find_out_of_bounds_accesses(&expr, &domain, &shapes);
+    assert_eq!(oob.len(), 1);
+    assert_eq!(oob[0].access.to_string(), "a[i - 1, 2 * j]");
+    assert_eq!(oob[0].axes, vec![0]);
+}
+
+#[test]
+fn test_linearize() {
+    use std::collections::HashMap;
+    use sym::mapper_impls::array_access::Interval;
+    use sym::mapper_impls::linearize::ArrayLayout;
+
+    assert_eq!(ArrayLayout::row_major(&[4, 5, 6]).strides, vec![30, 6, 1]);
+    assert_eq!(ArrayLayout::column_major(&[4, 5, 6]).strides,
+               vec![1, 4, 20]);
+
+    let layouts = HashMap::from([("a".to_string(), ArrayLayout::column_major(&[4, 5])),
+                                 ("b".to_string(), ArrayLayout::with_strides(&[3, 8], &[16, 2])),
+                                 ("c".to_string(), ArrayLayout::row_major(&[4, 107554, 10]))]);
+    let expr = parse("a[i, j + 1] * b[a[0, j], 2*k] + d[i, j]");
+    let linearized = sym::linearize(&expr, &layouts);
+    assert_eq!(linearized,
+               parse("a[i + (j + 1)*4] * b[a[j*4]*16 + 2*k*2] + d[i, j]"));
+    // without a domain only provably in-bounds indices are recovered
+    assert_eq!(sym::delinearize(&linearized, &layouts, None), linearized);
+    assert_eq!(sym::delinearize(&parse("a[n] + a[i % 4 + 8] + a[(i % 4) + 4*(j % 6)]"),
+                                &layouts,
+                                None),
+               parse("a[n] + a[i % 4, 2] + a[(i % 4) + 4*(j % 6)]"));
+    let domain = HashMap::from([("i".to_string(), Interval::new(0, 3)),
+                                ("j".to_string(), Interval::new(0, 3))]);
+    // the elements of a are not bounded
+    assert_eq!(sym::delinearize(&linearized, &layouts, Some(&domain)),
+               parse("a[i, j + 1] * b[a[0, j]*16 + 2*k*2] + d[i, j]"));
+
+    let flat = parse("c[iel*10 + idof + 1075540*iface - 10] + c[(iel*10 + idof) % 10]");
+    let mut domain = HashMap::from([("iface".to_string(), Interval::new(0, 3)),
+                                    ("iel".to_string(), Interval::new(1, 5)),
+                                    ("idof".to_string(), Interval::new(0, 9))]);
+    let delinearized = sym::delinearize(&flat, &layouts, Some(&domain));
+    assert_eq!(delinearized,
+               parse("c[iface, iel - 1, idof] + c[0, 0, (iel*10 + idof) % 10]"));
+    assert_eq!(sym::linearize(&delinearized, &layouts),
+               parse("c[iface*1075540 + (iel - 1)*10 + idof] + c[(iel*10 + idof) % 10]"));
+
+    // idof may overflow into the next element along axis 1
+    domain.insert("idof".to_string(), Interval::new(0, 10));
+    assert_eq!(sym::delinearize(&flat, &layouts, Some(&domain)),
+               parse("c[iel*10 + idof + 1075540*iface - 10] + c[0, 0, (iel*10 + idof) % 10]"));
+
+    // negative offsets borrow from the axis of j rather than going below 0
+    let layouts = HashMap::from([("a".to_string(), ArrayLayout::row_major(&[4, 5, 6])),
+                                 ("b".to_string(), ArrayLayout::row_major(&[5, 6]))]);
+    let domain = HashMap::from([("j".to_string(), Interval::new(2, 4))]);
+    assert_eq!(sym::delinearize(&parse("a[6*j - 1] + b[6*j - 1] + a[6*j - 7]"),
+                                &layouts,
+                                Some(&domain)),
+               parse("a[0, j - 1, 5] + b[j - 1, 5] + a[0, j - 2, 5]"));
+    assert_eq!(sym::linearize(&parse("a[0, j - 1, 5]"), &layouts),
+               parse("a[(j - 1)*6 + 5]"));
+
+    // offsets overflowing i64 are kept symbolic
+    let layouts =
+        HashMap::from([("a".to_string(), ArrayLayout::with_strides(&[2, 2], &[i64::MAX, 2]))]);
+    assert_eq!(sym::linearize(&parse("a[1, 1] + a[2, 0]"), &layouts).to_string(),
+               format!("a[(1 * 2 + {0})] + a[(2 * {0})]", i64::MAX));
+}
+
+#[test]
+fn test_expr_paths() {
+    use std::rc::Rc;
+    use sym::{ExprPath, ExprZipper, Expression};
+
+    let expr = parse("f(x, g(y, 2), z) + a[i, j]");
+    let path = ExprPath::from(vec![0, 2, 2]);
+    assert_eq!(path.to_string(), "/0/2/2");
+    assert_eq!(path.depth(), 3);
+    assert_eq!(ExprPath::root().to_string(), "/");
+    assert_eq!(path.parent(), Some(ExprPath::from(vec![0, 2])));
+    assert!(path.parent().unwrap().is_prefix_of(&path));
+    assert_eq!(expr.get(&path).unwrap().to_string(), "2");
+    assert!(Rc::ptr_eq(&expr.get(&ExprPath::root()).unwrap(), &expr));
+    assert!(expr.get(&ExprPath::from(vec![0, 4])).is_none());
+
+    // only the spine is rebuilt
+    let new_expr = expr.replace_at(&path, parse("3"));
+    assert_eq!(new_expr, parse("f(x, g(y, 3), z) + a[i, j]"));
+    let right = ExprPath::from(vec![1]);
+    assert!(Rc::ptr_eq(&new_expr.get(&right).unwrap(), &expr.get(&right).unwrap()));
+    let arg = ExprPath::from(vec![0, 1]);
+    assert!(Rc::ptr_eq(&new_expr.get(&arg).unwrap(), &expr.get(&arg).unwrap()));
+
+    let paths = sym::find_paths(&expr, |e| matches!(e, Expression::Variable(_)));
+    let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+    assert_eq!(paths,
+               vec!["/0/0", "/0/1", "/0/2/0", "/0/2/1", "/0/3", "/1/0", "/1/1", "/1/2"]);
+
+    // find_nodes visits each node of a DAG once, find_paths each of its 2^64 paths
+    let dag = (0..64).fold(parse("x + 1"), |acc, _| {
+                         Rc::new(Expression::BinaryOp(acc.clone(), sym::BinaryOpType::Sum, acc))
+                     });
+    let nodes = sym::find_nodes(&dag, |e| matches!(e, Expression::Variable(_)));
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].0, ExprPath::from(vec![0; 65]));
+    assert!(Rc::ptr_eq(&dag.get(&nodes[0].0).unwrap(), &nodes[0].1));
+    let nodes = sym::find_nodes(&expr, |e| matches!(e, Expression::Variable(_)));
+    let paths: Vec<String> = nodes.iter().map(|(path, _)| path.to_string()).collect();
+    assert_eq!(paths,
+               vec!["/0/0", "/0/1", "/0/2/0", "/0/2/1", "/0/3", "/1/0", "/1/1", "/1/2"]);
+
+    let mut zipper = ExprZipper::new(expr.clone());
+    assert!(!zipper.up() && !zipper.right());
+    assert!(zipper.down(1) && zipper.down(2));
+    assert!(!zipper.right() && !zipper.down(0));
+    assert!(zipper.left() && zipper.left() && !zipper.left());
+    assert_eq!(zipper.focus().to_string(), "a");
+    assert_eq!(zipper.path(), ExprPath::from(vec![1, 0]));
+    // navigation alone does not rebuild anything
+    assert!(Rc::ptr_eq(&ExprZipper::new(expr.clone()).into_root(), &expr));
+    zipper.replace(parse("b"));
+    assert!(zipper.up() && zipper.left() && zipper.down(0));
+    zipper.replace(parse("h"));
+    assert_eq!(zipper.into_root(), parse("h(x, g(y, 2), z) + b[i, j]"));
+}
+
+#[test]
+fn test_query() {
+    use sym::mapper_impls::stats::NodeKind;
+    use sym::{BinaryOpType, Selector};
+
+    let expr = parse("hankel_1(-2, x) + hankel_1(2, a[i - 1]) * (a[i] if i > 0 else -a[0])");
+    let paths = |selector: &Selector| -> Vec<String> {
+        sym::select(&expr, selector).iter()
+                                    .map(|m| m.path.to_string())
+                                    .collect()
+    };
+
+    let negative = Selector::literal(|x| x.as_f64() < 0.0);
+    assert_eq!(paths(&Selector::call("hankel_1").with_arg(0, negative.clone())),
+               vec!["/0"]);
+    assert!(paths(&Selector::call("hankel_1").with_arg(2, Selector::any())).is_empty());
+    assert_eq!(paths(&Selector::subscript("a")),
+               vec!["/1/0/2", "/1/1/1", "/1/1/2/0"]);
+    assert_eq!(paths(&Selector::subscript("a").child_of(Selector::kind(NodeKind::If))),
+               vec!["/1/1/1"]);
+    assert_eq!(paths(&Selector::variable("i").descendant_of(Selector::call("hankel_1"))),
+               vec!["/1/0/2/1/0"]);
+    assert_eq!(paths(&Selector::kind(NodeKind::Call).with_descendant(Selector::variable("i"))),
+               vec!["/1/0"]);
+    assert_eq!(paths(&Selector::binary_op(BinaryOpType::Product)
+                     .with_any_child(Selector::kind(NodeKind::If))),
+               vec!["/1"]);
+    assert_eq!(paths(&Selector::variable("x").or(Selector::variable("i").and(!Selector::any()))),
+               vec!["/0/2"]);
+
+    let matches = sym::select(&expr,
+                              &Selector::kind(NodeKind::Call).and(!Selector::call("j_0")));
+    assert_eq!(matches.iter().map(|m| m.expr.clone()).collect::<Vec<_>>(),
+               vec![parse("hankel_1(-2, x)"), parse("hankel_1(2, a[i - 1])")]);
+
+    let rewritten = sym::replace_matches(&expr, &Selector::subscript("a"), |x| match &**x {
+        sym::Expression::Subscript(_, indices) => {
+            sym::Expression::Subscript(sym::var("b"), indices.clone()).into()
+        }
+        _ => unreachable!(),
+    });
+    assert_eq!(rewritten,
+               parse("hankel_1(-2, x) + hankel_1(2, b[i - 1]) * (b[i] if i > 0 else -b[0])"));
+
+    // a DAG with 2^64 paths: every node is visited once per distinct context
+    use std::rc::Rc;
+    let leaf = parse("f(a[i])");
+    let dag = (0..64).fold(leaf.clone(), |acc, _| {
+                         Rc::new(sym::Expression::BinaryOp(acc.clone(), BinaryOpType::Sum, acc))
+                     });
+    let sums = Selector::binary_op(BinaryOpType::Sum);
+    assert_eq!(sym::select(&dag,
+                           &sums.clone().with_descendant(Selector::subscript("a"))).len(),
+               64);
+    let matches = sym::select(&dag, &Selector::subscript("a").descendant_of(sums.clone()));
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].path.depth(), 65);
+    assert_eq!(sym::select(&dag, &sums.clone().child_of(sums.clone())).len(),
+               63);
+    let mut ncalls = 0;
+    let rewritten = sym::replace_matches(&dag, &Selector::call("f"), |_| {
+        ncalls += 1;
+        parse("g(a[i])")
+    });
+    assert_eq!(ncalls, 1);
+    assert_eq!(sym::get_num_nodes(&rewritten), sym::get_num_nodes(&dag));
+    let mut rewritten_leaf = rewritten;
+    while let sym::Expression::BinaryOp(left, _, right) = &*rewritten_leaf.clone() {
+        assert!(Rc::ptr_eq(left, right));
+        rewritten_leaf = left.clone();
+    }
+    assert_eq!(rewritten_leaf, parse("g(a[i])"));
+}
+
+#[test]
+fn test_diff() {
+    use sym::mapper_impls::diff::DiffOptions;
+    use sym::PrettyOptions;
+
+    let old = parse("f(alpha_1 + alpha_2 * beta, gamma[i, j + 1]) - delta ** 2");
+    assert!(sym::diff(&old, &old.clone()).is_empty());
+    assert_eq!(sym::diff(&old, &old).to_string(), "");
+
+    let new = parse("f(alpha_1 + alpha_3 * beta, gamma[i, j + 1]) - delta ** 3");
+    let d = sym::diff(&old, &new);
+    let differences: Vec<String> = d.differences.iter().map(|x| x.to_string()).collect();
+    assert_eq!(differences,
+               vec!["/0/1/1/0: alpha_2 -> alpha_3", "/1/1: 2 -> 3"]);
+
+    // differing operators are reported as a whole
+    let d = sym::diff(&parse("a[i] * (x + y)"), &parse("a[i] * (x - y)"));
+    assert_eq!(d.differences.len(), 1);
+    assert_eq!(d.differences[0].path.to_string(), "/1");
+
+    let options = DiffOptions { pretty: PrettyOptions { width: 22,
+                                                        indent: 2,
+                                                        let_bindings: false },
+                                context: 1,
+                                color: false };
+    assert_eq!(sym::diff(&old, &new).to_unified(&options),
+               "--- old\n+++ new\n@@ -2,5 +2,5 @@\n   alpha_1\n-    + alpha_2 * beta,\n\
+                +    + alpha_3 * beta,\n   gamma[i, j + 1]\n )\n-  - delta ** 2\n\
+                +  - delta ** 3\n");
+    // without context the changes are not close enough to share a hunk
+    assert_eq!(sym::diff(&old, &new).to_unified(&DiffOptions { context: 0,
+                                                               ..options }),
+               "--- old\n+++ new\n@@ -3 +3 @@\n-    + alpha_2 * beta,\n+    + alpha_3 * beta,\n\
+                @@ -6 +6 @@\n-  - delta ** 2\n+  - delta ** 3\n");
+    let colored = sym::diff(&old, &new).to_unified(&DiffOptions { color: true,
+                                                                  ..options });
+    assert!(colored.contains("\x1b[31m-  - delta ** 2\x1b[0m\n\x1b[32m+  - delta ** 3\x1b[0m\n"));
+
+    assert_eq!(sym::tree_edit_distance(&old, &old), 0);
+    assert_eq!(sym::tree_edit_distance(&old, &new), 2);
+    assert_eq!(sym::tree_edit_distance(&parse("f(x, y)"), &parse("f(y)")),
+               1);
+    assert_eq!(sym::tree_edit_distance(&parse("a + b"), &parse("c")), 3);
+    assert_eq!(sym::tree_edit_distance(&parse("a*(b + c)"), &parse("(a*b) + c")),
+               3);
+}
+
+#[test]
+fn test_alpha_equivalence() {
+    use sym::AlphaEquivalenceOptions;
+
+    let renaming = sym::are_alpha_equivalent(&parse("a[iface_ensm15, iel] + sin(iel) * 2"),
+                                             &parse("_0[_1, _2] + sin(_2) * 2")).unwrap();
+    assert_eq!(renaming.len(), 3);
+    assert_eq!(renaming["a"], "_0");
+    assert_eq!(renaming["iface_ensm15"], "_1");
+    assert_eq!(renaming["iel"], "_2");
+
+    // renamings are bijective
+    assert!(sym::are_alpha_equivalent(&parse("x + y"), &parse("z + z")).is_none());
+    assert!(sym::are_alpha_equivalent(&parse("x + x"), &parse("y + z")).is_none());
+    // functions and literals are not renamed
+    assert!(sym::are_alpha_equivalent(&parse("sin(x)"), &parse("cos(x)")).is_none());
+    assert!(sym::are_alpha_equivalent(&parse("x + 1"), &parse("x + 2")).is_none());
+    assert!(sym::are_alpha_equivalent(&parse("x + 1"), &parse("x - 1")).is_none());
+
+    let (expr1, expr2) = (parse("x*y + (x - y)"), parse("(b*a) + (a - b)"));
+    assert!(sym::are_alpha_equivalent(&expr1, &expr2).is_none());
+    let options = AlphaEquivalenceOptions { commutative: true };
+    // requires revisiting the order chosen for x*y once x - y is compared
+    let renaming = sym::are_alpha_equivalent_with_options(&expr1, &expr2, &options).unwrap();
+    assert_eq!(renaming["x"], "a");
+    assert_eq!(renaming["y"], "b");
+    // requires revisiting the order chosen for x + y once x + 1 is compared
+    let renaming = sym::are_alpha_equivalent_with_options(&parse("(x + y) - (x + 1)"),
+                                                          &parse("(a + b) - (b + 1)"),
+                                                          &options).unwrap();
+    assert_eq!(renaming["x"], "b");
+    assert_eq!(renaming["y"], "a");
+    // subtraction is not commutative
+    assert!(sym::are_alpha_equivalent_with_options(&parse("x - y"), &parse("b - a"), &options)
+            .is_some_and(|renaming| renaming["x"] == "b"));
+    assert!(sym::are_alpha_equivalent_with_options(&parse("(x - 1) * 2"),
+                                                   &parse("2 * (1 - x)"),
+                                                   &options).is_none());
+
+    // shared sub-expressions are matched once
+    let (mut chain1, mut chain2) = (sym::var("x"), sym::var("y"));
+    for _ in 0..64 {
+        chain1 = ops::add(&chain1, &chain1);
+        chain2 = ops::add(&chain2, &chain2);
+    }
+    assert!(sym::are_alpha_equivalent(&chain1, &chain2).is_some_and(|r| r["x"] == "y"));
+    assert!(sym::are_alpha_equivalent_with_options(&chain1, &chain2, &options).is_some());
+
+    // a failed match is not retried with every order of the operands
+    let terms = |constant: i32, names: (&str, &str)| {
+        (0..20).fold(parse(constant.to_string()), |acc, i| {
+                   let product = parse(format!("{}{} * {}{}", names.0, i, names.1, i));
+                   ops::add(&acc, &product)
+               })
+    };
+    let expr1 = terms(1, ("x", "y"));
+    assert!(sym::are_alpha_equivalent_with_options(&expr1, &terms(2, ("x", "y")), &options)
+            .is_none());
+    let renaming =
+        sym::are_alpha_equivalent_with_options(&expr1, &terms(1, ("b", "a")), &options).unwrap();
+    assert_eq!(renaming.len(), 40);
+    // nor are the choices made for the products unrelated to a conflict
+    let expr2 = (1..20).fold(parse("1 + a0 * a0"), |acc, i| {
+                           ops::add(&acc, &parse(format!("b{} * a{}", i, i)))
+                       });
+    assert!(sym::are_alpha_equivalent_with_options(&expr1, &expr2, &options).is_none());
+}
+
+#[test]
+fn test_probably_equivalent() {
+    use std::collections::HashMap;
+    use sym::mapper_impls::array_access::Interval;
+    use sym::mapper_impls::equivalence::{Domain, EquivalenceOptions};
+    use sym::LiteralT;
+
+    let options = EquivalenceOptions { domains:
+                                           HashMap::from([("i".to_string(),
+                                                           Domain::Integer(Interval::new(0, 9))),
+                                                          ("j".to_string(),
+                                                           Domain::Integer(Interval::new(-4, 4)))]),
+                                       ..EquivalenceOptions::default() };
+
+    // exact integer arithmetic
+    assert!(sym::probably_equivalent(&parse("(i*10 + j) // 10"),
+                                     &parse("i + (j // 10)"),
+                                     &options).is_ok());
+    // floating point results agree up to the tolerance
+    assert!(sym::probably_equivalent(&parse("sin(x)**2 + cos(x)**2"), &parse("1"), &options)
+            .is_ok());
+    assert!(sym::probably_equivalent(&parse("(x*y + x) / x"), &parse("y + 1"), &options).is_ok());
+
+    let error = sym::probably_equivalent(&parse("i - j"), &parse("j - i"), &options).unwrap_err();
+    let counterexample = error.counterexample().unwrap();
+    assert_eq!(counterexample.assignment.keys().collect::<Vec<_>>(),
+               vec!["i", "j"]);
+    let (i, j) = (counterexample.assignment["i"].as_i64(), counterexample.assignment["j"].as_i64());
+    assert!((0..=9).contains(&i) && (-4..=4).contains(&j) && i != j);
+    assert!(counterexample.value1 == Ok(LiteralT::I64(i - j)));
+    assert_eq!(error.to_string(),
+               format!("{} != {} at {{i = {}, j = {}}}", i - j, j - i, i, j));
+
+    // a point at which only one side can be evaluated is a counterexample
+    let domain = Domain::Integer(Interval::new(-5, 5));
+    let options = EquivalenceOptions { domains: HashMap::from([("i".to_string(), domain)]),
+                                       ..options };
+    let error = sym::probably_equivalent(&parse("i // i"), &parse("1"), &options).unwrap_err();
+    let counterexample = error.counterexample().unwrap();
+    assert_eq!(counterexample.assignment["i"].as_i64(), 0);
+    assert!(counterexample.value1.is_err() && counterexample.value2 == Ok(LiteralT::I64(1)));
+    assert_eq!(counterexample.to_string(),
+               "<Integer division by zero in '0 // 0'.> != 1 at {i = 0}");
+    // points at which neither side can be evaluated are skipped
+    assert!(sym::probably_equivalent(&parse("i // i"), &parse("1 + 0 // i"), &options).is_ok());
+
+    // a relative error of 1e-6 is only tolerated if rtol allows for it
+    let (expr1, expr2) = (parse("x"), parse("x * 1.000001"));
+    assert!(sym::probably_equivalent(&expr1, &expr2, &options).is_err());
+    let loose = EquivalenceOptions { rtol: 1e-5,
+                                     ..options.clone() };
+    assert!(sym::probably_equivalent(&expr1, &expr2, &loose).is_ok());
+
+    // subscripts cannot be evaluated, and index variables are integers
+    let error =
+        sym::probably_equivalent(&parse("a[k] + 1"), &parse("1 + a[k]"), &options).unwrap_err();
+    assert!(error.counterexample().is_none());
+    assert_eq!(error.to_string(),
+               "Could not evaluate either expression at any of the sampled points: Cannot \
+                evaluate the subscript of 'a': subscripts are not supported. Cannot evaluate the \
+                subscript of 'a': subscripts are not supported.");
+    let error =
+        sym::probably_equivalent(&parse("a[k] + k"), &parse("k // 1"), &options).unwrap_err();
+    let counterexample = error.counterexample().unwrap();
+    assert!(matches!(counterexample.assignment["k"], LiteralT::I64(-10..=10)));
+    assert!(counterexample.value1.is_err() && counterexample.value2.is_ok());
+}
+
+// rustfmt would split the `**` and glue the signs in the macro input
+#[test]
+#[rustfmt::skip]
+fn test_expr_macro() {
+    use sym::expr;
+
+    assert_eq!(expr!(a[i, j] * sin(x) + 2 if i < n else 0),
+               parse("a[i, j] * sin(x) + 2 if i < n else 0"));
+    assert_eq!(expr!(x - -1.5 * (y ** 2)), parse("x - -1.5 * (y ** 2)"));
+    assert_eq!(expr!(not a and b or c != d), parse("not a and b or c != d"));
+    assert_eq!(expr!(a | b ^ c & d << 2 >> e), parse("a | b ^ c & d << 2 >> e"));
+    assert_eq!(expr!(-(x % 3) / (~y)), parse("-(x % 3) / (~y)"));
+    assert_eq!(expr!(f()), parse("f()"));
+    assert_eq!(expr!(f(x, y,)), parse("f(x, y,)"));
+    assert_eq!(expr!("i // 2 + x**2"), parse("i // 2 + x**2"));
+
+    // a sign is part of a literal only if no whitespace separates them
+    assert_ne!(parse("-2**2"), parse("- 2**2"));
+    assert_eq!(expr!(-2**2), parse("-2**2"));
+    assert_eq!(expr!(- 2**2), parse("- 2**2"));
+    assert_eq!(expr!(x**-1.5 - 1), parse("x**-1.5 - 1"));
+    assert_eq!(expr!(3 - -1 * (- 2)), parse("3 - -1 * (- 2)"));
+
+    // interpolation of Rust values
+    let x = parse("u + v");
+    let n = 4;
+    assert_eq!(expr!(#x * #n), parse("(u + v) * 4"));
+    assert_eq!(expr!("#x // #n"), parse("(u + v) // 4"));
+}